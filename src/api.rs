@@ -0,0 +1,471 @@
+//! Optional JSON API on localhost, giving tools access to the wiki through the session of the
+//! desktop app. Requests need an `Authorization: Bearer <token>` header, with the token shown
+//! in the app and kept in `<local data dir>/pubky-wiki/api_token`.
+//!
+//! - `GET /pages`: the user's pages, as `[{"page_id", "title"}]`
+//! - `GET /pages/<id>[?user_pk=<pk>]`: a page of the user, or of another user
+//! - `POST /pages` with `{"content"}`: create a page
+//! - `PUT /pages/<id>` with `{"content"}`: update a page
+//! - `DELETE /pages/<id>`: move a page to the trash
+//! - `GET /pages/<id>/forks`: versions of a page by the user and their follows, as `pk/id` links
+//! - `POST /pages/<id>/fork` with `{"user_pk"}`: fork the page of another user
+
+use std::{
+    convert::Infallible,
+    fs,
+    io::Write,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use eframe::egui::Ui;
+use egui::CollapsingHeader;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    header,
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use percent_encoding::percent_decode_str;
+use pubky::{PubkySession, PublicKey, PublicStorage};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tokio::{net::TcpListener, task::JoinHandle};
+use uuid::Uuid;
+
+use crate::{
+    create_wiki_post, discover_fork_urls, links::is_valid_page_id, revisions::record_fork, trash::trash_wiki_post, update_wiki_post,
+    utils::{extract_title, list_pages},
+    AuthState, PubkyApp,
+};
+
+const API_ADDRESS: &str = "127.0.0.1:8081";
+
+/// The API server, while it runs
+pub(crate) struct ApiServer {
+    address: SocketAddr,
+    token: String,
+    task: JoinHandle<()>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Endpoint {
+    ListPages,
+    GetPage(String),
+    CreatePage,
+    UpdatePage(String),
+    DeletePage(String),
+    Forks(String),
+    Fork(String),
+}
+
+fn endpoint(method: &Method, path: &str) -> Option<Endpoint> {
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let endpoint = match (method, segments.as_slice()) {
+        (&Method::GET, ["pages"]) => Endpoint::ListPages,
+        (&Method::POST, ["pages"]) => Endpoint::CreatePage,
+        (&Method::GET, ["pages", page_id]) => Endpoint::GetPage(page_id.to_string()),
+        (&Method::PUT, ["pages", page_id]) => Endpoint::UpdatePage(page_id.to_string()),
+        (&Method::DELETE, ["pages", page_id]) => Endpoint::DeletePage(page_id.to_string()),
+        (&Method::GET, ["pages", page_id, "forks"]) => Endpoint::Forks(page_id.to_string()),
+        (&Method::POST, ["pages", page_id, "fork"]) => Endpoint::Fork(page_id.to_string()),
+        _ => return None,
+    };
+
+    match &endpoint {
+        Endpoint::GetPage(page_id)
+        | Endpoint::UpdatePage(page_id)
+        | Endpoint::DeletePage(page_id)
+        | Endpoint::Forks(page_id)
+        | Endpoint::Fork(page_id)
+            if !is_valid_page_id(page_id) =>
+        {
+            None
+        }
+        _ => Some(endpoint),
+    }
+}
+
+#[derive(Serialize)]
+struct PageSummary {
+    page_id: String,
+    title: String,
+}
+
+#[derive(Serialize)]
+struct PageDetails {
+    user_pk: String,
+    page_id: String,
+    title: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct PageContent {
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ForkRequest {
+    user_pk: String,
+}
+
+fn json_response(status: StatusCode, value: &impl Serialize) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(serde_json::to_vec(value).unwrap_or_default().into()))
+        .unwrap_or_default()
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response<Full<Bytes>> {
+    json_response(status, &serde_json::json!({ "error": error }))
+}
+
+fn is_authorized(request: &Request<Incoming>, token: &str) -> bool {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        // Compared in constant time, so that the token can't be guessed from response times
+        .is_some_and(|given| bool::from(given.trim().as_bytes().ct_eq(token.as_bytes())))
+}
+
+async fn read_json<T: DeserializeOwned>(request: Request<Incoming>) -> Result<T> {
+    let body = request.into_body().collect().await?.to_bytes();
+    serde_json::from_slice(&body).map_err(|e| anyhow!("Invalid request body: {e}"))
+}
+
+/// Token of the API, created on first use and kept so that tools don't need to be reconfigured
+fn load_or_create_token() -> Result<String> {
+    let data_dir = dirs::data_local_dir().ok_or(anyhow!("No local data directory"))?;
+    let path = data_dir.join("pubky-wiki").join("api_token");
+
+    if let Ok(token) = fs::read_to_string(&path) {
+        if !token.trim().is_empty() {
+            restrict_to_owner(&path)?;
+            return Ok(token.trim().to_string());
+        }
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(token.as_bytes())?;
+    restrict_to_owner(&path)?;
+    Ok(token)
+}
+
+/// Make the token file readable by the user only, including files written by older versions
+fn restrict_to_owner(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+struct Api {
+    state: Arc<Mutex<AuthState>>,
+    token: String,
+}
+
+impl Api {
+    /// Current session of the app, which may be replaced while the API runs
+    fn session(&self) -> Option<(PubkySession, PublicStorage)> {
+        match &*self.state.lock().unwrap() {
+            AuthState::Authenticated {
+                session,
+                pub_storage,
+                ..
+            } => Some((session.clone(), pub_storage.clone())),
+            _ => None,
+        }
+    }
+
+    /// Keep the page list of the app in sync, with `None` for a deleted page
+    fn update_file_cache(&self, file_url: String, title: Option<String>) {
+        if let AuthState::Authenticated {
+            ref mut file_cache, ..
+        } = *self.state.lock().unwrap()
+        {
+            match title {
+                Some(title) => file_cache.insert(file_url, title),
+                None => file_cache.remove(&file_url),
+            };
+        }
+    }
+
+    async fn handle(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        if !is_authorized(&request, &self.token) {
+            return error_response(StatusCode::UNAUTHORIZED, "Missing or invalid token");
+        }
+        let Some(endpoint) = endpoint(request.method(), request.uri().path()) else {
+            return error_response(StatusCode::NOT_FOUND, "Unknown endpoint");
+        };
+        let Some((session, pub_storage)) = self.session() else {
+            return error_response(StatusCode::SERVICE_UNAVAILABLE, "Not signed in");
+        };
+
+        match self.handle_endpoint(endpoint, request, &session, &pub_storage).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::error!("API request failed: {e}");
+                error_response(StatusCode::BAD_GATEWAY, &e.to_string())
+            }
+        }
+    }
+
+    async fn handle_endpoint(
+        &self,
+        endpoint: Endpoint,
+        request: Request<Incoming>,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
+    ) -> Result<Response<Full<Bytes>>> {
+        let own_pk = session.info().public_key().z32();
+        let file_url = |user_pk: &str, page_id: &str| format!("pubky://{user_pk}/pub/wiki.app/{page_id}");
+
+        let response = match endpoint {
+            Endpoint::ListPages => {
+                let mut pages = vec![];
+                let storage = session.storage();
+                for url in list_pages(|| storage.list("/pub/wiki.app/")).await? {
+                    // As in the app, a page that can't be read is skipped rather than failing the list
+                    let content = async { anyhow::Ok(pub_storage.get(&url).await?.text().await?) }.await;
+                    let content = match content {
+                        Ok(content) => content,
+                        Err(e) => {
+                            log::error!("Error fetching path {url}: {e}");
+                            continue;
+                        }
+                    };
+                    pages.push(PageSummary {
+                        page_id: url.split('/').next_back().unwrap_or(&url).to_string(),
+                        title: extract_title(&content).to_string(),
+                    });
+                }
+                json_response(StatusCode::OK, &pages)
+            }
+            Endpoint::GetPage(page_id) => {
+                let query = request.uri().query().unwrap_or_default();
+                let user_pk = url::form_urlencoded::parse(query.as_bytes())
+                    .find(|(key, _)| key == "user_pk")
+                    .map(|(_, user_pk)| user_pk.parse::<PublicKey>().map(|pk| pk.z32()));
+                let user_pk = match user_pk {
+                    Some(Ok(user_pk)) => user_pk,
+                    Some(Err(_)) => return Ok(error_response(StatusCode::BAD_REQUEST, "Invalid user_pk")),
+                    None => own_pk,
+                };
+
+                match pub_storage.get(file_url(&user_pk, &page_id)).await {
+                    Ok(response) => {
+                        let content = response.text().await?;
+                        json_response(
+                            StatusCode::OK,
+                            &PageDetails {
+                                user_pk,
+                                page_id,
+                                title: extract_title(&content).to_string(),
+                                content,
+                            },
+                        )
+                    }
+                    Err(_) => error_response(StatusCode::NOT_FOUND, "Page not found"),
+                }
+            }
+            Endpoint::CreatePage => {
+                let PageContent { content } = match read_json(request).await {
+                    Ok(body) => body,
+                    Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, &e.to_string())),
+                };
+                let path = create_wiki_post(session, &content, None).await?;
+                let page_id = path.split('/').next_back().unwrap_or(&path).to_string();
+
+                self.update_file_cache(file_url(&own_pk, &page_id), Some(extract_title(&content).into()));
+                json_response(StatusCode::CREATED, &serde_json::json!({ "page_id": page_id }))
+            }
+            Endpoint::UpdatePage(page_id) => {
+                let PageContent { content } = match read_json(request).await {
+                    Ok(body) => body,
+                    Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, &e.to_string())),
+                };
+                if !session.storage().exists(format!("/pub/wiki.app/{page_id}")).await? {
+                    return Ok(error_response(StatusCode::NOT_FOUND, "Page not found"));
+                }
+                update_wiki_post(session, &page_id, &content).await?;
+
+                self.update_file_cache(file_url(&own_pk, &page_id), Some(extract_title(&content).into()));
+                json_response(StatusCode::OK, &serde_json::json!({ "page_id": page_id }))
+            }
+            Endpoint::DeletePage(page_id) => {
+                if !session.storage().exists(format!("/pub/wiki.app/{page_id}")).await? {
+                    return Ok(error_response(StatusCode::NOT_FOUND, "Page not found"));
+                }
+                trash_wiki_post(session, &page_id).await?;
+
+                self.update_file_cache(file_url(&own_pk, &page_id), None);
+                json_response(StatusCode::OK, &serde_json::json!({ "page_id": page_id }))
+            }
+            Endpoint::Forks(page_id) => {
+                json_response(StatusCode::OK, &discover_fork_urls(session, pub_storage, &page_id).await)
+            }
+            Endpoint::Fork(page_id) => {
+                let ForkRequest { user_pk } = match read_json(request).await {
+                    Ok(body) => body,
+                    Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, &e.to_string())),
+                };
+                let Ok(user_pk) = user_pk.parse::<PublicKey>() else {
+                    return Ok(error_response(StatusCode::BAD_REQUEST, "Invalid user_pk"));
+                };
+                if session.storage().exists(format!("/pub/wiki.app/{page_id}")).await? {
+                    return Ok(error_response(StatusCode::CONFLICT, "You already have this page"));
+                }
+                let content = match pub_storage.get(file_url(&user_pk.z32(), &page_id)).await {
+                    Ok(response) => response.text().await?,
+                    Err(_) => return Ok(error_response(StatusCode::NOT_FOUND, "Page not found")),
+                };
+                // Forks keep the page ID of the original, as in the app
+                create_wiki_post(session, &content, Some(&page_id)).await?;
+                if let Err(e) = record_fork(session, pub_storage, &page_id, &user_pk).await {
+                    log::error!("Failed to record the fork point of {page_id}: {e}");
+                }
+
+                self.update_file_cache(file_url(&own_pk, &page_id), Some(extract_title(&content).into()));
+                json_response(StatusCode::CREATED, &serde_json::json!({ "page_id": page_id }))
+            }
+        };
+        Ok(response)
+    }
+}
+
+impl PubkyApp {
+    fn start_api(&mut self) -> Result<()> {
+        let address: SocketAddr = API_ADDRESS.parse()?;
+        let token = load_or_create_token()?;
+        let listener = self.rt.block_on(TcpListener::bind(address))?;
+
+        let api = Arc::new(Api {
+            state: self.state.clone(),
+            token: token.clone(),
+        });
+        let task = self.rt.spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::error!("API failed to accept a connection: {e}");
+                        continue;
+                    }
+                };
+                let api = api.clone();
+
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| {
+                        let api = api.clone();
+                        async move { Ok::<_, Infallible>(api.handle(request).await) }
+                    });
+                    if let Err(e) = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        log::error!("Error serving API connection: {e}");
+                    }
+                });
+            }
+        });
+
+        log::info!("Started the API at http://{address}/");
+
+        self.api = Some(ApiServer {
+            address,
+            token,
+            task,
+        });
+        Ok(())
+    }
+
+    fn stop_api(&mut self) {
+        if let Some(api) = self.api.take() {
+            api.task.abort();
+            log::info!("Stopped the API");
+        }
+    }
+}
+
+/// Start or stop the API, and show how to use it
+pub(crate) fn show_settings(app: &mut PubkyApp, ui: &mut Ui) {
+    CollapsingHeader::new(egui::RichText::new("🔌 Local API").size(15.0)).show(ui, |ui| {
+        ui.add_space(5.0);
+
+        match &app.api {
+            Some(api) => {
+                ui.label(format!("Running at http://{}/", api.address));
+                ui.horizontal(|ui| {
+                    ui.label("Token:");
+                    ui.label(egui::RichText::new(&api.token).monospace());
+                    if ui.button("📋 Copy").clicked() {
+                        ui.ctx().copy_text(api.token.clone());
+                    }
+                });
+                ui.label(
+                    egui::RichText::new("Send it as \"Authorization: Bearer <token>\"")
+                        .color(egui::Color32::GRAY),
+                );
+                if ui.button("Stop").clicked() {
+                    app.stop_api();
+                }
+            }
+            None => {
+                ui.label("Let local tools read and edit your wiki through this app.");
+                if ui.button("Start").clicked() {
+                    app.api_error = app
+                        .start_api()
+                        .inspect_err(|e| log::error!("Failed to start the API: {e}"))
+                        .err()
+                        .map(|e| e.to_string());
+                }
+            }
+        }
+
+        if let Some(error) = &app.api_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_endpoints() {
+        assert_eq!(endpoint(&Method::GET, "/pages"), Some(Endpoint::ListPages));
+        assert_eq!(endpoint(&Method::POST, "/pages/"), Some(Endpoint::CreatePage));
+        assert_eq!(endpoint(&Method::GET, "/pages/id-1"), Some(Endpoint::GetPage("id-1".into())));
+        assert_eq!(endpoint(&Method::PUT, "/pages/id-1"), Some(Endpoint::UpdatePage("id-1".into())));
+        assert_eq!(endpoint(&Method::DELETE, "/pages/id-1"), Some(Endpoint::DeletePage("id-1".into())));
+        assert_eq!(endpoint(&Method::GET, "/pages/id-1/forks"), Some(Endpoint::Forks("id-1".into())));
+        assert_eq!(endpoint(&Method::POST, "/pages/id-1/fork"), Some(Endpoint::Fork("id-1".into())));
+        assert_eq!(endpoint(&Method::DELETE, "/pages"), None);
+        assert_eq!(endpoint(&Method::GET, "/other"), None);
+        assert_eq!(endpoint(&Method::GET, "/pages/.revisions"), None);
+        assert_eq!(endpoint(&Method::DELETE, "/pages/id%201"), None);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{initialize_auth, utils::generate_qr_image, AuthState, PubkyApp};
+
+use eframe::egui::{Context, Ui};
+use egui::CollapsingHeader;
+use pubky::{Capabilities, Capability, PubkySession};
+
+/// Features of the app, each needing its own set of capabilities
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Feature {
+    Wiki,
+    Follows,
+    Posts,
+    Tags,
+}
+
+impl Feature {
+    pub(crate) const ALL: [Feature; 4] = [
+        Feature::Wiki,
+        Feature::Follows,
+        Feature::Posts,
+        Feature::Tags,
+    ];
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Feature::Wiki => "Manage wiki pages",
+            Feature::Follows => "Follow users",
+            Feature::Posts => "Announce pages",
+            Feature::Tags => "Tag pages",
+        }
+    }
+
+    /// Capabilities needed on the user's homeserver to use this feature
+    pub(crate) fn capabilities(&self) -> Vec<Capability> {
+        let scope = match self {
+            Feature::Wiki => "/pub/wiki.app/",
+            Feature::Follows => "/pub/pubky.app/follows/",
+            Feature::Posts => "/pub/pubky.app/posts/",
+            Feature::Tags => "/pub/pubky.app/tags/",
+        };
+        vec![Capability::write(scope)]
+    }
+}
+
+/// State of an incremental auth flow, started when a feature needs more capabilities
+#[derive(Clone, Default)]
+pub(crate) enum CapsRequestState {
+    #[default]
+    Idle,
+    Initializing(Feature),
+    ShowingQR {
+        auth_url: String,
+        feature: Feature,
+    },
+    Granted(Feature),
+    Error(String),
+}
+
+/// Capabilities requested at login: only what the wiki itself needs
+pub(crate) fn initial_capabilities() -> Capabilities {
+    Capabilities::builder()
+        .extend(Feature::Wiki.capabilities())
+        .finish()
+}
+
+/// Whether `held` allows every action of `wanted`, on the same scope or a parent directory
+fn covers(held: &Capability, wanted: &Capability) -> bool {
+    let scope_covered = held.scope == wanted.scope
+        || (held.scope.ends_with('/') && wanted.scope.starts_with(&held.scope));
+
+    scope_covered && wanted.actions.iter().all(|a| held.actions.contains(a))
+}
+
+/// Capabilities needed by `feature` which the session does not hold yet
+pub(crate) fn missing_capabilities(session: &PubkySession, feature: Feature) -> Vec<Capability> {
+    let held = session.info().capabilities();
+
+    feature
+        .capabilities()
+        .into_iter()
+        .filter(|wanted| !held.iter().any(|cap| covers(cap, wanted)))
+        .collect()
+}
+
+impl PubkyApp {
+    /// Returns true if the session can already use `feature`.
+    ///
+    /// Otherwise starts an incremental auth flow for the missing capabilities and returns false,
+    /// so the caller can skip the action until the user approved the request.
+    pub(crate) fn ensure_capabilities(&mut self, session: &PubkySession, feature: Feature) -> bool {
+        let missing = missing_capabilities(session, feature);
+        if missing.is_empty() {
+            return true;
+        }
+
+        self.request_capabilities(session, feature, missing);
+        false
+    }
+
+    fn request_capabilities(
+        &mut self,
+        session: &PubkySession,
+        feature: Feature,
+        missing: Vec<Capability>,
+    ) {
+        if matches!(
+            *self.caps_request.lock().unwrap(),
+            CapsRequestState::Initializing(_) | CapsRequestState::ShowingQR { .. }
+        ) {
+            return;
+        }
+
+        // The approved session replaces the current one, so the held capabilities are carried over
+        let caps = Capabilities::builder()
+            .extend(session.info().capabilities().iter().cloned())
+            .extend(missing)
+            .finish();
+        let own_pk = session.info().public_key().clone();
+
+        self.caps_qr_texture = None;
+        *self.caps_request.lock().unwrap() = CapsRequestState::Initializing(feature);
+
+        let caps_request = self.caps_request.clone();
+        let state = self.state.clone();
+        let rt = self.rt.clone();
+        std::thread::spawn(move || {
+            match rt.block_on(initialize_auth(&caps)) {
+                Ok((_pubky, flow, auth_url)) => {
+                    *caps_request.lock().unwrap() = CapsRequestState::ShowingQR {
+                        auth_url: auth_url.clone(),
+                        feature,
+                    };
+
+                    let outcome = match rt.block_on(flow.await_approval()) {
+                        Ok(new_session) if new_session.info().public_key() == &own_pk => {
+                            if let AuthState::Authenticated {
+                                ref mut session, ..
+                            } = *state.lock().unwrap()
+                            {
+                                *session = new_session;
+                            }
+                            CapsRequestState::Granted(feature)
+                        }
+                        Ok(_) => CapsRequestState::Error(
+                            "The request was approved with a different key".into(),
+                        ),
+                        Err(e) => CapsRequestState::Error(format!("Authorization failed: {e}")),
+                    };
+
+                    // Only report back if the user did not cancel this request in the meantime
+                    let mut current = caps_request.lock().unwrap();
+                    if let CapsRequestState::ShowingQR { auth_url: ref url, .. } = *current {
+                        if *url == auth_url {
+                            *current = outcome;
+                        }
+                    }
+                }
+                Err(e) => {
+                    *caps_request.lock().unwrap() =
+                        CapsRequestState::Error(format!("Failed to initialize: {e}"));
+                }
+            }
+        });
+    }
+}
+
+/// List the capabilities held by the current session, with buttons to request the missing ones
+pub(crate) fn show_held(app: &mut PubkyApp, session: &PubkySession, ui: &mut Ui) {
+    CollapsingHeader::new(egui::RichText::new("🔑 Session Capabilities").size(15.0)).show(ui, |ui| {
+        ui.add_space(5.0);
+        for cap in session.info().capabilities() {
+            ui.label(egui::RichText::new(cap.to_string()).monospace());
+        }
+
+        ui.add_space(10.0);
+        for feature in Feature::ALL {
+            if missing_capabilities(session, feature).is_empty() {
+                continue;
+            }
+
+            ui.horizontal(|ui| {
+                ui.label(feature.label());
+                if ui.button("Request").clicked() {
+                    app.ensure_capabilities(session, feature);
+                }
+            });
+        }
+    });
+}
+
+/// Show the pending capability request, if any, in a window on top of the current view
+pub(crate) fn show_request_window(app: &mut PubkyApp, ctx: &Context) {
+    let request = app.caps_request.lock().unwrap().clone();
+    let mut close = false;
+
+    match request {
+        CapsRequestState::Idle => return,
+        CapsRequestState::Initializing(feature) => {
+            egui::Window::new("Additional Permissions")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label(format!("Preparing request to: {}", feature.label()));
+                    ui.spinner();
+                });
+        }
+        CapsRequestState::ShowingQR {
+            ref auth_url,
+            feature,
+        } => {
+            if app.caps_qr_texture.is_none() {
+                if let Some(qr_image) = generate_qr_image(auth_url) {
+                    app.caps_qr_texture =
+                        Some(ctx.load_texture("caps_qr_code", qr_image, Default::default()));
+                }
+            }
+
+            egui::Window::new("Additional Permissions")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label(format!("To {}, scan this QR code with your Pubky app to grant:", feature.label().to_lowercase()));
+                    ui.add_space(5.0);
+                    for cap in feature.capabilities() {
+                        ui.label(egui::RichText::new(cap.to_string()).monospace());
+                    }
+                    ui.add_space(10.0);
+
+                    if let Some(texture) = &app.caps_qr_texture {
+                        ui.add(egui::Image::from_texture(texture).max_size(egui::vec2(250.0, 250.0)));
+                    }
+
+                    ui.add_space(10.0);
+                    close = ui.button("Cancel").clicked();
+                });
+        }
+        CapsRequestState::Granted(feature) => {
+            egui::Window::new("Additional Permissions")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label(format!("Granted. You can now: {}", feature.label().to_lowercase()));
+                    ui.add_space(10.0);
+                    close = ui.button("OK").clicked();
+                });
+        }
+        CapsRequestState::Error(ref error) => {
+            egui::Window::new("Additional Permissions")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.colored_label(egui::Color32::RED, error);
+                    ui.add_space(10.0);
+                    close = ui.button("Close").clicked();
+                });
+        }
+    }
+
+    if close {
+        app.caps_qr_texture = None;
+        *app.caps_request.lock().unwrap() = CapsRequestState::Idle;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_same_scope_and_sub_paths() {
+        let held = Capability::write("/pub/wiki.app/");
+        assert!(covers(&held, &Capability::write("/pub/wiki.app/")));
+        assert!(covers(&held, &Capability::write("/pub/wiki.app/.trash/")));
+        assert!(!covers(&Capability::write("/pub/wiki.app/.trash/"), &held));
+    }
+
+    #[test]
+    fn does_not_cover_sibling_prefixes() {
+        let wanted = Capability::write("/pub/wiki.apple/");
+        assert!(!covers(&Capability::write("/pub/wiki.app/"), &wanted));
+        assert!(!covers(&Capability::write("/pub/wiki.app"), &wanted));
+        assert!(covers(&Capability::write("/pub/"), &wanted));
+    }
+
+    #[test]
+    fn covers_only_held_actions() {
+        let scope = "/pub/pubky.app/tags/";
+        assert!(!covers(&Capability::read(scope), &Capability::write(scope)));
+        assert!(!covers(&Capability::read(scope), &Capability::read_write(scope)));
+        assert!(!covers(&Capability::write(scope), &Capability::read_write(scope)));
+        assert!(covers(&Capability::read_write(scope), &Capability::write(scope)));
+        assert!(covers(&Capability::read_write(scope), &Capability::read(scope)));
+        assert!(covers(&Capability::root(), &Capability::read_write(scope)));
+    }
+}
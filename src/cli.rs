@@ -0,0 +1,226 @@
+//! Command line interface, used when the app is started with arguments.
+
+use std::{net::SocketAddr, path::Path, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use pubky::{PubkyAuthFlow, PubkySession, PublicKey, PublicStorage};
+use qrcode::{render::unicode, QrCode};
+use tokio::runtime::Runtime;
+
+use crate::{
+    capabilities::initial_capabilities,
+    export::{export_wiki, fetch_pages},
+    feed::{atom_feed, feed_size, pubky_href, FEED_SIZE},
+    gateway::{serve, DEFAULT_ADDRESS},
+    git_export::export_git,
+    import::{format_report, plan_import, run_import},
+    initialize_auth,
+    migrate::{initialize_signup, migrate, MigrationState},
+    revisions::fetch_histories,
+    site::generate_site,
+    sync::{sync, WATCH_INTERVAL},
+};
+
+const USAGE: &str = "Usage: pubky-wiki [COMMAND]
+
+Without a command, starts the desktop app.
+
+Commands:
+  export <public key> <directory>   Export all the wiki pages of a user as markdown files
+  git <public key> <directory> [<page id>]
+                                    Export the history of the wiki of a user, or of one of
+                                    their pages, as a git repository
+  import <public key> <directory or dump.xml> [--dry-run]
+                                    Import a folder of markdown files, an Obsidian vault or
+                                    a MediaWiki XML dump, or only show what would be imported with --dry-run
+  migrate <public key> <new homeserver> [--signup-token <token>]
+                                    Move the wiki of a user to a new homeserver, or resume
+                                    an interrupted migration
+  sync <directory> [--watch]        Sync your wiki with a folder of markdown files both ways,
+                                    again every 30 seconds with --watch
+  feed <public key> [<file>]        Write an Atom feed of the pages a user recently created or
+                                    updated, to standard output by default
+  site <directory> <public key>...  Render the wiki of one or more users as a static HTML site
+  serve [<address>]                 Serve wiki pages to web browsers, at 127.0.0.1:8080 by default
+  help                              Show this help";
+
+/// Run the command given as arguments
+pub(crate) fn run(args: &[String]) -> Result<()> {
+    let rt = Arc::new(Runtime::new()?);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["export", user_pk, dir] => {
+            let user_pk = parse_public_key(user_pk)?;
+            let pub_storage = PublicStorage::new()?;
+
+            let manifest = export_wiki(&pub_storage, &user_pk, Path::new(dir), rt)?;
+            for page in &manifest.pages {
+                println!("{}  {}", page.file, page.title);
+            }
+            println!("Exported {} pages to {dir}", manifest.pages.len());
+        }
+        ["git", user_pk, dir, options @ ..] if options.len() <= 1 => {
+            let user_pk = parse_public_key(user_pk)?;
+            let pub_storage = PublicStorage::new()?;
+
+            let count = export_git(&pub_storage, &user_pk, options.first().copied(), Path::new(dir), rt)?;
+            println!("Exported {count} commits to the git repository in {dir}");
+        }
+        ["import", user_pk, dir, options @ ..] if options.iter().all(|o| *o == "--dry-run") => {
+            let user_pk = parse_public_key(user_pk)?;
+            let pub_storage = PublicStorage::new()?;
+
+            let existing_pages = fetch_pages(&pub_storage, &user_pk, rt.clone())?;
+            let plan = plan_import(Path::new(dir), &existing_pages)?;
+            print!("{}", format_report(&plan));
+
+            if options.is_empty() {
+                let session = sign_in(&rt)?;
+                let session_pk = session.info().public_key().z32();
+                if session_pk != user_pk {
+                    bail!("Signed in as {session_pk} instead of {user_pk}");
+                }
+
+                let count = rt.block_on(run_import(&session, &plan))?;
+                println!("Imported {count} pages");
+            }
+        }
+        ["migrate", user_pk, new_homeserver, options @ ..] => {
+            let signup_token = match options {
+                [] => None,
+                ["--signup-token", token] => Some(token.to_string()),
+                _ => bail!("Invalid arguments\n\n{USAGE}"),
+            };
+            let user_pk: PublicKey = parse_public_key(user_pk)?.parse()?;
+            let new_homeserver: PublicKey = parse_public_key(new_homeserver)?.parse()?;
+            let pub_storage = PublicStorage::new()?;
+
+            let on_progress = |state| {
+                let (step, done, total) = match state {
+                    MigrationState::Saving { done, total } => ("Saving", done, total),
+                    MigrationState::Copying { done, total } => ("Copying", done, total),
+                    MigrationState::Verifying { done, total } => ("Verifying", done, total),
+                    _ => return,
+                };
+                println!("{step} files: {} of {total}", done + 1);
+            };
+            let count = migrate(&user_pk, &pub_storage, &new_homeserver, rt.clone(), on_progress, |already_moved| {
+                if already_moved {
+                    return sign_in(&rt);
+                }
+                let (flow, auth_url) = initialize_signup(&initial_capabilities(), &new_homeserver, signup_token)?;
+                await_approval(&rt, flow, &auth_url, "sign up on the new homeserver")
+            })?;
+            println!("Migrated and verified {count} files to {new_homeserver}");
+        }
+        ["sync", dir, options @ ..] if options.iter().all(|o| *o == "--watch") => {
+            let session = sign_in(&rt)?;
+            if options.is_empty() {
+                match rt.block_on(sync(&session, Path::new(dir)))? {
+                    report if report.is_empty() => println!("Already in sync"),
+                    report => print!("{report}"),
+                }
+                return Ok(());
+            }
+
+            // When watching, a failed sync, for example while offline, is retried on the next round
+            loop {
+                match rt.block_on(sync(&session, Path::new(dir))) {
+                    Ok(report) if report.is_empty() => {}
+                    Ok(report) => print!("{report}"),
+                    Err(e) => log::error!("Failed to sync {dir}: {e}"),
+                }
+                std::thread::sleep(WATCH_INTERVAL);
+            }
+        }
+        ["feed", user_pk, options @ ..] if options.len() <= 1 => {
+            let user_pk = parse_public_key(user_pk)?;
+            let pub_storage = PublicStorage::new()?;
+
+            let pages = fetch_histories(&pub_storage, &user_pk.parse()?, Some(FEED_SIZE), rt)?;
+            let feed = atom_feed(
+                &user_pk.parse()?,
+                &pages,
+                None,
+                |page_id| format!("pubky://{user_pk}/pub/wiki.app/{page_id}"),
+                |url| pubky_href(url, &user_pk, &pages),
+            );
+            match options.first() {
+                Some(file) => {
+                    std::fs::write(file, feed)?;
+                    println!("Wrote the feed of the {} latest changes to {file}", feed_size(&pages));
+                }
+                None => print!("{feed}"),
+            }
+        }
+        ["site", dir, user_pks @ ..] if !user_pks.is_empty() => {
+            let user_pks = user_pks
+                .iter()
+                .map(|user_pk| parse_public_key(user_pk))
+                .collect::<Result<Vec<_>>>()?;
+            let pub_storage = PublicStorage::new()?;
+
+            let count = generate_site(&pub_storage, &user_pks, Path::new(dir), rt)?;
+            println!("Generated {count} pages in {dir}, open {dir}/index.html to browse them");
+        }
+        ["serve", options @ ..] if options.len() <= 1 => {
+            let address = options.first().copied().unwrap_or(DEFAULT_ADDRESS);
+            let address: SocketAddr = address
+                .parse()
+                .map_err(|e| anyhow!("Invalid address {address}: {e}"))?;
+
+            println!("Serving wiki pages at http://{address}/<public key>/, press Ctrl+C to stop");
+            rt.block_on(serve(address))?;
+        }
+        ["help" | "--help" | "-h"] => println!("{USAGE}"),
+        _ => bail!("Invalid arguments\n\n{USAGE}"),
+    }
+
+    Ok(())
+}
+
+/// Sign in by scanning a QR code shown in the terminal with Pubky Ring
+fn sign_in(rt: &Runtime) -> Result<PubkySession> {
+    let caps = initial_capabilities();
+    let (_pubky, flow, auth_url) = rt.block_on(initialize_auth(&caps))?;
+
+    await_approval(rt, flow, &auth_url, "sign in")
+}
+
+/// Show the QR code of an auth flow in the terminal, and wait for Pubky Ring to approve it
+fn await_approval(rt: &Runtime, flow: PubkyAuthFlow, auth_url: &str, action: &str) -> Result<PubkySession> {
+    let qr_code = QrCode::new(auth_url.as_bytes())?;
+    println!("{}", qr_code.render::<unicode::Dense1x2>().quiet_zone(true).build());
+    println!("Scan this QR code with Pubky Ring to {action}, or open: {auth_url}");
+
+    let session = rt.block_on(flow.await_approval())?;
+    println!("Signed in as {}", session.info().public_key());
+
+    Ok(session)
+}
+
+/// The bare z32 form of a public key given with or without the `pubky` prefix or scheme
+fn parse_public_key(user_pk: &str) -> Result<String> {
+    let user_pk = user_pk.trim().trim_start_matches("pubky://").trim_end_matches('/');
+    let key = user_pk
+        .parse::<PublicKey>()
+        .map_err(|e| anyhow!("Invalid public key {user_pk}: {e}"))?;
+
+    Ok(key.z32())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PK: &str = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+
+    #[test]
+    fn parses_public_keys_to_the_bare_key() {
+        for input in [PK.to_string(), format!("pubky{PK}"), format!(" pubky://{PK}/ ")] {
+            assert_eq!(parse_public_key(&input).unwrap(), PK, "{input}");
+        }
+        assert!(parse_public_key("not-a-key").is_err());
+    }
+}
@@ -1,28 +1,47 @@
-use crate::{create_wiki_post, utils::extract_title, AuthState, PubkyApp, ViewState};
+use std::collections::HashMap;
+
+use crate::{
+    capabilities::{missing_capabilities, Feature},
+    create_wiki_post, drafts, editor,
+    posts::{announce, announcement},
+    revisions,
+    user_wiki::parse_user_pk,
+    utils::extract_title,
+    AuthState, PubkyApp, ViewState,
+};
 
 use eframe::egui::{Context, Ui};
-use pubky::PubkySession;
+use pubky::{PubkySession, PublicKey, PublicStorage};
 
-pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, _ctx: &Context, ui: &mut Ui) {
+pub(crate) fn update(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    file_cache: &HashMap<String, String>,
+    _ctx: &Context,
+    ui: &mut Ui,
+) {
     ui.label(egui::RichText::new("Create New Wiki Page").size(20.0).strong());
     ui.add_space(25.0);
 
-    // Textarea for wiki content
-    ui.label(egui::RichText::new("Content:").size(16.0));
-    ui.add_space(12.0);
-
-    egui::ScrollArea::vertical()
-        .max_height(400.0)
-        .show(ui, |ui| {
-            ui.add(
-                egui::TextEdit::multiline(&mut app.edit_wiki_content)
-                    .desired_width(f32::INFINITY)
-                    .desired_rows(15)
-                    .font(egui::TextStyle::Monospace),
-            );
-        });
+    drafts::show_restored_notice(app, session, ui);
+    editor::show(app, session, pub_storage, file_cache, ui);
+    app.autosave_draft(session);
 
-    ui.add_space(25.0);
+    ui.add_space(15.0);
+
+    let announce_label = match app.forked_from_page_id {
+        Some(_) => "📣 Announce the fork as a pubky.app post",
+        None => "📣 Announce the page as a pubky.app post",
+    };
+    let announce_checkbox = ui
+        .checkbox(&mut app.announce, announce_label)
+        .on_hover_text("Your followers will see the title and link of the page in their pubky.app feed");
+    if announce_checkbox.changed() && app.announce {
+        app.ensure_capabilities(session, Feature::Posts);
+    }
+
+    ui.add_space(10.0);
 
     ui.horizontal(|ui| {
         // Save button for creating new page
@@ -49,18 +68,30 @@ pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, _ctx: &Context,
                             ..
                         } = *state
                         {
-                            let own_user_pk = session.info().public_key().to_string();
+                            let own_user_pk = session.info().public_key().z32();
                             let file_url = format!("pubky://{own_user_pk}{wiki_page_path}");
                             let file_title = extract_title(&content);
                             file_cache.insert(file_url, file_title.into());
                         }
                     }
+                    app.discard_draft(session);
+
+                    if let Some(page_id) = app.forked_from_page_id.clone() {
+                        record_fork(app, session, pub_storage, &page_id);
+                    }
+                    if app.announce {
+                        announce_page(app, session, &content, &wiki_page_path);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to create wiki post: {e}");
+                    app.keep_draft(session);
                 }
-                Err(e) => log::error!("Failed to create wiki post: {e}"),
             }
 
             app.edit_wiki_content.clear();
             app.forked_from_page_id = None;
+            app.announce = false;
             app.view_state = ViewState::WikiList;
         }
 
@@ -70,9 +101,41 @@ pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, _ctx: &Context,
             egui::Button::new(egui::RichText::new("Cancel").size(15.0))
         );
         if cancel_button.clicked() {
+            app.keep_draft(session);
             app.edit_wiki_content.clear();
             app.forked_from_page_id = None;
-            app.view_state = ViewState::WikiList;
+            app.announce = false;
+            app.go_back(session, pub_storage);
         }
     });
 }
+
+/// Record the page and revision a newly saved fork was made from
+fn record_fork(app: &PubkyApp, session: &PubkySession, pub_storage: &PublicStorage, page_id: &str) {
+    let Ok(original_pk) = app.selected_wiki_user_id.parse::<PublicKey>() else {
+        return;
+    };
+    if let Err(e) = app.rt.block_on(revisions::record_fork(session, pub_storage, page_id, &original_pk)) {
+        log::error!("Failed to record the fork point of {page_id}: {e}");
+    }
+}
+
+/// Post the title and link of a newly created page, or fork, to the user's pubky.app feed
+fn announce_page(app: &PubkyApp, session: &PubkySession, content: &str, wiki_page_path: &str) {
+    if !missing_capabilities(session, Feature::Posts).is_empty() {
+        log::error!("Not announcing {wiki_page_path}: permission to write posts was not granted");
+        return;
+    }
+
+    let own_pk = session.info().public_key().z32();
+    let page_url = format!("pubky://{own_pk}{wiki_page_path}");
+    let forked_from = app.forked_from_page_id.as_ref().and_then(|page_id| {
+        let original_pk = parse_user_pk(&app.selected_wiki_user_id)?.z32();
+        Some(format!("pubky://{original_pk}/pub/wiki.app/{page_id}"))
+    });
+
+    let post = announcement(extract_title(content), &page_url, forked_from.as_deref());
+    if let Err(e) = app.rt.block_on(announce(session, &post)) {
+        log::error!("Failed to announce {wiki_page_path}: {e}");
+    }
+}
@@ -0,0 +1,224 @@
+//! Line-based diff and three-way merge of page contents.
+
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Lines of a text, each keeping its line ending so that joining them gives back the text
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Line diff turning `old` into `new`, based on their longest common subsequence
+pub(crate) fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    diff(&lines(old), &lines(new))
+}
+
+fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+    // lcs[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut result = vec![];
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            result.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            result.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            result.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    result.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    result.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+    result
+}
+
+/// A change replacing the `base` lines in `range` by `lines`
+struct Hunk<'a> {
+    range: Range<usize>,
+    lines: Vec<&'a str>,
+}
+
+fn hunks<'a>(base: &[&'a str], other: &[&'a str]) -> Vec<Hunk<'a>> {
+    let mut result: Vec<Hunk> = vec![];
+    let mut base_index = 0;
+    let mut in_hunk = false;
+
+    for line in diff(base, other) {
+        match line {
+            DiffLine::Same(_) => {
+                base_index += 1;
+                in_hunk = false;
+            }
+            DiffLine::Removed(_) | DiffLine::Added(_) => {
+                if !in_hunk {
+                    result.push(Hunk {
+                        range: base_index..base_index,
+                        lines: vec![],
+                    });
+                    in_hunk = true;
+                }
+                let hunk = result.last_mut().expect("hunk was just pushed");
+                match line {
+                    DiffLine::Removed(_) => {
+                        base_index += 1;
+                        hunk.range.end = base_index;
+                    }
+                    DiffLine::Added(added) => hunk.lines.push(added),
+                    DiffLine::Same(_) => unreachable!(),
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// The `base` lines in `range`, with the given hunks applied
+fn apply<'a>(base: &[&'a str], range: Range<usize>, hunks: &[Hunk<'a>]) -> Vec<&'a str> {
+    let mut result = vec![];
+    let mut pos = range.start;
+    for hunk in hunks {
+        result.extend_from_slice(&base[pos..hunk.range.start]);
+        result.extend_from_slice(&hunk.lines);
+        pos = hunk.range.end;
+    }
+    result.extend_from_slice(&base[pos..range.end]);
+    result
+}
+
+pub(crate) struct Merge {
+    pub(crate) content: String,
+    /// Number of conflicting changes, marked in the content
+    pub(crate) conflicts: usize,
+}
+
+/// Three-way merge of the changes made to `base` in `ours` and in `theirs`.
+///
+/// Overlapping changes that differ are kept both, between git-style conflict markers.
+pub(crate) fn merge(base: &str, ours: &str, theirs: &str) -> Merge {
+    let base = lines(base);
+    let our_hunks = hunks(&base, &lines(ours));
+    let their_hunks = hunks(&base, &lines(theirs));
+
+    let mut content = String::new();
+    let mut conflicts = 0;
+    let (mut i, mut j) = (0, 0);
+    let mut pos = 0;
+
+    loop {
+        // Start a group with the first remaining hunk of either side
+        let start = match (our_hunks.get(i), their_hunks.get(j)) {
+            (Some(ours), Some(theirs)) => ours.range.start.min(theirs.range.start),
+            (Some(ours), None) => ours.range.start,
+            (None, Some(theirs)) => theirs.range.start,
+            (None, None) => break,
+        };
+
+        // Extend the group with all the hunks touching it, on both sides
+        let (first_ours, first_theirs) = (i, j);
+        let mut end = start;
+        loop {
+            if let Some(hunk) = our_hunks.get(i).filter(|h| h.range.start <= end) {
+                end = end.max(hunk.range.end);
+                i += 1;
+            } else if let Some(hunk) = their_hunks.get(j).filter(|h| h.range.start <= end) {
+                end = end.max(hunk.range.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        content.extend(base[pos..start].iter().copied());
+        let ours = apply(&base, start..end, &our_hunks[first_ours..i]);
+        let theirs = apply(&base, start..end, &their_hunks[first_theirs..j]);
+
+        if first_theirs == j || ours == theirs {
+            content.extend(ours);
+        } else if first_ours == i {
+            content.extend(theirs);
+        } else {
+            conflicts += 1;
+            push_conflict(&mut content, &ours, &theirs);
+        }
+        pos = end;
+    }
+    content.extend(base[pos..].iter().copied());
+
+    Merge { content, conflicts }
+}
+
+fn push_conflict(content: &mut String, ours: &[&str], theirs: &[&str]) {
+    let mut push_lines = |marker: &str, lines: &[&str]| {
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(marker);
+        content.extend(lines.iter().copied());
+    };
+    push_lines("<<<<<<< yours\n", ours);
+    push_lines("=======\n", theirs);
+    push_lines(">>>>>>> theirs\n", &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_lines() {
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nc\nd\n"),
+            vec![
+                DiffLine::Same("a\n"),
+                DiffLine::Removed("b\n"),
+                DiffLine::Same("c\n"),
+                DiffLine::Added("d\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_separate_changes() {
+        let merge = merge("a\nb\nc\nd\n", "A\nb\nc\nd\n", "a\nb\nc\nD\ne\n");
+        assert_eq!(merge.content, "A\nb\nc\nD\ne\n");
+        assert_eq!(merge.conflicts, 0);
+    }
+
+    #[test]
+    fn merges_identical_changes() {
+        let merge = merge("a\nb\n", "a\nB\n", "a\nB\n");
+        assert_eq!(merge.content, "a\nB\n");
+        assert_eq!(merge.conflicts, 0);
+    }
+
+    #[test]
+    fn marks_conflicts() {
+        let merge = merge("a\nb\nc", "a\nours\nc", "a\ntheirs\nc");
+        assert_eq!(
+            merge.content,
+            "a\n<<<<<<< yours\nours\n=======\ntheirs\n>>>>>>> theirs\nc"
+        );
+        assert_eq!(merge.conflicts, 1);
+    }
+}
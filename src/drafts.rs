@@ -0,0 +1,425 @@
+//! Local drafts of the pages being edited, autosaved so that no text is lost on cancel,
+//! crash or failed save.
+//!
+//! Drafts are stored as JSON files in `<local data dir>/pubky-wiki/drafts/<user pk>/`,
+//! named after the page ID when editing a page, or a random draft ID for a new page.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use eframe::egui::Ui;
+use pubky::PubkySession;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    utils::{extract_title, format_age, now_timestamp},
+    PubkyApp,
+};
+
+/// Minimum time between two autosaves of the same draft
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Draft {
+    pub(crate) draft_id: String,
+    /// Page being edited, or `None` for a new page
+    pub(crate) page_id: Option<String>,
+    /// Page ID from which the new page is forked
+    pub(crate) forked_from_page_id: Option<String>,
+    pub(crate) content: String,
+    /// Content of the page when the edit started, to detect changes made elsewhere since
+    #[serde(default)]
+    pub(crate) base_content: Option<String>,
+    /// Unix timestamp (seconds) of the last save
+    pub(crate) saved_at: u64,
+}
+
+/// Autosave state of the draft open in the editor
+#[derive(Default)]
+pub(crate) struct Autosave {
+    draft_id: Option<String>,
+    page_id: Option<String>,
+    /// Content of the page when the editor was opened, empty for a new page
+    original_content: String,
+    /// Content of the page the edits are based on, older than `original_content` for a restored draft
+    base_content: Option<String>,
+    last_saved_content: String,
+    last_saved_at: Option<Instant>,
+    /// When the draft restored on opening the editor was saved, if any
+    pub(crate) restored_from: Option<u64>,
+}
+
+fn drafts_dir(user_pk: &str) -> Result<PathBuf> {
+    let data_dir = dirs::data_local_dir().ok_or(anyhow!("No local data directory"))?;
+    Ok(data_dir.join("pubky-wiki").join("drafts").join(user_pk))
+}
+
+/// Drafts folder of the logged in user
+fn own_drafts_dir(session: &PubkySession) -> Result<PathBuf> {
+    drafts_dir(&session.info().public_key().z32())
+}
+
+fn draft_path(dir: &Path, draft_id: &str) -> PathBuf {
+    dir.join(format!("{draft_id}.json"))
+}
+
+pub(crate) fn save_draft(dir: &Path, draft: &Draft) -> Result<()> {
+    let path = draft_path(dir, &draft.draft_id);
+    fs::create_dir_all(dir)?;
+    fs::write(&path, serde_json::to_string_pretty(draft)?)?;
+
+    log::info!("Saved draft at: {}", path.display());
+
+    Ok(())
+}
+
+pub(crate) fn load_draft(dir: &Path, draft_id: &str) -> Option<Draft> {
+    let json = fs::read_to_string(draft_path(dir, draft_id)).ok()?;
+
+    serde_json::from_str(&json)
+        .inspect_err(|e| log::error!("Invalid draft {draft_id}: {e}"))
+        .ok()
+}
+
+pub(crate) fn delete_draft(dir: &Path, draft_id: &str) {
+    let path = draft_path(dir, draft_id);
+    if path.exists() {
+        if let Err(e) = fs::remove_file(&path) {
+            log::error!("Failed to delete draft {}: {e}", path.display());
+        }
+    }
+}
+
+/// All drafts in `dir`, most recent first
+pub(crate) fn list_drafts(dir: &Path) -> Vec<Draft> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut drafts: Vec<Draft> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let draft_id = entry.path().file_stem()?.to_str()?.to_string();
+            load_draft(dir, &draft_id)
+        })
+        .collect();
+    drafts.sort_by_key(|draft| std::cmp::Reverse(draft.saved_at));
+    drafts
+}
+
+impl Autosave {
+    /// Autosave of an edit of an existing page, whose draft is named after the page
+    fn for_page(page_id: &str, content: &str) -> Self {
+        Autosave {
+            draft_id: Some(page_id.to_string()),
+            page_id: Some(page_id.to_string()),
+            original_content: content.to_string(),
+            base_content: Some(content.to_string()),
+            last_saved_content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Write `content` as the draft in `dir`
+    fn save(&mut self, dir: &Path, content: &str, forked_from_page_id: Option<String>) {
+        let Some(draft_id) = self.draft_id.clone() else {
+            return;
+        };
+
+        let draft = Draft {
+            draft_id,
+            page_id: self.page_id.clone(),
+            forked_from_page_id,
+            content: content.to_string(),
+            base_content: self.base_content.clone(),
+            saved_at: now_timestamp(),
+        };
+
+        match save_draft(dir, &draft) {
+            Ok(()) => self.last_saved_content = draft.content,
+            Err(e) => log::error!("Failed to save draft: {e}"),
+        }
+        self.last_saved_at = Some(Instant::now());
+    }
+
+    /// On leaving the editor without saving, keep the draft of `content` unless it is unchanged
+    fn keep(&mut self, dir: &Path, content: &str, forked_from_page_id: Option<String>) {
+        let is_unchanged = content.trim().is_empty() || content == self.original_content;
+
+        if is_unchanged {
+            self.discard(dir);
+            return;
+        }
+
+        if content != self.last_saved_content {
+            self.save(dir, content, forked_from_page_id);
+        }
+        *self = Autosave::default();
+    }
+
+    /// Delete the draft from `dir`
+    fn discard(&mut self, dir: &Path) {
+        if let Some(draft_id) = &self.draft_id {
+            delete_draft(dir, draft_id);
+        }
+        *self = Autosave::default();
+    }
+}
+
+impl PubkyApp {
+    /// Open the editor on the selected page, restoring its draft if one was left unsaved
+    pub(crate) fn start_edit_draft(&mut self, session: &PubkySession) {
+        let page_id = self.selected_wiki_page_id.clone();
+        self.autosave = Autosave::for_page(&page_id, &self.edit_wiki_content);
+
+        let draft = own_drafts_dir(session)
+            .ok()
+            .and_then(|dir| load_draft(&dir, &page_id));
+        if let Some(draft) = draft {
+            if draft.content != self.edit_wiki_content {
+                self.edit_wiki_content = draft.content.clone();
+                self.autosave.last_saved_content = draft.content;
+                self.autosave.restored_from = Some(draft.saved_at);
+                // The draft was written against the page as it was then, so that the changes made
+                // elsewhere since are detected when saving
+                if let Some(base_content) = draft.base_content {
+                    self.edit_base_content = base_content.clone();
+                    self.autosave.base_content = Some(base_content);
+                }
+            }
+        }
+    }
+
+    /// Resume a new page draft in the Create Wiki view
+    pub(crate) fn resume_new_page_draft(&mut self, draft: Draft) {
+        self.edit_wiki_content = draft.content.clone();
+        self.forked_from_page_id = draft.forked_from_page_id;
+        self.autosave = Autosave {
+            draft_id: Some(draft.draft_id),
+            last_saved_content: draft.content,
+            restored_from: Some(draft.saved_at),
+            ..Default::default()
+        };
+    }
+
+    /// Save the editor content as draft, if it changed and the last save is old enough
+    pub(crate) fn autosave_draft(&mut self, session: &PubkySession) {
+        if self.autosave.draft_id.is_none() {
+            // New page: the draft gets a random ID, and starts from the prefilled content if any
+            self.autosave.draft_id = Some(Uuid::new_v4().to_string());
+            self.autosave.original_content = self.edit_wiki_content.clone();
+            self.autosave.last_saved_content = self.edit_wiki_content.clone();
+        }
+
+        let is_due = self
+            .autosave
+            .last_saved_at
+            .is_none_or(|t| t.elapsed() >= AUTOSAVE_INTERVAL);
+        if is_due && self.edit_wiki_content != self.autosave.last_saved_content {
+            match own_drafts_dir(session) {
+                Ok(dir) => {
+                    let forked_from_page_id = self.forked_from_page_id.clone();
+                    self.autosave.save(&dir, &self.edit_wiki_content, forked_from_page_id);
+                }
+                Err(e) => log::error!("Failed to save draft: {e}"),
+            }
+            self.drafts = None;
+        }
+    }
+
+    /// Leave the editor keeping the draft, for example on cancel or failed save
+    pub(crate) fn keep_draft(&mut self, session: &PubkySession) {
+        match own_drafts_dir(session) {
+            Ok(dir) => {
+                let forked_from_page_id = self.forked_from_page_id.clone();
+                self.autosave.keep(&dir, &self.edit_wiki_content, forked_from_page_id);
+            }
+            Err(e) => {
+                log::error!("Failed to save draft: {e}");
+                self.autosave = Autosave::default();
+            }
+        }
+        self.drafts = None;
+    }
+
+    /// Leave the editor after the content was saved to the homeserver, deleting the draft
+    pub(crate) fn discard_draft(&mut self, session: &PubkySession) {
+        match own_drafts_dir(session) {
+            Ok(dir) => self.autosave.discard(&dir),
+            Err(_) => self.autosave = Autosave::default(),
+        }
+        self.drafts = None;
+    }
+
+    /// Drafts of the user, read from disk again only after a draft was saved or deleted
+    fn get_drafts(&mut self, session: &PubkySession) -> &[Draft] {
+        self.drafts.get_or_insert_with(|| {
+            own_drafts_dir(session)
+                .map(|dir| list_drafts(&dir))
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Tell the user the editor content comes from a draft, and let them drop it
+pub(crate) fn show_restored_notice(app: &mut PubkyApp, session: &PubkySession, ui: &mut Ui) {
+    let Some(saved_at) = app.autosave.restored_from else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.label(
+            egui::RichText::new(format!("📝 Restored unsaved draft from {}", format_age(saved_at)))
+                .italics(),
+        );
+        if ui.button("Discard draft").clicked() {
+            if let (Some(draft_id), Ok(dir)) = (&app.autosave.draft_id, own_drafts_dir(session)) {
+                delete_draft(&dir, draft_id);
+                app.drafts = None;
+            }
+            if app.autosave.page_id.is_some() {
+                app.edit_base_content = app.autosave.original_content.clone();
+                app.autosave.base_content = Some(app.autosave.original_content.clone());
+            }
+            app.edit_wiki_content = app.autosave.original_content.clone();
+            app.autosave.last_saved_content = app.autosave.original_content.clone();
+            app.autosave.restored_from = None;
+        }
+    });
+    ui.add_space(10.0);
+}
+
+/// List the unfinished drafts, to resume or discard them
+pub(crate) fn show_drafts(app: &mut PubkyApp, session: &PubkySession, ui: &mut Ui) -> Option<Draft> {
+    let drafts = app.get_drafts(session).to_vec();
+    if drafts.is_empty() {
+        return None;
+    }
+
+    let mut resumed = None;
+    egui::CollapsingHeader::new(
+        egui::RichText::new(format!("📝 Unfinished Drafts ({})", drafts.len())).size(15.0),
+    )
+    .show(ui, |ui| {
+        for draft in drafts {
+            ui.horizontal(|ui| {
+                if ui.button("Resume").clicked() {
+                    resumed = Some(draft.clone());
+                }
+                if ui.button("Discard").clicked() {
+                    if let Ok(dir) = own_drafts_dir(session) {
+                        delete_draft(&dir, &draft.draft_id);
+                    }
+                    app.drafts = None;
+                }
+
+                let title = extract_title(&draft.content);
+                let title = if title.trim().is_empty() { "(untitled)" } else { title };
+                let kind = if draft.page_id.is_some() { "edit" } else { "new page" };
+                ui.label(egui::RichText::new(title).strong());
+                ui.label(
+                    egui::RichText::new(format!("{kind}, {}", format_age(draft.saved_at)))
+                        .color(egui::Color32::GRAY),
+                );
+            });
+        }
+    });
+
+    resumed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pubky-wiki-drafts-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn new_page() -> Autosave {
+        Autosave {
+            draft_id: Some("draft-1".into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn saves_loads_and_discards_drafts() {
+        let dir = temp_dir("save");
+        let mut autosave = Autosave::for_page("id-1", "# Lugano\n\nOld");
+
+        autosave.save(&dir, "# Lugano\n\nNew", None);
+        let draft = load_draft(&dir, "id-1").unwrap();
+        assert_eq!(draft.page_id.as_deref(), Some("id-1"));
+        assert_eq!(draft.content, "# Lugano\n\nNew");
+        assert_eq!(draft.base_content.as_deref(), Some("# Lugano\n\nOld"));
+
+        autosave.discard(&dir);
+        assert!(load_draft(&dir, "id-1").is_none());
+        assert!(list_drafts(&dir).is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_changed_drafts_on_cancel() {
+        let dir = temp_dir("keep");
+
+        new_page().keep(&dir, "# Zurich", Some("id-2".into()));
+        let draft = load_draft(&dir, "draft-1").unwrap();
+        assert_eq!(draft.page_id, None);
+        assert_eq!(draft.forked_from_page_id.as_deref(), Some("id-2"));
+        assert_eq!(draft.content, "# Zurich");
+
+        Autosave::for_page("id-1", "# Lugano").keep(&dir, "# Lugano\n\nNew", None);
+        assert_eq!(load_draft(&dir, "id-1").unwrap().content, "# Lugano\n\nNew");
+        assert_eq!(list_drafts(&dir).len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn discards_unchanged_drafts_on_cancel() {
+        let dir = temp_dir("discard");
+
+        // An autosaved change that was undone before cancelling
+        let mut autosave = Autosave::for_page("id-1", "# Lugano");
+        autosave.save(&dir, "# Lugano\n\nNew", None);
+        autosave.keep(&dir, "# Lugano", None);
+        assert!(load_draft(&dir, "id-1").is_none());
+
+        let mut autosave = new_page();
+        autosave.save(&dir, "# Zurich", None);
+        autosave.keep(&dir, "  \n", None);
+        assert!(list_drafts(&dir).is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_one_draft_per_page() {
+        let dir = temp_dir("one");
+
+        let mut autosave = Autosave::for_page("id-1", "# Lugano");
+        autosave.save(&dir, "# Lugano\n\nFirst", None);
+        autosave.save(&dir, "# Lugano\n\nSecond", None);
+        autosave.keep(&dir, "# Lugano\n\nThird", None);
+
+        // Editing the page again starts from the same draft
+        Autosave::for_page("id-1", "# Lugano").keep(&dir, "# Lugano\n\nFourth", None);
+
+        let drafts = list_drafts(&dir);
+        assert_eq!(drafts.len(), 1);
+        assert_eq!(drafts[0].draft_id, "id-1");
+        assert_eq!(drafts[0].content, "# Lugano\n\nFourth");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
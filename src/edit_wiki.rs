@@ -1,26 +1,41 @@
-use crate::{delete_wiki_post, update_wiki_post, AuthState, PubkyApp, ViewState};
+use std::collections::HashMap;
+
+use crate::{
+    diff::{self, DiffLine},
+    drafts, editor, trash, update_wiki_post,
+    utils::get_own_content,
+    PubkyApp, ViewState,
+};
 
 use eframe::egui::{Context, Ui};
-use pubky::PubkySession;
+use pubky::{PubkySession, PublicStorage};
+
+/// Marker starting a conflicting change left by a merge
+const CONFLICT_MARKER: &str = "<<<<<<< yours";
 
-pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, _ctx: &Context, ui: &mut Ui) {
+pub(crate) fn update(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    file_cache: &HashMap<String, String>,
+    ctx: &Context,
+    ui: &mut Ui,
+) {
     ui.label(egui::RichText::new("Edit Wiki Page").size(20.0).strong());
     ui.add_space(25.0);
 
-    // Textarea for wiki content
-    ui.label(egui::RichText::new("Content:").size(16.0));
-    ui.add_space(12.0);
-
-    egui::ScrollArea::vertical()
-        .max_height(400.0)
-        .show(ui, |ui| {
-            ui.add(
-                egui::TextEdit::multiline(&mut app.edit_wiki_content)
-                    .desired_width(f32::INFINITY)
-                    .desired_rows(15)
-                    .font(egui::TextStyle::Monospace),
-            );
-        });
+    drafts::show_restored_notice(app, session, ui);
+    if app.edit_wiki_content.contains(CONFLICT_MARKER) {
+        ui.label(
+            egui::RichText::new(format!(
+                "⚠ Conflicting changes are marked between \"{CONFLICT_MARKER}\" and \">>>>>>> theirs\", resolve them before updating"
+            ))
+            .color(egui::Color32::from_rgb(200, 150, 50)),
+        );
+        ui.add_space(10.0);
+    }
+    editor::show(app, session, pub_storage, file_cache, ui);
+    app.autosave_draft(session);
 
     ui.add_space(25.0);
 
@@ -30,23 +45,7 @@ pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, _ctx: &Context,
             egui::Button::new(egui::RichText::new("✓ Update").size(15.0))
         );
         if update_button.clicked() {
-            let session_clone = session.clone();
-            let content = app.edit_wiki_content.clone();
-            let page_id = app.selected_wiki_page_id.clone();
-
-            let update_wiki_post_fut = update_wiki_post(&session_clone, &page_id, &content);
-            match app.rt.block_on(update_wiki_post_fut) {
-                Ok(_) => {
-                    log::info!("Updated wiki post: {}", page_id);
-                    // Update the selected content to reflect changes
-                    app.selected_wiki_content = content;
-                }
-                Err(e) => log::error!("Failed to update wiki post: {e}"),
-            }
-
-            app.edit_wiki_content.clear();
-            app.view_state = ViewState::WikiList;
-            app.needs_refresh = true;
+            check_and_save(app, session);
         }
 
         ui.add_space(10.0);
@@ -56,38 +55,7 @@ pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, _ctx: &Context,
             egui::Button::new(egui::RichText::new("🗑 Delete").size(15.0).color(egui::Color32::from_rgb(200, 80, 80)))
         );
         if delete_button.clicked() {
-            let session_clone = session.clone();
-            let page_id = app.selected_wiki_page_id.clone();
-            let state_clone = app.state.clone();
-
-            let delete_wiki_post_fut = delete_wiki_post(&session_clone, &page_id);
-            match app.rt.block_on(delete_wiki_post_fut) {
-                Ok(_) => {
-                    log::info!("Deleted wiki post: {}", page_id);
-
-                    // Remove from file_urls list
-                    if let Ok(mut state) = state_clone.lock() {
-                        if let AuthState::Authenticated {
-                            ref session,
-                            ref mut file_cache,
-                            ..
-                        } = *state
-                        {
-                            let own_user_pk = session.info().public_key().to_string();
-                            let file_url = format!("pubky://{own_user_pk}/pub/wiki.app/{page_id}");
-                            file_cache.remove(&file_url);
-                        }
-                    }
-                }
-                Err(e) => log::error!("Failed to delete wiki post: {e}"),
-            }
-
-            app.edit_wiki_content.clear();
-            app.selected_wiki_page_id.clear();
-            app.selected_wiki_content.clear();
-            app.selected_wiki_fork_urls.clear();
-            app.view_state = ViewState::WikiList;
-            app.needs_refresh = true;
+            app.confirm_delete = true;
         }
 
         ui.add_space(10.0);
@@ -96,8 +64,240 @@ pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, _ctx: &Context,
             egui::Button::new(egui::RichText::new("Cancel").size(15.0))
         );
         if cancel_button.clicked() {
+            app.keep_draft(session);
             app.edit_wiki_content.clear();
-            app.view_state = ViewState::WikiList;
+            app.go_back(session, pub_storage);
         }
     });
+
+    show_conflict_window(app, session, ctx);
+    trash::show_confirm_delete_window(app, session, pub_storage, ctx);
+}
+
+/// Why the edited page was not saved right away
+pub(crate) enum EditConflict {
+    /// The page was changed elsewhere since it was loaded
+    Changed {
+        remote_content: String,
+        /// Lines of the diff from the remote content to the edited one, with their color
+        diff: Vec<(String, egui::Color32)>,
+    },
+    /// The current version of the page could not be fetched to check for changes
+    CheckFailed(String),
+}
+
+fn diff_to_remote(remote_content: &str, content: &str) -> Vec<(String, egui::Color32)> {
+    diff::diff_lines(remote_content, content)
+        .into_iter()
+        .map(|line| match line {
+            DiffLine::Same(text) => (format!("  {text}"), egui::Color32::GRAY),
+            DiffLine::Removed(text) => (format!("- {text}"), egui::Color32::from_rgb(200, 80, 80)),
+            DiffLine::Added(text) => (format!("+ {text}"), egui::Color32::from_rgb(80, 170, 80)),
+        })
+        .map(|(text, color)| (text.trim_end_matches('\n').to_string(), color))
+        .collect()
+}
+
+/// Save the editor content, unless the page was changed elsewhere since it was loaded or
+/// that could not be checked
+fn check_and_save(app: &mut PubkyApp, session: &PubkySession) {
+    // Only own pages are edited, so the current version is read through the session
+    let path = format!("/pub/wiki.app/{}", app.selected_wiki_page_id);
+    let remote_content = get_own_content(session, &path, app.rt.clone());
+
+    if check_remote_changes(app, remote_content) {
+        save(app, session);
+    }
+}
+
+/// Whether the editor content can be saved over `remote_content`, the current version of the
+/// page, recording the conflict otherwise
+fn check_remote_changes(app: &mut PubkyApp, remote_content: anyhow::Result<String>) -> bool {
+    let page_id = &app.selected_wiki_page_id;
+    match remote_content {
+        Ok(remote_content)
+            if remote_content != app.edit_base_content && remote_content != app.edit_wiki_content =>
+        {
+            log::warn!("Wiki post {page_id} was changed since it was loaded");
+            let diff = diff_to_remote(&remote_content, &app.edit_wiki_content);
+            app.edit_conflict = Some(EditConflict::Changed { remote_content, diff });
+            false
+        }
+        Ok(_) => true,
+        Err(e) => {
+            log::error!("Could not check remote changes of {page_id}: {e}");
+            app.edit_conflict = Some(EditConflict::CheckFailed(e.to_string()));
+            false
+        }
+    }
+}
+
+/// Combine the edits with the changes of `remote_content`, marking the ones that conflict
+fn merge_remote_changes(app: &mut PubkyApp, remote_content: &str) {
+    let merge = diff::merge(&app.edit_base_content, &app.edit_wiki_content, remote_content);
+    log::info!("Merged remote changes with {} conflict(s)", merge.conflicts);
+
+    app.edit_wiki_content = merge.content;
+    app.edit_base_content = remote_content.to_string();
+    app.edit_conflict = None;
+}
+
+/// Write the editor content to the page and leave the editor
+fn save(app: &mut PubkyApp, session: &PubkySession) {
+    let content = app.edit_wiki_content.clone();
+    let page_id = app.selected_wiki_page_id.clone();
+
+    let update_wiki_post_fut = update_wiki_post(session, &page_id, &content);
+    match app.rt.block_on(update_wiki_post_fut) {
+        Ok(_) => {
+            log::info!("Updated wiki post: {}", page_id);
+            // Update the selected content to reflect changes
+            app.selected_wiki_content = content;
+            app.discard_draft(session);
+        }
+        Err(e) => {
+            log::error!("Failed to update wiki post: {e}");
+            app.keep_draft(session);
+        }
+    }
+
+    app.edit_wiki_content.clear();
+    app.edit_conflict = None;
+    app.view_state = ViewState::WikiList;
+    app.needs_refresh = true;
+}
+
+/// Let the user choose how to resolve a page changed elsewhere since it was loaded
+fn show_conflict_window(app: &mut PubkyApp, session: &PubkySession, ctx: &Context) {
+    let (remote_content, diff) = match &app.edit_conflict {
+        None => return,
+        Some(EditConflict::CheckFailed(error)) => {
+            show_check_failed_window(app, session, &error.clone(), ctx);
+            return;
+        }
+        Some(EditConflict::Changed { remote_content, diff }) => (remote_content.clone(), diff.clone()),
+    };
+
+    egui::Window::new("Edit Conflict")
+        .collapsible(false)
+        .resizable(true)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label("This page was changed elsewhere since you started editing it.");
+            ui.add_space(10.0);
+
+            ui.label(egui::RichText::new("Changes from the current version to yours:").strong());
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for (text, color) in diff {
+                        ui.label(egui::RichText::new(text).monospace().color(color));
+                    }
+                });
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Merge")
+                    .on_hover_text("Combine both changes, marking the ones that conflict")
+                    .clicked()
+                {
+                    merge_remote_changes(app, &remote_content);
+                }
+                if ui
+                    .button("Overwrite")
+                    .on_hover_text("Replace the current version with yours")
+                    .clicked()
+                {
+                    save(app, session);
+                }
+                if ui
+                    .button("Use Theirs")
+                    .on_hover_text("Discard your changes and edit the current version")
+                    .clicked()
+                {
+                    app.edit_wiki_content = remote_content.clone();
+                    app.edit_base_content = remote_content.clone();
+                    app.edit_conflict = None;
+                }
+                if ui.button("Keep Editing").clicked() {
+                    app.edit_conflict = None;
+                }
+            });
+        });
+}
+
+/// Let the user retry the check for changes made elsewhere, or save without it
+fn show_check_failed_window(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    error: &str,
+    ctx: &Context,
+) {
+    egui::Window::new("Could Not Check for Changes")
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label("The current version of the page could not be fetched, so changes made elsewhere since you started editing it can't be detected:");
+            ui.colored_label(egui::Color32::RED, error);
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                if ui.button("Retry").clicked() {
+                    app.edit_conflict = None;
+                    check_and_save(app, session);
+                }
+                if ui
+                    .button("Save Anyway")
+                    .on_hover_text("Replace the current version with yours, whatever it is")
+                    .clicked()
+                {
+                    save(app, session);
+                }
+                if ui.button("Keep Editing").clicked() {
+                    app.edit_conflict = None;
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    fn editing(base_content: &str, content: &str) -> PubkyApp {
+        let mut app = PubkyApp::for_tests();
+        app.selected_wiki_page_id = "id-1".into();
+        app.edit_base_content = base_content.into();
+        app.edit_wiki_content = content.into();
+        app
+    }
+
+    #[test]
+    fn merges_changes_made_elsewhere() {
+        let mut app = editing("# Lugano\n\nLake\n\nHills\n", "# Lugano\n\nLake Lugano\n\nHills\n");
+
+        assert!(!check_remote_changes(&mut app, Ok("# Lugano\n\nLake\n\nMonte Brè\n".into())));
+        let Some(EditConflict::Changed { remote_content, .. }) = app.edit_conflict.take() else {
+            panic!("The change made elsewhere was not detected");
+        };
+
+        merge_remote_changes(&mut app, &remote_content);
+        assert_eq!(app.edit_wiki_content, "# Lugano\n\nLake Lugano\n\nMonte Brè\n");
+        assert_eq!(app.edit_base_content, remote_content);
+        assert!(app.edit_conflict.is_none());
+    }
+
+    #[test]
+    fn saves_unless_the_check_fails() {
+        let mut app = editing("# Lugano\n", "# Lugano\n\nLake\n");
+        assert!(check_remote_changes(&mut app, Ok("# Lugano\n".into())));
+        assert!(check_remote_changes(&mut app, Ok("# Lugano\n\nLake\n".into())));
+        assert!(app.edit_conflict.is_none());
+
+        assert!(!check_remote_changes(&mut app, Err(anyhow!("offline"))));
+        assert!(matches!(app.edit_conflict, Some(EditConflict::CheckFailed(_))));
+    }
 }
@@ -0,0 +1,545 @@
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use crate::{
+    highlight,
+    links::{extract_links, find_anchor, find_pages_by_title, LinkTarget, WikiLink},
+    view_wiki::{follow_link, show_markdown, show_pending_link, show_title_link_picker, take_clicked_urls},
+    PubkyApp, ViewState,
+};
+
+use eframe::egui::{Context, Ui};
+use egui::{
+    scroll_area::ScrollAreaOutput,
+    text::{CCursor, CCursorRange, Galley},
+    CollapsingHeader, Id, Key, KeyboardShortcut, Modifiers, TextBuffer,
+};
+use pubky::{PubkySession, PublicStorage};
+
+const TEXT_EDIT_ID: &str = "editor_text_edit";
+
+/// Maximum number of pages listed in the link picker
+const MAX_LINK_CANDIDATES: usize = 20;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum EditorMode {
+    Edit,
+    Split,
+    Preview,
+}
+
+/// Formatting applied to the selected text, from the toolbar or a shortcut
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Bold,
+    Italic,
+    Heading(usize),
+    BulletList,
+    NumberedList,
+    CodeBlock,
+    Table,
+}
+
+impl Format {
+    const SHORTCUTS: [(KeyboardShortcut, Format); 5] = [
+        (KeyboardShortcut::new(Modifiers::COMMAND, Key::B), Format::Bold),
+        (KeyboardShortcut::new(Modifiers::COMMAND, Key::I), Format::Italic),
+        (KeyboardShortcut::new(Modifiers::COMMAND, Key::Num1), Format::Heading(1)),
+        (KeyboardShortcut::new(Modifiers::COMMAND, Key::Num2), Format::Heading(2)),
+        (KeyboardShortcut::new(Modifiers::COMMAND, Key::Num3), Format::Heading(3)),
+    ];
+
+    const INSERT_LINK_SHORTCUT: KeyboardShortcut =
+        KeyboardShortcut::new(Modifiers::COMMAND, Key::K);
+
+    /// Apply the format to the `selection` (in chars) of `text`, returning the new selection
+    fn apply(self, text: &mut String, selection: Range<usize>) -> Range<usize> {
+        let start = byte_index(text, selection.start);
+        let end = byte_index(text, selection.end);
+        let selected = text[start..end].to_string();
+        let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+
+        let (range, replacement, new_selection) = match self {
+            Format::Bold | Format::Italic => {
+                let delimiter = if self == Format::Bold { "**" } else { "*" };
+                let offset = delimiter.len();
+                (
+                    start..end,
+                    format!("{delimiter}{selected}{delimiter}"),
+                    selection.start + offset..selection.end + offset,
+                )
+            }
+            Format::Heading(level) => {
+                let line_end = text[line_start..].find('\n').map_or(text.len(), |i| line_start + i);
+                let line = &text[line_start..line_end];
+                let title = line.trim_start_matches('#').trim_start();
+                let heading = format!("{} {title}", "#".repeat(level));
+                let cursor = char_index(text, line_start) + heading.chars().count();
+                (line_start..line_end, heading, cursor..cursor)
+            }
+            Format::BulletList | Format::NumberedList => {
+                let end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+                let lines = text[line_start..end].to_string();
+                let list: Vec<String> = lines
+                    .split('\n')
+                    .enumerate()
+                    .map(|(i, line)| match self {
+                        Format::BulletList => format!("- {line}"),
+                        _ => format!("{}. {line}", i + 1),
+                    })
+                    .collect();
+                let list = list.join("\n");
+                let cursor = char_index(text, line_start) + list.chars().count();
+                (line_start..end, list, cursor..cursor)
+            }
+            Format::CodeBlock => {
+                let block = format!("```\n{selected}\n```");
+                let cursor = selection.start + 4;
+                (start..end, block, cursor..cursor + selected.chars().count())
+            }
+            Format::Table => {
+                let table = "\n| Column | Column |\n| --- | --- |\n| | |\n".to_string();
+                let cursor = selection.end + table.chars().count();
+                (end..end, table, cursor..cursor)
+            }
+        };
+
+        text.replace_range(range, &replacement);
+        new_selection
+    }
+}
+
+fn byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices()
+        .nth(char_index)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+fn char_index(text: &str, byte_index: usize) -> usize {
+    text[..byte_index].chars().count()
+}
+
+/// Markdown editor for `app.edit_wiki_content`, with a formatting toolbar and a live preview
+pub(crate) fn show(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    file_cache: &HashMap<String, String>,
+    ui: &mut Ui,
+) {
+    let own_pk = session.info().public_key().z32();
+
+    ui.horizontal(|ui| {
+        ui.label(egui::RichText::new("Content:").size(16.0));
+        ui.add_space(20.0);
+        ui.selectable_value(&mut app.editor_mode, EditorMode::Edit, "✏ Edit");
+        ui.selectable_value(&mut app.editor_mode, EditorMode::Split, "◫ Split");
+        ui.selectable_value(&mut app.editor_mode, EditorMode::Preview, "👁 Preview");
+    });
+    ui.add_space(8.0);
+
+    if app.editor_mode != EditorMode::Preview {
+        let format = show_toolbar(app, ui).or_else(|| {
+            ui.ctx().input_mut(|i| {
+                Format::SHORTCUTS
+                    .iter()
+                    .find(|(shortcut, _)| i.consume_shortcut(shortcut))
+                    .map(|(_, format)| *format)
+            })
+        });
+        if let Some(format) = format {
+            apply_format(app, ui.ctx(), format);
+        }
+        if ui.ctx().input_mut(|i| i.consume_shortcut(&Format::INSERT_LINK_SHORTCUT)) {
+            app.link_picker_query = Some(String::new());
+        }
+    }
+    ui.add_space(8.0);
+
+    match app.editor_mode {
+        EditorMode::Edit => {
+            show_text_edit(app, ui);
+        }
+        EditorMode::Preview => {
+            show_preview(app, ui);
+        }
+        EditorMode::Split => {
+            ui.columns(2, |columns| {
+                let text_edit_output = show_text_edit(app, &mut columns[0]);
+                let preview_output = show_preview(app, &mut columns[1]);
+                sync_scroll(app, columns[0].ctx(), text_edit_output, preview_output);
+            });
+        }
+    }
+
+    if app.editor_mode != EditorMode::Edit {
+        show_links(app, &own_pk, file_cache, ui);
+    }
+
+    // Links clicked in the preview lead to the same places as in the viewer, and links without
+    // author to the user's own pages, where the edited page is saved
+    for url in take_clicked_urls(ui.ctx()) {
+        match LinkTarget::classify(&url) {
+            LinkTarget::Anchor(anchor) => app.preview_anchor = Some(anchor),
+            LinkTarget::Wiki(mut link) => {
+                link.user_pk.get_or_insert_with(|| own_pk.clone());
+                follow_link(app, LinkTarget::Wiki(link), session, pub_storage, file_cache);
+            }
+            target => follow_link(app, target, session, pub_storage, file_cache),
+        }
+    }
+
+    show_link_picker(app, session, pub_storage, file_cache, ui.ctx());
+    show_title_link_picker(app, session, pub_storage, ui.ctx());
+    show_pending_link(app, ui.ctx());
+}
+
+impl PubkyApp {
+    /// Keep the edits as a draft when a link leads out of the editor
+    pub(crate) fn leave_editor(&mut self, session: &PubkySession) {
+        if matches!(self.view_state, ViewState::CreateWiki | ViewState::EditWiki) {
+            self.keep_draft(session);
+            self.edit_wiki_content.clear();
+            self.edit_conflict = None;
+            self.forked_from_page_id = None;
+            self.announce = false;
+        }
+    }
+}
+
+/// Formatting buttons, returning the format to apply if one was clicked
+fn show_toolbar(app: &mut PubkyApp, ui: &mut Ui) -> Option<Format> {
+    let mut format = None;
+
+    ui.horizontal(|ui| {
+        let buttons = [
+            (egui::RichText::new("B").strong(), "Bold (Ctrl+B)", Format::Bold),
+            (egui::RichText::new("I").italics(), "Italic (Ctrl+I)", Format::Italic),
+            (egui::RichText::new("H1"), "Heading 1 (Ctrl+1)", Format::Heading(1)),
+            (egui::RichText::new("H2"), "Heading 2 (Ctrl+2)", Format::Heading(2)),
+            (egui::RichText::new("H3"), "Heading 3 (Ctrl+3)", Format::Heading(3)),
+            (egui::RichText::new("•"), "Bullet list", Format::BulletList),
+            (egui::RichText::new("1."), "Numbered list", Format::NumberedList),
+            (egui::RichText::new("</>").monospace(), "Code block", Format::CodeBlock),
+            (egui::RichText::new("▦"), "Table", Format::Table),
+        ];
+        for (label, hover_text, button_format) in buttons {
+            if ui.button(label).on_hover_text(hover_text).clicked() {
+                format = Some(button_format);
+            }
+        }
+
+        ui.add_space(10.0);
+        if ui.button("🔗 Wiki link").on_hover_text("Insert wiki link (Ctrl+K)").clicked() {
+            app.link_picker_query = Some(String::new());
+        }
+    });
+
+    format
+}
+
+/// Current selection of the text edit, in chars, defaulting to the end of the text
+fn selection(app: &PubkyApp, ctx: &Context) -> Range<usize> {
+    let end = app.edit_wiki_content.chars().count();
+
+    egui::TextEdit::load_state(ctx, Id::new(TEXT_EDIT_ID))
+        .and_then(|state| state.cursor.char_range())
+        .map(|range| {
+            let [start, end] = range.sorted_cursors();
+            start.index..end.index
+        })
+        .unwrap_or(end..end)
+}
+
+fn set_selection(ctx: &Context, selection: Range<usize>) {
+    let id = Id::new(TEXT_EDIT_ID);
+    let mut state = egui::TextEdit::load_state(ctx, id).unwrap_or_default();
+    state.cursor.set_char_range(Some(CCursorRange::two(
+        CCursor::new(selection.start),
+        CCursor::new(selection.end),
+    )));
+    state.store(ctx, id);
+    ctx.memory_mut(|m| m.request_focus(id));
+}
+
+fn apply_format(app: &mut PubkyApp, ctx: &Context, format: Format) {
+    let selection = selection(app, ctx);
+    let new_selection = format.apply(&mut app.edit_wiki_content, selection);
+    set_selection(ctx, new_selection);
+}
+
+/// Replace the selection with `text`, leaving the cursor after it
+fn insert_text(app: &mut PubkyApp, ctx: &Context, text: &str) {
+    let selection = selection(app, ctx);
+    let start = byte_index(&app.edit_wiki_content, selection.start);
+    let end = byte_index(&app.edit_wiki_content, selection.end);
+    app.edit_wiki_content.replace_range(start..end, text);
+
+    let cursor = selection.start + text.chars().count();
+    set_selection(ctx, cursor..cursor);
+}
+
+fn show_text_edit(app: &mut PubkyApp, ui: &mut Ui) -> ScrollAreaOutput<()> {
+    let mut layouter = |ui: &Ui, text: &dyn TextBuffer, wrap_width: f32| -> Arc<Galley> {
+        let mut layout_job = highlight::layout_job(ui, text.as_str());
+        layout_job.wrap.max_width = wrap_width;
+        ui.fonts_mut(|f| f.layout_job(layout_job))
+    };
+
+    egui::ScrollArea::vertical()
+        .id_salt("editor_text")
+        .max_height(400.0)
+        .show(ui, |ui| {
+            ui.add(
+                egui::TextEdit::multiline(&mut app.edit_wiki_content)
+                    .id(Id::new(TEXT_EDIT_ID))
+                    .desired_width(f32::INFINITY)
+                    .desired_rows(15)
+                    .font(egui::TextStyle::Monospace)
+                    .layouter(&mut layouter),
+            );
+        })
+}
+
+/// Search own and follows' pages by title, and insert a link to the chosen one
+fn show_link_picker(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    file_cache: &HashMap<String, String>,
+    ctx: &Context,
+) {
+    let Some(mut query) = app.link_picker_query.clone() else {
+        return;
+    };
+
+    let query_lowercase = query.to_lowercase();
+    let follows_file_cache = app.get_follows_file_cache(session, pub_storage, ctx);
+    let is_searching_follows = follows_file_cache.is_none();
+    let mut candidates: Vec<(String, WikiLink)> = file_cache
+        .iter()
+        .chain(follows_file_cache.into_iter().flatten())
+        .filter(|(_, title)| title.to_lowercase().contains(&query_lowercase))
+        .filter_map(|(file_url, title)| Some((title.clone(), WikiLink::parse(file_url)?)))
+        .collect();
+    candidates.sort_by(|a, b| a.0.cmp(&b.0));
+    candidates.truncate(MAX_LINK_CANDIDATES);
+
+    let own_pk = session.info().public_key().z32();
+    let mut open = true;
+    let mut inserted = None;
+    egui::Window::new("Insert Wiki Link")
+        .collapsible(false)
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.add(egui::TextEdit::singleline(&mut query).hint_text("Search by title"))
+                .request_focus();
+            ui.add_space(10.0);
+
+            if is_searching_follows {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Searching your follows' pages…");
+                });
+            } else if candidates.is_empty() {
+                ui.label(egui::RichText::new("No matching page").italics());
+            }
+            for (title, link) in candidates {
+                let user_pk = link.user_pk.clone().unwrap_or_default();
+                let author = if user_pk == own_pk {
+                    "mine".to_string()
+                } else {
+                    app.display_name(&user_pk, pub_storage, ctx)
+                };
+                let button = ui.button(format!("{title} ({author})"));
+                if button.on_hover_text(&user_pk).clicked() {
+                    inserted = Some(markdown_link(&title, &link));
+                }
+            }
+        });
+
+    match inserted {
+        Some(markdown_link) => {
+            insert_text(app, ctx, &markdown_link);
+            app.link_picker_query = None;
+        }
+        None if !open => app.link_picker_query = None,
+        None => app.link_picker_query = Some(query),
+    }
+}
+
+/// Markdown link to a page, escaping the brackets of the title that would end the link text
+fn markdown_link(title: &str, link: &WikiLink) -> String {
+    let mut text = String::new();
+    for c in title.chars() {
+        if matches!(c, '\\' | '[' | ']') {
+            text.push('\\');
+        }
+        text.push(c);
+    }
+    format!("[{text}]({link})")
+}
+
+fn show_preview(app: &mut PubkyApp, ui: &mut Ui) -> ScrollAreaOutput<()> {
+    let output = egui::ScrollArea::vertical()
+        .id_salt("editor_preview")
+        .max_height(400.0)
+        .show(ui, |ui| {
+            show_markdown(ui, &mut app.cache, &app.edit_wiki_content);
+        });
+
+    if let Some(anchor) = app.preview_anchor.take() {
+        match find_anchor(&app.edit_wiki_content, &anchor) {
+            Some(position) => {
+                let mut scroll_state = output.state;
+                scroll_state.offset.y = position * output.content_size.y;
+                scroll_state.store(ui.ctx(), output.id);
+            }
+            None => log::warn!("Section not found: {anchor}"),
+        }
+    }
+
+    output
+}
+
+/// Vertical scroll position, from 0 (top) to 1 (bottom)
+fn scroll_ratio(output: &ScrollAreaOutput<()>) -> f32 {
+    let max_offset = output.content_size.y - output.inner_rect.height();
+    if max_offset > 0.0 {
+        output.state.offset.y / max_offset
+    } else {
+        0.0
+    }
+}
+
+fn set_scroll_ratio(ctx: &Context, output: ScrollAreaOutput<()>, ratio: f32) {
+    let max_offset = (output.content_size.y - output.inner_rect.height()).max(0.0);
+    let mut scroll_state = output.state;
+    scroll_state.offset.y = ratio * max_offset;
+    scroll_state.store(ctx, output.id);
+}
+
+/// Scroll one side to the same relative position, when the other side was scrolled
+fn sync_scroll(
+    app: &mut PubkyApp,
+    ctx: &Context,
+    text_edit_output: ScrollAreaOutput<()>,
+    preview_output: ScrollAreaOutput<()>,
+) {
+    const EPSILON: f32 = 0.001;
+
+    let text_edit_ratio = scroll_ratio(&text_edit_output);
+    let preview_ratio = scroll_ratio(&preview_output);
+
+    if (text_edit_ratio - app.editor_scroll_ratio).abs() > EPSILON {
+        set_scroll_ratio(ctx, preview_output, text_edit_ratio);
+        app.editor_scroll_ratio = text_edit_ratio;
+    } else if (preview_ratio - app.editor_scroll_ratio).abs() > EPSILON {
+        set_scroll_ratio(ctx, text_edit_output, preview_ratio);
+        app.editor_scroll_ratio = preview_ratio;
+    }
+}
+
+/// List the links of the edited page and where each one leads
+fn show_links(app: &PubkyApp, own_pk: &str, file_cache: &HashMap<String, String>, ui: &mut Ui) {
+    let links = extract_links(&app.edit_wiki_content);
+    if links.is_empty() {
+        return;
+    }
+
+    ui.add_space(10.0);
+    CollapsingHeader::new(egui::RichText::new(format!("🔗 Links ({})", links.len())).size(15.0))
+        .show(ui, |ui| {
+            for url in links {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(&url).monospace());
+                    ui.label(describe_link(&url, own_pk, &app.edit_wiki_content, file_cache));
+                });
+            }
+        });
+}
+
+fn describe_link(
+    url: &str,
+    own_pk: &str,
+    content: &str,
+    file_cache: &HashMap<String, String>,
+) -> egui::RichText {
+    match LinkTarget::classify(url) {
+        LinkTarget::Wiki(link) => {
+            let user_pk = link.user_pk_or(own_pk);
+            let file_url = format!("pubky://{user_pk}/pub/wiki.app/{}", link.page_id);
+            match file_cache.get(&file_url) {
+                Some(title) => egui::RichText::new(format!("→ {title}")),
+                None if user_pk == own_pk => {
+                    egui::RichText::new("⚠ You have no page with this ID").color(egui::Color32::YELLOW)
+                }
+                None => egui::RichText::new("→ Page of another user"),
+            }
+        }
+        LinkTarget::Title(title) => match find_pages_by_title(file_cache, &title).len() {
+            0 => egui::RichText::new("? Not among your pages, follows' pages are checked on click")
+                .italics(),
+            1 => egui::RichText::new("→ One of your pages"),
+            n => egui::RichText::new(format!("⚠ {n} of your pages have this title"))
+                .color(egui::Color32::YELLOW),
+        },
+        LinkTarget::Anchor(anchor) => match find_anchor(content, &anchor) {
+            Some(_) => egui::RichText::new("→ Section of this page"),
+            None => egui::RichText::new("⚠ No such section").color(egui::Color32::YELLOW),
+        },
+        LinkTarget::External { domain, .. } => egui::RichText::new(format!("🌐 {domain}")),
+        LinkTarget::Unsupported(_) => {
+            egui::RichText::new("⚠ Can't be opened").color(egui::Color32::YELLOW)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_links_to_own_pages() {
+        let pk = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+        let file_cache = HashMap::from([(format!("pubky://{pk}/pub/wiki.app/id-1"), "Lugano".to_string())]);
+
+        let describe = |url: &str| describe_link(url, pk, "", &file_cache).text().to_string();
+        assert_eq!(describe("id-1"), "→ Lugano");
+        assert_eq!(describe(&format!("pubky://{pk}/pub/wiki.app/id-1")), "→ Lugano");
+        assert_eq!(describe("id-2"), "⚠ You have no page with this ID");
+    }
+
+    fn apply(text: &str, selection: Range<usize>, format: Format) -> (String, Range<usize>) {
+        let mut text = text.to_string();
+        let selection = format.apply(&mut text, selection);
+        (text, selection)
+    }
+
+    #[test]
+    fn wraps_selection() {
+        assert_eq!(apply("a bé c", 2..4, Format::Bold), ("a **bé** c".into(), 4..6));
+        assert_eq!(apply("a bé c", 2..4, Format::Italic), ("a *bé* c".into(), 3..5));
+    }
+
+    #[test]
+    fn replaces_heading_level() {
+        assert_eq!(
+            apply("intro\n## Title\nend", 8..8, Format::Heading(1)),
+            ("intro\n# Title\nend".into(), 13..13)
+        );
+    }
+
+    #[test]
+    fn prefixes_selected_lines() {
+        assert_eq!(
+            apply("x\none\ntwo", 3..8, Format::NumberedList),
+            ("x\n1. one\n2. two".into(), 15..15)
+        );
+    }
+
+    #[test]
+    fn escapes_brackets_in_link_titles() {
+        let link = WikiLink::parse("id-1").unwrap();
+        assert_eq!(markdown_link("Lugano [TI]", &link), "[Lugano \\[TI\\]](id-1)");
+    }
+}
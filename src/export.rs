@@ -0,0 +1,318 @@
+//! Export of a user's wiki to a folder of markdown files, for a credible exit from the homeserver.
+//!
+//! Each page is written as `<title>.md` with its metadata as YAML front-matter. Links between
+//! the exported pages become relative file links, and `manifest.json` lists all the pages.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Result;
+use eframe::egui::Context;
+use pubky::PublicStorage;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::{
+    links::{rewrite_link_urls, rewrite_title_links, slugify, WikiLink},
+    utils::{extract_title, format_rfc3339, get_content, get_page_list, now_timestamp},
+    PubkyApp,
+};
+
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ExportedPage {
+    pub(crate) page_id: String,
+    pub(crate) title: String,
+    /// File name of the page, relative to the export folder
+    pub(crate) file: String,
+    /// Pubky URL the page was exported from
+    pub(crate) source: String,
+    /// Unix timestamp (seconds) of the last change of the page, if known
+    pub(crate) updated_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    pub(crate) user_pk: String,
+    pub(crate) exported_at: String,
+    pub(crate) pages: Vec<ExportedPage>,
+}
+
+/// A page fetched from the homeserver
+pub(crate) struct Page {
+    pub(crate) page_id: String,
+    pub(crate) content: String,
+    pub(crate) updated_at: Option<u64>,
+}
+
+/// Fetch a wiki page of a user, with its last modification time
+pub(crate) fn fetch_page(pub_storage: &PublicStorage, user_pk: &str, page_id: &str, rt: Arc<Runtime>) -> Result<Page> {
+    let file_url = format!("pubky://{user_pk}/pub/wiki.app/{page_id}");
+
+    let content = get_content(pub_storage, &file_url, rt.clone())?;
+    let updated_at = rt
+        .block_on(pub_storage.stats(&file_url))
+        .ok()
+        .flatten()
+        .and_then(|stats| stats.last_modified)
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs());
+
+    Ok(Page {
+        page_id: page_id.to_string(),
+        content,
+        updated_at,
+    })
+}
+
+/// Fetch all the wiki pages of a user
+pub(crate) fn fetch_pages(
+    pub_storage: &PublicStorage,
+    user_pk: &str,
+    rt: Arc<Runtime>,
+) -> Result<Vec<Page>> {
+    let mut pages = vec![];
+    for file_url in get_page_list(pub_storage, user_pk, rt.clone())? {
+        let page_id = file_url.split('/').next_back().unwrap_or(&file_url);
+
+        match fetch_page(pub_storage, user_pk, page_id, rt.clone()) {
+            Ok(page) => pages.push(page),
+            Err(e) => log::error!("Error fetching path {file_url}: {e}"),
+        }
+    }
+    pages.sort_by(|a, b| a.page_id.cmp(&b.page_id));
+
+    Ok(pages)
+}
+
+/// File name of each page (by page ID), derived from its title and unique within the export
+fn file_names(pages: &[Page]) -> HashMap<String, String> {
+    let mut used = HashSet::new();
+    let mut result = HashMap::new();
+
+    for page in pages {
+        let slug = slugify(extract_title(&page.content));
+        let short_id: String = page.page_id.chars().take(8).collect();
+        let candidates = if slug.is_empty() {
+            vec![page.page_id.clone()]
+        } else {
+            vec![slug.clone(), format!("{slug}-{short_id}"), page.page_id.clone()]
+        };
+
+        // A page ID can be taken by the title of another page, then a number is added to it
+        let numbered = (2..).map(|n| format!("{}-{n}", page.page_id));
+        let name = candidates
+            .into_iter()
+            .chain(numbered)
+            .find(|name| !used.contains(name))
+            .unwrap_or_default();
+        used.insert(name.clone());
+        result.insert(page.page_id.clone(), format!("{name}.md"));
+    }
+
+    result
+}
+
+/// Rewrite the links of a page into links to the exported files, or to absolute pubky URLs
+/// for pages that are not part of the export
+fn rewrite_links(
+    content: &str,
+    user_pk: &str,
+    files: &HashMap<String, String>,
+    files_by_title: &HashMap<String, String>,
+) -> String {
+    let content = rewrite_link_urls(content, |url| {
+        let link = WikiLink::parse(url)?;
+        let pk = link.user_pk_or(user_pk);
+        let anchor = link.anchor.as_ref().map(|a| format!("#{a}")).unwrap_or_default();
+
+        match files.get(&link.page_id) {
+            Some(file) if pk == user_pk => Some(format!("{file}{anchor}")),
+            _ => Some(format!("pubky://{pk}/pub/wiki.app/{}{anchor}", link.page_id)),
+        }
+    });
+
+    rewrite_title_links(&content, |title, label| {
+        files_by_title
+            .get(&title.to_lowercase())
+            .map(|file| format!("[{label}]({file})"))
+    })
+}
+
+fn front_matter(page: &ExportedPage, user_pk: &str) -> String {
+    // JSON strings are valid YAML double-quoted strings
+    let title = serde_json::to_string(&page.title).unwrap_or_default();
+
+    let mut result = format!(
+        "---\ntitle: {title}\npage_id: {}\nauthor: {user_pk}\nsource: {}\n",
+        page.page_id, page.source
+    );
+    if let Some(updated_at) = page.updated_at {
+        result.push_str(&format!("updated: {}\n", format_rfc3339(updated_at)));
+    }
+    result.push_str("---\n\n");
+    result
+}
+
+/// Write all the wiki pages of a user to `dir`, returning the manifest of the export
+pub(crate) fn export_wiki(
+    pub_storage: &PublicStorage,
+    user_pk: &str,
+    dir: &Path,
+    rt: Arc<Runtime>,
+) -> Result<Manifest> {
+    fs::create_dir_all(dir)?;
+
+    let pages = fetch_pages(pub_storage, user_pk, rt)?;
+    let files = file_names(&pages);
+    let files_by_title: HashMap<String, String> = pages
+        .iter()
+        .map(|page| (extract_title(&page.content).trim().to_lowercase(), files[&page.page_id].clone()))
+        .collect();
+
+    let mut manifest = Manifest {
+        user_pk: user_pk.to_string(),
+        exported_at: format_rfc3339(now_timestamp()),
+        pages: vec![],
+    };
+
+    for page in &pages {
+        let exported_page = ExportedPage {
+            page_id: page.page_id.clone(),
+            title: extract_title(&page.content).trim().to_string(),
+            file: files[&page.page_id].clone(),
+            source: format!("pubky://{user_pk}/pub/wiki.app/{}", page.page_id),
+            updated_at: page.updated_at,
+        };
+
+        let content = rewrite_links(&page.content, user_pk, &files, &files_by_title);
+        fs::write(
+            dir.join(&exported_page.file),
+            front_matter(&exported_page, user_pk) + &content,
+        )?;
+
+        manifest.pages.push(exported_page);
+    }
+
+    fs::write(dir.join(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?)?;
+
+    log::info!("Exported {} pages to {}", manifest.pages.len(), dir.display());
+
+    Ok(manifest)
+}
+
+/// Default folder for exports, in the user's documents
+pub(crate) fn default_export_dir(user_pk: &str) -> PathBuf {
+    dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+        .join("pubky-wiki-export")
+        .join(user_pk)
+}
+
+/// Ask where to export the user's wiki, then export it
+pub(crate) fn show_export_window(
+    app: &mut PubkyApp,
+    user_pk: &str,
+    pub_storage: &PublicStorage,
+    ctx: &Context,
+) {
+    let Some(mut export_dir) = app.export_dir.clone() else {
+        return;
+    };
+    let mut is_open = true;
+
+    egui::Window::new("Export Wiki")
+        .collapsible(false)
+        .resizable(false)
+        .open(&mut is_open)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label("Write all your pages as markdown files to:");
+            ui.text_edit_singleline(&mut export_dir);
+            ui.add_space(10.0);
+
+            if ui.button("📦 Export").clicked() {
+                let status = match export_wiki(pub_storage, user_pk, Path::new(&export_dir), app.rt.clone()) {
+                    Ok(manifest) => format!("Exported {} pages", manifest.pages.len()),
+                    Err(e) => {
+                        log::error!("Failed to export wiki: {e}");
+                        format!("Export failed: {e}")
+                    }
+                };
+                app.export_status = Some(status);
+            }
+
+            if let Some(status) = &app.export_status {
+                ui.add_space(5.0);
+                ui.label(egui::RichText::new(status).italics());
+            }
+        });
+
+    app.export_dir = is_open.then_some(export_dir);
+    if !is_open {
+        app.export_status = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PK: &str = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+
+    fn page(page_id: &str, content: &str) -> Page {
+        Page {
+            page_id: page_id.into(),
+            content: content.into(),
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn names_files_after_titles() {
+        let pages = [
+            page("id-1", "# Lugano\n"),
+            page("id-2", "# Lugano\n"),
+            page("id-3", "\n"),
+        ];
+        let files = file_names(&pages);
+        assert_eq!(files["id-1"], "lugano.md");
+        assert_eq!(files["id-2"], "lugano-id-2.md");
+        assert_eq!(files["id-3"], "id-3.md");
+    }
+
+    #[test]
+    fn names_files_uniquely_when_ids_and_titles_collide() {
+        let pages = [
+            page("id-1", "# Lugano\n"),
+            page("lugano", "\n"),
+            page("id-2", "# Lugano 2\n"),
+            page("lugano-2", "\n"),
+        ];
+        let files = file_names(&pages);
+        assert_eq!(files["id-1"], "lugano.md");
+        assert_eq!(files["lugano"], "lugano-2.md");
+        assert_eq!(files["id-2"], "lugano-2-id-2.md");
+        assert_eq!(files["lugano-2"], "lugano-2-2.md");
+        assert_eq!(files.values().collect::<HashSet<_>>().len(), pages.len());
+    }
+
+    #[test]
+    fn rewrites_links_to_files() {
+        let files = HashMap::from([("id-1".to_string(), "lugano.md".to_string())]);
+        let files_by_title = HashMap::from([("lugano".to_string(), "lugano.md".to_string())]);
+
+        let content = format!("[a](id-1#history) [b]({PK}/id-1) [c](id-2) [[Lugano|d]] [[Zurich]] [e](https://x.org)");
+        assert_eq!(
+            rewrite_links(&content, PK, &files, &files_by_title),
+            format!("[a](lugano.md#history) [b](lugano.md) [c](pubky://{PK}/pub/wiki.app/id-2) [d](lugano.md) [[Zurich]] [e](https://x.org)")
+        );
+    }
+}
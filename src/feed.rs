@@ -0,0 +1,208 @@
+//! Atom feed of the changes to a user's wiki, for feed readers to follow it.
+//!
+//! Each entry is a revision of a page, dated by the revision time: the first recorded revision
+//! of a page is its creation, and the later ones are updates, as are the oldest revisions shown
+//! when older ones are left out. Pages saved before revisions were
+//! recorded only have their latest version, dated by the last modification reported by the
+//! homeserver, which can't tell whether it was created or updated.
+
+use pubky::PublicKey;
+
+use crate::{
+    links::LinkTarget,
+    revisions::{revision_url, PageHistory, Revision},
+    site::{escape_html, render_html},
+    utils::{extract_title, format_rfc3339},
+};
+
+/// Maximum number of entries in a feed
+pub(crate) const FEED_SIZE: usize = 50;
+
+/// What a revision did to its page
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    Created,
+    Updated,
+    Unknown,
+}
+
+/// Latest revisions of the pages, most recent first, with what they changed
+fn latest_changes(pages: &[PageHistory]) -> Vec<(&PageHistory, &Revision, Change)> {
+    let mut changes: Vec<(&PageHistory, &Revision, Change)> = pages
+        .iter()
+        .flat_map(|page| {
+            page.revisions.iter().enumerate().map(move |(i, revision)| {
+                let change = match (i, &revision.id) {
+                    (0, Some(_)) if page.is_complete => Change::Created,
+                    (0, None) => Change::Unknown,
+                    _ => Change::Updated,
+                };
+                (page, revision, change)
+            })
+        })
+        .collect();
+    changes.sort_by_key(|(_, revision, _)| std::cmp::Reverse(revision.created_at));
+    changes.truncate(FEED_SIZE);
+    changes
+}
+
+/// Number of entries in the feed of the given pages
+pub(crate) fn feed_size(pages: &[PageHistory]) -> usize {
+    latest_changes(pages).len()
+}
+
+/// Atom feed of the changes to the pages of `user_pk`, most recent first.
+///
+/// `page_url` gives the link of each page by its ID, and `rewrite_url` the target of the
+/// links within the pages.
+pub(crate) fn atom_feed(
+    user_pk: &PublicKey,
+    pages: &[PageHistory],
+    feed_url: Option<&str>,
+    page_url: impl Fn(&str) -> String,
+    mut rewrite_url: impl FnMut(&str) -> String,
+) -> String {
+    let pk = user_pk.z32();
+    let changes = latest_changes(pages);
+    let updated = changes
+        .first()
+        .and_then(|(_, revision, _)| revision.created_at)
+        .unwrap_or_default();
+
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\">
+<id>pubky://{pk}/pub/wiki.app/</id>
+<title>Wiki of {pk}</title>
+<updated>{}</updated>
+<author><name>{pk}</name></author>
+",
+        format_rfc3339(updated)
+    );
+    if let Some(feed_url) = feed_url {
+        feed.push_str(&format!("<link rel=\"self\" href=\"{}\" />\n", escape_html(feed_url)));
+    }
+
+    for (page, revision, change) in changes {
+        let page_id = page.page_id.as_str();
+        let title = match extract_title(&revision.content).trim() {
+            "" => page_id,
+            title => title,
+        };
+        let (title, category) = match change {
+            Change::Created => (format!("New page: {title}"), "<category term=\"created\" />\n"),
+            Change::Updated => (format!("Updated: {title}"), "<category term=\"updated\" />\n"),
+            Change::Unknown => (title.to_string(), ""),
+        };
+        let id = match &revision.id {
+            Some(revision_id) => revision_url(user_pk, page_id, revision_id),
+            None => format!("pubky://{pk}/pub/wiki.app/{page_id}"),
+        };
+        let content = render_html(&revision.content, &mut rewrite_url);
+
+        feed.push_str(&format!(
+            "<entry>
+<id>{}</id>
+<title>{}</title>
+<updated>{}</updated>
+{category}<link href=\"{}\" />
+<content type=\"html\">{}</content>
+</entry>
+",
+            escape_html(&id),
+            escape_html(&title),
+            format_rfc3339(revision.created_at.unwrap_or_default()),
+            escape_html(&page_url(page_id)),
+            escape_html(&content)
+        ));
+    }
+
+    feed.push_str("</feed>\n");
+    feed
+}
+
+/// Target of a link in a page of `author` as an absolute pubky URL, for readers outside the app
+pub(crate) fn pubky_href(url: &str, author: &str, pages: &[PageHistory]) -> String {
+    match LinkTarget::classify(url) {
+        LinkTarget::Wiki(link) => {
+            let anchor = link.anchor.as_ref().map(|a| format!("#{a}")).unwrap_or_default();
+            format!("pubky://{}/pub/wiki.app/{}{anchor}", link.user_pk_or(author), link.page_id)
+        }
+        LinkTarget::Title(title) => pages
+            .iter()
+            .find(|page| extract_title(page.content()).trim().eq_ignore_ascii_case(title.trim()))
+            .map(|page| format!("pubky://{author}/pub/wiki.app/{}", page.page_id))
+            .unwrap_or_else(|| "#".into()),
+        _ => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PK: &str = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+
+    fn revision(id: Option<&str>, content: &str, created_at: u64) -> Revision {
+        Revision {
+            id: id.map(str::to_string),
+            content: content.into(),
+            created_at: Some(created_at),
+        }
+    }
+
+    fn page(page_id: &str, revisions: Vec<Revision>) -> PageHistory {
+        PageHistory {
+            page_id: page_id.into(),
+            revisions,
+            is_complete: true,
+        }
+    }
+
+    #[test]
+    fn lists_recent_changes_first() {
+        let pages = [
+            page("id-1", vec![revision(None, "# Lugano\n\nSee [[Ticino]]", 1_700_000_000)]),
+            page(
+                "id-2",
+                vec![
+                    revision(Some("r1"), "# Ticino", 1_750_000_000),
+                    revision(Some("r2"), "# Ticino\n\nBellinzona & Locarno", 1_760_000_000),
+                ],
+            ),
+        ];
+        let feed = atom_feed(
+            &PK.parse().unwrap(),
+            &pages,
+            Some("http://localhost:8080/feed.atom"),
+            |page_id| format!("http://localhost:8080/{PK}/{page_id}"),
+            |url| pubky_href(url, PK, &pages),
+        );
+
+        assert_eq!(feed_size(&pages), 3);
+        assert!(feed.contains("<updated>2025-10-09T08:53:20Z</updated>\n<author>"));
+        assert!(feed.contains("<link rel=\"self\" href=\"http://localhost:8080/feed.atom\" />"));
+        let updated = feed.find("<title>Updated: Ticino</title>").unwrap();
+        let created = feed.find("<title>New page: Ticino</title>").unwrap();
+        let lugano = feed.find("<title>Lugano</title>").unwrap();
+        assert!(updated < created && created < lugano);
+        assert!(feed.contains(&format!("<id>pubky://{PK}/pub/wiki.app/.revisions/id-2/r1</id>")));
+        assert!(feed.contains(&format!("<id>pubky://{PK}/pub/wiki.app/id-1</id>")));
+        assert!(feed.contains("<category term=\"created\" />"));
+        assert!(feed.contains("Bellinzona &amp;amp; Locarno"));
+        assert!(feed.contains(&format!(
+            "&lt;a href=&quot;pubky://{PK}/pub/wiki.app/id-2&quot;&gt;Ticino&lt;/a&gt;"
+        )));
+        assert!(feed.ends_with("</entry>\n</feed>\n"));
+    }
+
+    #[test]
+    fn only_marks_the_first_recorded_revision_as_created() {
+        let mut truncated = page("id-1", vec![revision(Some("r2"), "# Lugano", 1_760_000_000)]);
+        truncated.is_complete = false;
+        let feed = atom_feed(&PK.parse().unwrap(), &[truncated], None, |page_id| page_id.to_string(), |url| url.to_string());
+
+        assert!(feed.contains("<title>Updated: Lugano</title>"));
+        assert!(!feed.contains("New page"));
+    }
+}
@@ -0,0 +1,104 @@
+//! Following and unfollowing users from the wiki, with the follow records of pubky.app, so
+//! the follows are shared with the other Pubky apps and drive fork discovery.
+
+use anyhow::{anyhow, Result};
+use eframe::egui::Ui;
+use pubky::PubkySession;
+use pubky_timestamp::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::{capabilities::Feature, user_wiki::parse_user_pk, PubkyApp};
+
+/// Follow record of the pubky.app data model, stored at `/pub/pubky.app/follows/<pk>`
+#[derive(Serialize, Deserialize)]
+struct Follow {
+    /// Unix timestamp in microseconds
+    created_at: i64,
+}
+
+fn follow_path(user_pk: &str) -> Result<String> {
+    let user_pk = parse_user_pk(user_pk).ok_or(anyhow!("Invalid public key {user_pk}"))?;
+    Ok(format!("/pub/pubky.app/follows/{}", user_pk.z32()))
+}
+
+pub(crate) async fn follow(session: &PubkySession, user_pk: &str) -> Result<()> {
+    let follow = Follow {
+        created_at: Timestamp::now().as_u64() as i64,
+    };
+    session.storage().put(follow_path(user_pk)?, serde_json::to_string(&follow)?).await?;
+
+    log::info!("Followed {user_pk}");
+    Ok(())
+}
+
+pub(crate) async fn unfollow(session: &PubkySession, user_pk: &str) -> Result<()> {
+    session.storage().delete(follow_path(user_pk)?).await?;
+
+    log::info!("Unfollowed {user_pk}");
+    Ok(())
+}
+
+impl PubkyApp {
+    /// Whether the user follows `user_pk`, from the follows fetched on first use
+    pub(crate) fn is_following(&mut self, session: &PubkySession, user_pk: &str) -> bool {
+        let Some(user_pk) = parse_user_pk(user_pk) else {
+            return false;
+        };
+        if self.follows.is_none() {
+            self.follows = Some(self.get_my_follows(session));
+        }
+        self.follows.iter().flatten().any(|follow_pk| *follow_pk == user_pk.z32())
+    }
+
+    /// Follow or unfollow a user, asking for the follows capability first if needed
+    pub(crate) fn toggle_follow(&mut self, session: &PubkySession, user_pk: &str) {
+        if !self.ensure_capabilities(session, Feature::Follows) {
+            return;
+        }
+
+        let result = match self.is_following(session, user_pk) {
+            true => self.rt.block_on(unfollow(session, user_pk)),
+            false => self.rt.block_on(follow(session, user_pk)),
+        };
+        match result {
+            Ok(()) => {
+                // Fetched again on next use, along with the titles and tags of the follows' pages
+                self.follows = None;
+                self.follows_file_cache = None;
+                self.tags = None;
+            }
+            Err(e) => log::error!("Failed to update follow of {user_pk}: {e}"),
+        }
+    }
+}
+
+/// Follow or unfollow button for a user other than the signed in one
+pub(crate) fn show_follow_button(app: &mut PubkyApp, session: &PubkySession, user_pk: &str, ui: &mut Ui) {
+    let is_own = parse_user_pk(user_pk).is_some_and(|pk| &pk == session.info().public_key());
+    if is_own {
+        return;
+    }
+
+    let label = match app.is_following(session, user_pk) {
+        true => "➖ Unfollow",
+        false => "➕ Follow",
+    };
+    if ui.small_button(label).clicked() {
+        app.toggle_follow(session, user_pk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_pubky_app_follows() {
+        let pk = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+        assert_eq!(follow_path(&format!("pubky{pk}")).unwrap(), format!("/pub/pubky.app/follows/{pk}"));
+        assert!(follow_path("not a key").is_err());
+
+        let json = serde_json::to_string(&Follow { created_at: 1_760_000_000_000_000 }).unwrap();
+        assert_eq!(json, r#"{"created_at":1760000000000000}"#);
+    }
+}
@@ -0,0 +1,533 @@
+//! Local HTTP gateway, serving the wiki pages of any user to web browsers:
+//!
+//! - `/<pk>/` lists the pages of a user
+//! - `/<pk>/<page id>` renders a page as HTML, with the same markdown dialect as the app
+//! - `/<pk>/<page id>.md` serves the raw markdown of a page
+//! - `/<pk>/feed.atom` is an Atom feed of the pages a user recently created or updated
+//!
+//! Pages, with their last modification time, and lists fetched from the homeservers are cached
+//! for a minute.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use http_body_util::Full;
+use hyper::{
+    body::{Bytes, Incoming},
+    header,
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use percent_encoding::percent_decode_str;
+use pubky::{PublicKey, PublicStorage, ResourceStats};
+use tokio::net::TcpListener;
+
+use crate::{
+    feed::{atom_feed, FEED_SIZE},
+    links::{extract_links, is_valid_page_id, slugify, LinkTarget, TITLE_LINK_SCHEME},
+    revisions::{
+        complete_history, list_revisions, revision_time, revision_url, PageHistory, Revision,
+    },
+    site::{escape_html, html_document, render_html},
+    utils::{extract_title, list_pages},
+};
+
+pub(crate) const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+/// How long fetched pages and lists are served from the cache
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    Home,
+    UserPages(String),
+    Page { user_pk: String, page_id: String },
+    Markdown { user_pk: String, page_id: String },
+    Feed(String),
+    NotFound,
+}
+
+fn route(path: &str) -> Route {
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    let (user_pk, page) = match segments.as_slice() {
+        [""] => return Route::Home,
+        [user_pk] | [user_pk, ""] => (*user_pk, None),
+        [user_pk, page] => (*user_pk, Some(*page)),
+        _ => return Route::NotFound,
+    };
+    // Keys given with the `pubky` prefix are served under the bare key, as stored
+    let Ok(user_pk) = user_pk.parse::<PublicKey>() else {
+        return Route::NotFound;
+    };
+
+    let user_pk = user_pk.z32();
+    match page {
+        None => Route::UserPages(user_pk),
+        Some("feed.atom") => Route::Feed(user_pk),
+        Some(page) => match page.strip_suffix(".md") {
+            Some(page_id) if is_valid_page_id(page_id) => Route::Markdown {
+                user_pk,
+                page_id: page_id.to_string(),
+            },
+            None if is_valid_page_id(page) => Route::Page {
+                user_pk,
+                page_id: page.to_string(),
+            },
+            _ => Route::NotFound,
+        },
+    }
+}
+
+/// Target of a link in a page of `author`, within the gateway
+fn href(url: &str, author: &str, titles: &[(String, String)]) -> String {
+    match LinkTarget::classify(url) {
+        LinkTarget::Wiki(link) => {
+            let anchor = link
+                .anchor
+                .as_ref()
+                .map(|a| format!("#{}", slugify(a)))
+                .unwrap_or_default();
+            format!("/{}/{}{anchor}", link.user_pk_or(author), link.page_id)
+        }
+        LinkTarget::Title(title) => titles
+            .iter()
+            .find(|(_, page_title)| page_title.eq_ignore_ascii_case(title.trim()))
+            .map(|(page_id, _)| format!("/{author}/{page_id}"))
+            .unwrap_or_else(|| "#".into()),
+        LinkTarget::Anchor(anchor) => format!("#{}", slugify(&anchor)),
+        _ => url.to_string(),
+    }
+}
+
+fn response(
+    status: StatusCode,
+    content_type: &str,
+    body: impl Into<Bytes>,
+) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Full::new(body.into()))
+        .unwrap_or_default()
+}
+
+fn html_response(
+    status: StatusCode,
+    title: &str,
+    index_url: &str,
+    body: &str,
+) -> Response<Full<Bytes>> {
+    response(
+        status,
+        "text/html; charset=utf-8",
+        html_document(title, index_url, body),
+    )
+}
+
+fn not_found(message: &str) -> Response<Full<Bytes>> {
+    let body = format!("<h1>Not found</h1>\n<p>{}</p>\n", escape_html(message));
+    html_response(StatusCode::NOT_FOUND, "Not found", "/", &body)
+}
+
+/// Values fetched from the homeservers, with the time they were fetched, by key
+type Cache<T> = Mutex<HashMap<String, (Instant, T)>>;
+
+fn cached<T: Clone>(cache: &Cache<T>, key: &str) -> Option<T> {
+    let cache = cache.lock().unwrap();
+    let (fetched_at, value) = cache.get(key)?;
+    (fetched_at.elapsed() < CACHE_TTL).then(|| value.clone())
+}
+
+/// Cache a value, dropping the expired ones so that the cache doesn't grow without bound
+fn store<T>(cache: &Cache<T>, key: &str, value: T) {
+    let mut cache = cache.lock().unwrap();
+    cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < CACHE_TTL);
+    cache.insert(key.to_string(), (Instant::now(), value));
+}
+
+struct Gateway {
+    pub_storage: PublicStorage,
+    /// Content of the fetched files with their last modification time, by URL
+    contents: Cache<(String, Option<u64>)>,
+    /// Page URLs of each user
+    lists: Cache<Vec<String>>,
+    /// Revision IDs of each page, by page URL
+    revisions: Cache<Vec<String>>,
+}
+
+impl Gateway {
+    /// Content of a file and its last modification time, from a single request
+    async fn fetch(&self, file_url: &str) -> Result<(String, Option<u64>)> {
+        if let Some(file) = cached(&self.contents, file_url) {
+            return Ok(file);
+        }
+
+        let response = self.pub_storage.get(file_url).await?;
+        let updated_at = ResourceStats::from_headers(response.headers())
+            .last_modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+        let file = (response.text().await?, updated_at);
+        store(&self.contents, file_url, file.clone());
+        Ok(file)
+    }
+
+    async fn content(&self, file_url: &str) -> Result<String> {
+        Ok(self.fetch(file_url).await?.0)
+    }
+
+    async fn revision_ids(&self, user_pk: &PublicKey, page_id: &str) -> Result<Vec<String>> {
+        let page_url = format!("pubky://{}/pub/wiki.app/{page_id}", user_pk.z32());
+        if let Some(revision_ids) = cached(&self.revisions, &page_url) {
+            return Ok(revision_ids);
+        }
+
+        let revision_ids = list_revisions(&self.pub_storage, user_pk, page_id).await?;
+        store(&self.revisions, &page_url, revision_ids.clone());
+        Ok(revision_ids)
+    }
+
+    async fn page_urls(&self, user_pk: &str) -> Result<Vec<String>> {
+        if let Some(urls) = cached(&self.lists, user_pk) {
+            return Ok(urls);
+        }
+
+        let folder_url = format!("pubky://{user_pk}/pub/wiki.app/");
+        let urls = list_pages(|| self.pub_storage.list(&folder_url)).await?;
+        store(&self.lists, user_pk, urls.clone());
+        Ok(urls)
+    }
+
+    /// Page ID and title of the pages of a user, sorted by title
+    async fn titles(&self, user_pk: &str) -> Result<Vec<(String, String)>> {
+        let mut result = vec![];
+        for file_url in self.page_urls(user_pk).await? {
+            let page_id = file_url
+                .split('/')
+                .next_back()
+                .unwrap_or(&file_url)
+                .to_string();
+            match self.content(&file_url).await {
+                Ok(content) => result.push((page_id, extract_title(&content).trim().to_string())),
+                Err(e) => log::error!("Error fetching path {file_url}: {e}"),
+            }
+        }
+        result.sort_by_key(|(_, title)| title.to_lowercase());
+        Ok(result)
+    }
+
+    /// Latest revisions of the pages of a user, for the feed
+    async fn histories(&self, user_pk: &PublicKey) -> Result<Vec<PageHistory>> {
+        let mut pages = vec![];
+        for file_url in self.page_urls(&user_pk.z32()).await? {
+            let page_id = file_url
+                .split('/')
+                .next_back()
+                .unwrap_or(&file_url)
+                .to_string();
+            let (content, updated_at) = match self.fetch(&file_url).await {
+                Ok(file) => file,
+                Err(e) => {
+                    log::error!("Error fetching path {file_url}: {e}");
+                    continue;
+                }
+            };
+
+            let mut revision_ids = self
+                .revision_ids(user_pk, &page_id)
+                .await
+                .inspect_err(|e| log::error!("Failed to list the revisions of {file_url}: {e}"))
+                .unwrap_or_default();
+            let first_id = revision_ids.first().cloned();
+            revision_ids.drain(..revision_ids.len().saturating_sub(FEED_SIZE));
+
+            let mut revisions = vec![];
+            for revision_id in revision_ids {
+                let url = revision_url(user_pk, &page_id, &revision_id);
+                match self.content(&url).await {
+                    Ok(content) => revisions.push(Revision {
+                        created_at: revision_time(&revision_id),
+                        id: Some(revision_id),
+                        content,
+                    }),
+                    Err(e) => log::error!("Error fetching path {url}: {e}"),
+                }
+            }
+            let is_complete =
+                revisions.first().and_then(|revision| revision.id.as_ref()) == first_id.as_ref();
+            complete_history(&mut revisions, content, updated_at);
+
+            pages.push(PageHistory {
+                page_id,
+                revisions,
+                is_complete,
+            });
+        }
+        Ok(pages)
+    }
+
+    async fn handle(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        if !matches!(*request.method(), Method::GET | Method::HEAD) {
+            return response(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "text/plain",
+                "Method not allowed",
+            );
+        }
+
+        match route(request.uri().path()) {
+            Route::Home => {
+                let query = request.uri().query().unwrap_or_default();
+                let user_pk =
+                    url::form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "pk");
+                match user_pk.map(|(_, user_pk)| PublicKey::try_from(user_pk.trim())) {
+                    // Only redirect to a valid key, never to a URL given in the query
+                    Some(Ok(user_pk)) => Response::builder()
+                        .status(StatusCode::SEE_OTHER)
+                        .header(header::LOCATION, format!("/{}/", user_pk.z32()))
+                        .body(Full::default())
+                        .unwrap_or_default(),
+                    Some(Err(_)) => {
+                        let body =
+                            format!("{HOME_PAGE}<p class=\"meta\">Not a valid public key.</p>\n");
+                        html_response(StatusCode::BAD_REQUEST, "Pubky Wiki", "/", &body)
+                    }
+                    None => html_response(StatusCode::OK, "Pubky Wiki", "/", HOME_PAGE),
+                }
+            }
+            Route::UserPages(user_pk) => self.user_pages(&user_pk).await,
+            Route::Page { user_pk, page_id } => self.page(&user_pk, &page_id).await,
+            Route::Markdown { user_pk, page_id } => {
+                let file_url = format!("pubky://{user_pk}/pub/wiki.app/{page_id}");
+                match self.content(&file_url).await {
+                    Ok(content) => {
+                        response(StatusCode::OK, "text/markdown; charset=utf-8", content)
+                    }
+                    Err(e) => {
+                        log::error!("Error fetching path {file_url}: {e}");
+                        not_found(&format!("No page {page_id} by {user_pk}"))
+                    }
+                }
+            }
+            Route::Feed(user_pk) => {
+                let host = request
+                    .headers()
+                    .get(header::HOST)
+                    .and_then(|host| host.to_str().ok())
+                    .unwrap_or(DEFAULT_ADDRESS);
+                self.feed(&user_pk, &format!("http://{host}")).await
+            }
+            Route::NotFound => not_found("Pages are at /<public key>/<page id>"),
+        }
+    }
+
+    async fn user_pages(&self, user_pk: &str) -> Response<Full<Bytes>> {
+        let titles = match self.titles(user_pk).await {
+            Ok(titles) => titles,
+            Err(e) => {
+                log::error!("Failed to list pages of {user_pk}: {e}");
+                return not_found(&format!("Could not list the pages of {user_pk}"));
+            }
+        };
+
+        let mut body = format!(
+            "<h1>Pages by <code>{user_pk}</code></h1>\n<p class=\"meta\"><a href=\"/{user_pk}/feed.atom\">Atom feed</a></p>\n<ul>\n"
+        );
+        for (page_id, title) in &titles {
+            body.push_str(&format!(
+                "<li><a href=\"/{user_pk}/{page_id}\">{}</a></li>\n",
+                escape_html(title)
+            ));
+        }
+        body.push_str("</ul>\n");
+        if titles.is_empty() {
+            body.push_str("<p class=\"meta\">No wiki pages yet.</p>\n");
+        }
+        html_response(StatusCode::OK, "Pages", "/", &body)
+    }
+
+    /// Atom feed of a user, with absolute links to the gateway at `base_url`
+    async fn feed(&self, user_pk: &str, base_url: &str) -> Response<Full<Bytes>> {
+        let Ok(pk) = user_pk.parse::<PublicKey>() else {
+            return not_found(&format!("Invalid public key {user_pk}"));
+        };
+        let pages = match self.histories(&pk).await {
+            Ok(pages) => pages,
+            Err(e) => {
+                log::error!("Failed to list pages of {user_pk}: {e}");
+                return not_found(&format!("Could not list the pages of {user_pk}"));
+            }
+        };
+        let titles: Vec<(String, String)> = pages
+            .iter()
+            .map(|page| {
+                (
+                    page.page_id.clone(),
+                    extract_title(page.content()).trim().to_string(),
+                )
+            })
+            .collect();
+
+        let feed = atom_feed(
+            &pk,
+            &pages,
+            Some(&format!("{base_url}/{user_pk}/feed.atom")),
+            |page_id| format!("{base_url}/{user_pk}/{page_id}"),
+            |url| match href(url, user_pk, &titles) {
+                path if path.starts_with('/') => format!("{base_url}{path}"),
+                url => url,
+            },
+        );
+        response(StatusCode::OK, "application/atom+xml; charset=utf-8", feed)
+    }
+
+    async fn page(&self, user_pk: &str, page_id: &str) -> Response<Full<Bytes>> {
+        let file_url = format!("pubky://{user_pk}/pub/wiki.app/{page_id}");
+        let content = match self.content(&file_url).await {
+            Ok(content) => content,
+            Err(e) => {
+                log::error!("Error fetching path {file_url}: {e}");
+                return not_found(&format!("No page {page_id} by {user_pk}"));
+            }
+        };
+
+        // Title links are resolved against the author's pages, only fetched when needed
+        let has_title_links = extract_links(&content)
+            .iter()
+            .any(|url| url.starts_with(TITLE_LINK_SCHEME));
+        let titles = match has_title_links {
+            true => self.titles(user_pk).await.unwrap_or_default(),
+            false => vec![],
+        };
+
+        let html = render_html(&content, |url| href(url, user_pk, &titles));
+        let body = format!(
+            "<main>\n{html}</main>\n<footer><a href=\"/{user_pk}/{page_id}.md\">Markdown</a> · mirrored from <code>{}</code></footer>\n",
+            escape_html(&file_url)
+        );
+        html_response(
+            StatusCode::OK,
+            extract_title(&content),
+            &format!("/{user_pk}/"),
+            &body,
+        )
+    }
+}
+
+const HOME_PAGE: &str = "<h1>Pubky Wiki</h1>
+<form action=\"/\">
+<label>Public key of a user: <input name=\"pk\" size=\"55\" /></label>
+<button>Show pages</button>
+</form>
+";
+
+/// Serve wiki pages over HTTP at `address`, until the process is stopped
+pub(crate) async fn serve(address: SocketAddr) -> Result<()> {
+    let gateway = Arc::new(Gateway {
+        pub_storage: PublicStorage::new()?,
+        contents: Mutex::new(HashMap::new()),
+        lists: Mutex::new(HashMap::new()),
+        revisions: Mutex::new(HashMap::new()),
+    });
+    let listener = TcpListener::bind(address).await?;
+
+    log::info!("Serving wiki pages at http://{address}/");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let gateway = gateway.clone();
+
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let gateway = gateway.clone();
+                async move { Ok::<_, Infallible>(gateway.handle(request).await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::error!("Error serving connection: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PK: &str = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+
+    #[test]
+    fn routes_paths() {
+        assert_eq!(route("/"), Route::Home);
+        assert_eq!(route(&format!("/{PK}/")), Route::UserPages(PK.into()));
+        assert_eq!(route(&format!("/{PK}")), Route::UserPages(PK.into()));
+        assert_eq!(
+            route(&format!("/{PK}/id-1")),
+            Route::Page {
+                user_pk: PK.into(),
+                page_id: "id-1".into()
+            }
+        );
+        assert_eq!(
+            route(&format!("/{PK}/id-1.md")),
+            Route::Markdown {
+                user_pk: PK.into(),
+                page_id: "id-1".into()
+            }
+        );
+        assert_eq!(route(&format!("/{PK}/feed.atom")), Route::Feed(PK.into()));
+        assert_eq!(route("/not-a-key/id-1"), Route::NotFound);
+        assert_eq!(route(&format!("/{PK}/a/b")), Route::NotFound);
+        assert_eq!(route(&format!("/{PK}/.revisions")), Route::NotFound);
+        assert_eq!(route(&format!("/{PK}/..md")), Route::NotFound);
+        assert_eq!(route(&format!("/{PK}/id 1")), Route::NotFound);
+    }
+
+    #[test]
+    fn links_within_the_gateway() {
+        let titles = vec![("id-2".to_string(), "Ticino".to_string())];
+        assert_eq!(
+            href("id-1#Lake Side", PK, &titles),
+            format!("/{PK}/id-1#lake-side")
+        );
+        assert_eq!(
+            href("wiki-title:ticino", PK, &titles),
+            format!("/{PK}/id-2")
+        );
+        assert_eq!(href("wiki-title:Zurich", PK, &titles), "#");
+        assert_eq!(
+            href("https://example.com", PK, &titles),
+            "https://example.com"
+        );
+
+        let html = render_html("[x](javascript:alert(1)) ![y](data:image/png,x)", |url| {
+            href(url, PK, &titles)
+        });
+        assert_eq!(html, "<p><a>x</a> <img alt=\"y\" /></p>\n");
+    }
+
+    #[test]
+    fn drops_expired_cache_entries() {
+        let cache = Mutex::new(HashMap::new());
+        cache
+            .lock()
+            .unwrap()
+            .insert("old".to_string(), (Instant::now() - CACHE_TTL, 1));
+        store(&cache, "new", 2);
+
+        assert_eq!(cached(&cache, "new"), Some(2));
+        assert!(!cache.lock().unwrap().contains_key("old"));
+    }
+}
@@ -0,0 +1,363 @@
+//! Export of a wiki, or a single page, as a git repository to audit it with familiar tools.
+//!
+//! The `main` branch has one commit per page revision, authored by the page's author and dated
+//! by the revision time. The versions of the pages forked by the users the author follows become
+//! `forks/<pk>/<page id>` branches with one commit per revision of the fork, starting from the
+//! commit of the revision the fork was made from. Forks made before fork points were recorded
+//! start from the last revision of the original older than the fork.
+//!
+//! The repository is created with the `git` command, which must be installed.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::Path,
+    process::{Command, Output},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Result};
+use pubky::{PublicKey, PublicStorage};
+use tokio::runtime::Runtime;
+
+use crate::{
+    revisions::{fetch_fork_point, fetch_histories, fetch_history, PageHistory, Revision},
+    utils::{extract_title, get_public_list},
+};
+
+const MAIN_BRANCH: &str = "main";
+
+/// A page of the exported wiki forked by another user
+pub(crate) struct Fork {
+    pub(crate) user_pk: String,
+    pub(crate) history: PageHistory,
+    /// Revision of the original page the fork was made from, if it was recorded
+    pub(crate) fork_point: Option<String>,
+}
+
+/// Run a git command, with a clear error when git is not installed
+fn run(command: &mut Command) -> Result<Output> {
+    command.output().map_err(|e| match e.kind() {
+        ErrorKind::NotFound => {
+            anyhow!("git was not found, install it and make sure it is in the PATH")
+        }
+        _ => anyhow!("Failed to run git: {e}"),
+    })
+}
+
+/// Run git in `dir`, committing as `author` at the given Unix timestamp if any
+fn git(dir: &Path, args: &[&str], author: &str, timestamp: Option<u64>) -> Result<String> {
+    let mut command = Command::new("git");
+    command
+        .current_dir(dir)
+        .args(["-c", "commit.gpgsign=false"])
+        .args(args)
+        .env("GIT_AUTHOR_NAME", author)
+        .env("GIT_AUTHOR_EMAIL", format!("{author}@pubky"))
+        .env("GIT_COMMITTER_NAME", author)
+        .env("GIT_COMMITTER_EMAIL", format!("{author}@pubky"));
+    if let Some(timestamp) = timestamp {
+        let date = format!("@{timestamp} +0000");
+        command
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date);
+    }
+
+    let output = run(&mut command)?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Commit a revision of a page by `user_pk`, returning the commit hash
+fn commit_revision(
+    dir: &Path,
+    user_pk: &str,
+    page_id: &str,
+    revision: &Revision,
+) -> Result<String> {
+    let file = format!("{page_id}.md");
+    fs::write(dir.join(&file), &revision.content)?;
+
+    let title = match extract_title(&revision.content).trim() {
+        "" => page_id,
+        title => title,
+    };
+    let mut message = format!("{title}\n\nSource: pubky://{user_pk}/pub/wiki.app/{page_id}");
+    if let Some(revision_id) = &revision.id {
+        message.push_str(&format!("\nRevision: {revision_id}"));
+    }
+    git(dir, &["add", &file], user_pk, None)?;
+    git(
+        dir,
+        &["commit", "-q", "--allow-empty", "-m", &message],
+        user_pk,
+        revision.created_at,
+    )?;
+    git(dir, &["rev-parse", "HEAD"], user_pk, None)
+}
+
+/// A commit of the `main` branch
+struct Commit<'a> {
+    page_id: &'a str,
+    revision: &'a Revision,
+    hash: String,
+}
+
+/// Commit of the original page a fork starts from: the recorded fork point, or else the last
+/// revision older than the fork, or else the first revision
+fn fork_start<'a>(commits: &'a [Commit], fork: &Fork) -> Option<&'a Commit<'a>> {
+    let page_commits: Vec<&Commit> = commits
+        .iter()
+        .filter(|commit| commit.page_id == fork.history.page_id)
+        .collect();
+
+    let recorded = fork.fork_point.as_ref().and_then(|revision_id| {
+        page_commits
+            .iter()
+            .find(|commit| commit.revision.id.as_ref() == Some(revision_id))
+    });
+    let forked_at = fork
+        .history
+        .revisions
+        .first()
+        .and_then(|revision| revision.created_at);
+    let older = page_commits.iter().rfind(|commit| {
+        commit
+            .revision
+            .created_at
+            .is_some_and(|time| Some(time) <= forked_at)
+    });
+
+    recorded.or(older).or(page_commits.first()).copied()
+}
+
+/// Write the history of the pages of `user_pk` and of their forks to a new git repository in
+/// `dir`, returning the number of commits
+pub(crate) fn write_repository(
+    dir: &Path,
+    user_pk: &str,
+    pages: &[PageHistory],
+    forks: &[Fork],
+) -> Result<usize> {
+    if dir
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        bail!("{} is not empty", dir.display());
+    }
+    fs::create_dir_all(dir)?;
+    git(dir, &["init", "-q", "-b", MAIN_BRANCH], user_pk, None)?;
+
+    let mut revisions: Vec<(&str, &Revision)> = pages
+        .iter()
+        .flat_map(|page| {
+            page.revisions
+                .iter()
+                .map(|revision| (page.page_id.as_str(), revision))
+        })
+        .collect();
+    // Stable, so revisions of unknown time stay in order
+    revisions.sort_by_key(|(page_id, revision)| (revision.created_at, *page_id));
+
+    let mut commits = vec![];
+    for (page_id, revision) in revisions {
+        let hash = commit_revision(dir, user_pk, page_id, revision)?;
+        commits.push(Commit {
+            page_id,
+            revision,
+            hash,
+        });
+    }
+
+    let mut count = commits.len();
+    for fork in forks {
+        let Some(start) = fork_start(&commits, fork) else {
+            continue;
+        };
+        let page_id = &fork.history.page_id;
+        let branch = format!("forks/{}/{page_id}", fork.user_pk);
+        git(
+            dir,
+            &["checkout", "-q", "-b", &branch, &start.hash],
+            user_pk,
+            None,
+        )?;
+        for revision in &fork.history.revisions {
+            commit_revision(dir, &fork.user_pk, page_id, revision)?;
+            count += 1;
+        }
+        git(dir, &["checkout", "-q", MAIN_BRANCH], user_pk, None)?;
+    }
+
+    Ok(count)
+}
+
+/// Forks of the pages of `user_pk` by the users they follow. A page with the same ID is a fork
+/// unless its fork point says it was forked from another page.
+fn fetch_forks(
+    pub_storage: &PublicStorage,
+    user_pk: &str,
+    pages: &[PageHistory],
+    rt: Arc<Runtime>,
+) -> Vec<Fork> {
+    let follows_url = format!("pubky://{user_pk}/pub/pubky.app/follows/");
+    let follows = get_public_list(pub_storage, &follows_url, rt.clone())
+        .inspect_err(|e| log::error!("Failed to get follows of {user_pk}: {e}"))
+        .unwrap_or_default();
+
+    let mut forks = vec![];
+    for follow_url in follows {
+        let follow_key = follow_url.split('/').next_back().unwrap_or(&follow_url);
+        let Ok(follow_pk) = follow_key.parse::<PublicKey>() else {
+            continue;
+        };
+        for page in pages {
+            let page_url = format!("pubky://{user_pk}/pub/wiki.app/{}", page.page_id);
+            let fork_point = fetch_fork_point(pub_storage, &follow_pk, &page.page_id, rt.clone());
+            if fork_point
+                .as_ref()
+                .is_some_and(|fork_point| fork_point.forked_from != page_url)
+            {
+                continue;
+            }
+            if let Ok(history) =
+                fetch_history(pub_storage, &follow_pk, &page.page_id, None, rt.clone())
+            {
+                forks.push(Fork {
+                    user_pk: follow_pk.z32(),
+                    history,
+                    fork_point: fork_point.and_then(|fork_point| fork_point.revision),
+                });
+            }
+        }
+    }
+    forks
+}
+
+/// Export the wiki of `user_pk`, or only the page `page_id`, to a new git repository in `dir`,
+/// returning the number of commits
+pub(crate) fn export_git(
+    pub_storage: &PublicStorage,
+    user_pk: &str,
+    page_id: Option<&str>,
+    dir: &Path,
+    rt: Arc<Runtime>,
+) -> Result<usize> {
+    // Fail before fetching anything when git is missing
+    run(Command::new("git").arg("--version"))?;
+
+    let pk: PublicKey = user_pk.parse()?;
+    let pages = match page_id {
+        Some(page_id) => vec![fetch_history(pub_storage, &pk, page_id, None, rt.clone())?],
+        None => fetch_histories(pub_storage, &pk, None, rt.clone())?,
+    };
+    let forks = fetch_forks(pub_storage, user_pk, &pages, rt);
+
+    let count = write_repository(dir, user_pk, &pages, &forks)?;
+    log::info!("Exported {count} commits to {}", dir.display());
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revision(id: &str, content: &str, created_at: u64) -> Revision {
+        Revision {
+            id: Some(id.into()),
+            content: content.into(),
+            created_at: Some(created_at),
+        }
+    }
+
+    fn history(page_id: &str, revisions: Vec<Revision>) -> PageHistory {
+        PageHistory {
+            page_id: page_id.into(),
+            revisions,
+            is_complete: true,
+        }
+    }
+
+    fn log(dir: &Path, branch: &str) -> Vec<String> {
+        let log = git(dir, &["log", "--format=%an %at %s", branch], "", None).unwrap();
+        log.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn commits_revisions_and_forks() {
+        let dir = std::env::temp_dir().join(format!("pubky-wiki-git-{}", std::process::id()));
+        let pages = [
+            history("id-2", vec![revision("r3", "# Ticino", 1_760_000_000)]),
+            history(
+                "id-1",
+                vec![
+                    revision("r1", "# Lugano", 1_700_000_000),
+                    revision("r2", "# Lugano\n\nBy the lake", 1_720_000_000),
+                    Revision {
+                        id: None,
+                        content: "# Lugano\n\nOn Lake Lugano".into(),
+                        created_at: Some(1_770_000_000),
+                    },
+                ],
+            ),
+        ];
+        let forks = [
+            Fork {
+                user_pk: "pk-2".into(),
+                history: history(
+                    "id-1",
+                    vec![revision("r4", "# Lugano\n\nIn Ticino", 1_750_000_000)],
+                ),
+                fork_point: Some("r1".into()),
+            },
+            Fork {
+                user_pk: "pk-3".into(),
+                history: history(
+                    "id-1",
+                    vec![revision(
+                        "r5",
+                        "# Lugano\n\nBy the lake, in Ticino",
+                        1_730_000_000,
+                    )],
+                ),
+                fork_point: None,
+            },
+        ];
+
+        let count = write_repository(&dir, "pk-1", &pages, &forks).unwrap();
+        let main = log(&dir, MAIN_BRANCH);
+        let recorded_fork = log(&dir, "forks/pk-2/id-1");
+        let unrecorded_fork = log(&dir, "forks/pk-3/id-1");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(count, 6);
+        assert_eq!(
+            main,
+            [
+                "pk-1 1770000000 Lugano",
+                "pk-1 1760000000 Ticino",
+                "pk-1 1720000000 Lugano",
+                "pk-1 1700000000 Lugano"
+            ]
+        );
+        assert_eq!(
+            recorded_fork,
+            ["pk-2 1750000000 Lugano", "pk-1 1700000000 Lugano"]
+        );
+        assert_eq!(
+            unrecorded_fork,
+            [
+                "pk-3 1730000000 Lugano",
+                "pk-1 1720000000 Lugano",
+                "pk-1 1700000000 Lugano"
+            ]
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Markdown syntax highlighting for the page editor.
+
+use std::ops::Range;
+
+use eframe::egui::Ui;
+use egui::text::{LayoutJob, TextFormat};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Span {
+    Plain,
+    Heading,
+    Code,
+    Link,
+    Emphasis,
+    Strong,
+}
+
+/// Split markdown source into styled spans, covering the whole text
+pub(crate) fn spans(text: &str) -> Vec<(Range<usize>, Span)> {
+    let mut result = vec![];
+    let mut in_code_block = false;
+    let mut line_start = 0;
+
+    for line in text.split_inclusive('\n') {
+        let line_range = line_start..line_start + line.len();
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("```") {
+            in_code_block = !in_code_block;
+            push_span(&mut result, line_range, Span::Code);
+        } else if in_code_block {
+            push_span(&mut result, line_range, Span::Code);
+        } else if is_heading(trimmed) {
+            push_span(&mut result, line_range, Span::Heading);
+        } else {
+            inline_spans(line, line_start, &mut result);
+        }
+
+        line_start += line.len();
+    }
+
+    result
+}
+
+fn is_heading(line: &str) -> bool {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    (1..=6).contains(&level) && line[level..].starts_with([' ', '\n'])
+}
+
+/// Spans of code, links and emphasis within a single line
+fn inline_spans(line: &str, offset: usize, result: &mut Vec<(Range<usize>, Span)>) {
+    let mut i = 0;
+
+    while i < line.len() {
+        let rest = &line[i..];
+        let styled = if let Some(code) = rest.strip_prefix('`') {
+            code.find('`').map(|end| (end + 2, Span::Code))
+        } else if rest.starts_with("[[") {
+            rest.find("]]").map(|end| (end + 2, Span::Link))
+        } else if rest.starts_with('[') {
+            rest.find("](")
+                .filter(|mid| !rest[1..*mid].contains(['[', ']']))
+                .and_then(|mid| rest[mid..].find(')').map(|end| (mid + end + 1, Span::Link)))
+        } else if rest.starts_with("**") || rest.starts_with("__") {
+            let delimiter = &rest[..2];
+            rest[2..]
+                .find(delimiter)
+                .filter(|end| *end > 0)
+                .map(|end| (end + 4, Span::Strong))
+        } else if rest.starts_with(['*', '_']) && !rest[1..].starts_with(char::is_whitespace) {
+            let delimiter = &rest[..1];
+            rest[1..]
+                .find(delimiter)
+                .filter(|end| *end > 0)
+                .map(|end| (end + 2, Span::Emphasis))
+        } else {
+            None
+        };
+
+        match styled {
+            Some((len, span)) => {
+                push_span(result, offset + i..offset + i + len, span);
+                i += len;
+            }
+            None => {
+                let len = rest.chars().next().map_or(1, char::len_utf8);
+                push_span(result, offset + i..offset + i + len, Span::Plain);
+                i += len;
+            }
+        }
+    }
+}
+
+/// Append a span, merging it with the previous one if they have the same style
+fn push_span(result: &mut Vec<(Range<usize>, Span)>, range: Range<usize>, span: Span) {
+    if let Some((last_range, last_span)) = result.last_mut() {
+        if *last_span == span && last_range.end == range.start {
+            last_range.end = range.end;
+            return;
+        }
+    }
+    result.push((range, span));
+}
+
+/// Layout of the editor text, styled with the current theme
+pub(crate) fn layout_job(ui: &Ui, text: &str) -> LayoutJob {
+    let visuals = ui.visuals();
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+
+    let mut job = LayoutJob::default();
+    for (range, span) in spans(text) {
+        let mut format = TextFormat::simple(font_id.clone(), visuals.text_color());
+        match span {
+            Span::Plain => {}
+            Span::Heading => {
+                format.font_id.size *= 1.2;
+                format.color = visuals.strong_text_color();
+            }
+            Span::Code => format.background = visuals.code_bg_color,
+            Span::Link => format.color = visuals.hyperlink_color,
+            Span::Emphasis => format.italics = true,
+            Span::Strong => format.color = visuals.strong_text_color(),
+        }
+        job.append(&text[range], 0.0, format);
+    }
+
+    job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styled(text: &str) -> Vec<(&str, Span)> {
+        spans(text)
+            .into_iter()
+            .map(|(range, span)| (&text[range], span))
+            .collect()
+    }
+
+    #[test]
+    fn highlights_blocks() {
+        assert_eq!(
+            styled("# Title\ntext\n```\n# not a heading\n```\n#hashtag"),
+            vec![
+                ("# Title\n", Span::Heading),
+                ("text\n", Span::Plain),
+                ("```\n# not a heading\n```\n", Span::Code),
+                ("#hashtag", Span::Plain),
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_inline() {
+        assert_eq!(
+            styled("a `b` [c](d) [[e]] *f* **g** 2 * 3 [x] [y](z)"),
+            vec![
+                ("a ", Span::Plain),
+                ("`b`", Span::Code),
+                (" ", Span::Plain),
+                ("[c](d)", Span::Link),
+                (" ", Span::Plain),
+                ("[[e]]", Span::Link),
+                (" ", Span::Plain),
+                ("*f*", Span::Emphasis),
+                (" ", Span::Plain),
+                ("**g**", Span::Strong),
+                (" 2 * 3 [x] ", Span::Plain),
+                ("[y](z)", Span::Link),
+            ]
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use crate::{PubkyApp, ViewState};
+
+use eframe::egui::{Context, Ui};
+use egui::{Key, Modifiers, PointerButton};
+use pubky::{PubkySession, PublicStorage};
+
+/// A place the user can navigate back or forward to
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Location {
+    WikiList,
+    /// A page, at its latest version or at a pinned revision
+    WikiPage {
+        user_pk: String,
+        page_id: String,
+        revision: Option<String>,
+    },
+    EditWiki { user_pk: String, page_id: String },
+    CreateWiki,
+    Trash,
+    UserWiki { user_pk: String },
+    Tags,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HistoryEntry {
+    pub(crate) location: Location,
+    /// Vertical scroll offset of the page content when the user left it
+    pub(crate) scroll_offset: f32,
+}
+
+/// Browser-style back and forward stacks
+#[derive(Default)]
+pub(crate) struct History {
+    back: Vec<HistoryEntry>,
+    forward: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Record `current` before navigating to a new location
+    pub(crate) fn visit(&mut self, current: HistoryEntry) {
+        if self.back.last().map(|e| &e.location) != Some(&current.location) {
+            self.back.push(current);
+        }
+        self.forward.clear();
+    }
+
+    /// Entry to go back to, recording `current` to allow going forward again
+    pub(crate) fn go_back(&mut self, current: HistoryEntry) -> Option<HistoryEntry> {
+        let previous = self.back.pop()?;
+        self.forward.push(current);
+        Some(previous)
+    }
+
+    /// Entry to go forward to, recording `current` to allow going back again
+    pub(crate) fn go_forward(&mut self, current: HistoryEntry) -> Option<HistoryEntry> {
+        let next = self.forward.pop()?;
+        self.back.push(current);
+        Some(next)
+    }
+
+    /// Drop the entries of a page that no longer exists
+    pub(crate) fn forget_page(&mut self, user_pk: &str, page_id: &str) {
+        let is_page = |entry: &HistoryEntry| match &entry.location {
+            Location::WikiPage {
+                user_pk: pk, page_id: id, ..
+            }
+            | Location::EditWiki { user_pk: pk, page_id: id } => {
+                pk == user_pk && id == page_id
+            }
+            _ => false,
+        };
+        self.back.retain(|entry| !is_page(entry));
+        self.forward.retain(|entry| !is_page(entry));
+    }
+
+    pub(crate) fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub(crate) fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+impl PubkyApp {
+    /// Where the user currently is, as recorded in the history
+    pub(crate) fn current_history_entry(&self) -> HistoryEntry {
+        let location = match self.view_state {
+            ViewState::ViewWiki if !self.selected_wiki_page_id.is_empty() => Location::WikiPage {
+                user_pk: self.selected_wiki_user_id.clone(),
+                page_id: self.selected_wiki_page_id.clone(),
+                revision: self.selected_wiki_revision.clone(),
+            },
+            ViewState::EditWiki if !self.selected_wiki_page_id.is_empty() => Location::EditWiki {
+                user_pk: self.selected_wiki_user_id.clone(),
+                page_id: self.selected_wiki_page_id.clone(),
+            },
+            ViewState::CreateWiki => Location::CreateWiki,
+            ViewState::Trash => Location::Trash,
+            ViewState::Tags => Location::Tags,
+            ViewState::UserWiki => match &self.user_wiki {
+                Some(user_wiki) => Location::UserWiki {
+                    user_pk: user_wiki.user_pk.clone(),
+                },
+                None => Location::WikiList,
+            },
+            _ => Location::WikiList,
+        };
+
+        HistoryEntry {
+            location,
+            scroll_offset: self.page_scroll_offset,
+        }
+    }
+
+    /// Open a view without a location of its own, recording the current location in the history
+    pub(crate) fn navigate_to(&mut self, view_state: ViewState) {
+        let current = self.current_history_entry();
+        self.history.visit(current);
+
+        if view_state == ViewState::Trash {
+            self.trash = None;
+        }
+        self.view_state = view_state;
+    }
+
+    pub(crate) fn go_back(&mut self, session: &PubkySession, pub_storage: &PublicStorage) {
+        let current = self.current_history_entry();
+        match self.history.go_back(current) {
+            Some(entry) => self.open_history_entry(entry, session, pub_storage),
+            // Nothing to go back to: behave like the former "Back" button
+            None => self.open_history_entry(
+                HistoryEntry {
+                    location: Location::WikiList,
+                    scroll_offset: 0.0,
+                },
+                session,
+                pub_storage,
+            ),
+        }
+    }
+
+    pub(crate) fn go_forward(&mut self, session: &PubkySession, pub_storage: &PublicStorage) {
+        let current = self.current_history_entry();
+        if let Some(entry) = self.history.go_forward(current) {
+            self.open_history_entry(entry, session, pub_storage);
+        }
+    }
+
+    /// Leave a page that was just deleted, going back to where the user was before opening it
+    pub(crate) fn leave_deleted_page(
+        &mut self,
+        user_pk: &str,
+        page_id: &str,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
+    ) {
+        self.history.forget_page(user_pk, page_id);
+        let previous = self.history.back.pop().unwrap_or(HistoryEntry {
+            location: Location::WikiList,
+            scroll_offset: 0.0,
+        });
+        self.open_history_entry(previous, session, pub_storage);
+    }
+
+    fn open_history_entry(
+        &mut self,
+        entry: HistoryEntry,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
+    ) {
+        match entry.location {
+            Location::WikiList => {
+                self.selected_wiki_page_id.clear();
+                self.selected_wiki_content.clear();
+                self.selected_wiki_revision = None;
+                self.selected_wiki_fork_urls.clear();
+                self.selected_wiki_anchor = None;
+                self.view_state = ViewState::WikiList;
+            }
+            Location::WikiPage {
+                user_pk,
+                page_id,
+                revision,
+            } => {
+                self.show_wiki_page(&user_pk, &page_id, session, pub_storage);
+                self.selected_wiki_revision = revision;
+                self.pending_page_scroll = Some(entry.scroll_offset);
+            }
+            Location::EditWiki { user_pk, page_id } => self.show_edit_wiki_page(&user_pk, &page_id, session, pub_storage),
+            Location::CreateWiki => self.view_state = ViewState::CreateWiki,
+            Location::Trash => {
+                self.trash = None;
+                self.view_state = ViewState::Trash;
+            }
+            Location::UserWiki { user_pk } => self.show_user_wiki(&user_pk, pub_storage),
+            Location::Tags => self.view_state = ViewState::Tags,
+        }
+    }
+}
+
+/// Back and forward buttons
+pub(crate) fn show_nav_buttons(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    ui: &mut Ui,
+) {
+    ui.horizontal(|ui| {
+        let back_button = ui.add_enabled(app.history.can_go_back(), egui::Button::new("◀"));
+        if back_button.on_hover_text("Back (Alt+←)").clicked() {
+            app.go_back(session, pub_storage);
+        }
+
+        let forward_button = ui.add_enabled(app.history.can_go_forward(), egui::Button::new("▶"));
+        if forward_button.on_hover_text("Forward (Alt+→)").clicked() {
+            app.go_forward(session, pub_storage);
+        }
+    });
+}
+
+/// Navigate with the mouse back/forward buttons and Alt+Left/Right
+pub(crate) fn handle_shortcuts(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    ctx: &Context,
+) {
+    let (back, forward) = ctx.input_mut(|i| {
+        let back = i.pointer.button_pressed(PointerButton::Extra1)
+            || i.consume_key(Modifiers::ALT, Key::ArrowLeft);
+        let forward = i.pointer.button_pressed(PointerButton::Extra2)
+            || i.consume_key(Modifiers::ALT, Key::ArrowRight);
+        (back, forward)
+    });
+
+    if back && app.history.can_go_back() {
+        app.go_back(session, pub_storage);
+    } else if forward {
+        app.go_forward(session, pub_storage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(page_id: &str, scroll_offset: f32) -> HistoryEntry {
+        HistoryEntry {
+            location: Location::WikiPage {
+                user_pk: "pk".into(),
+                page_id: page_id.into(),
+                revision: None,
+            },
+            scroll_offset,
+        }
+    }
+
+    #[test]
+    fn goes_back_and_forward() {
+        let mut history = History::default();
+        history.visit(page("a", 10.0));
+        history.visit(page("b", 20.0));
+
+        assert_eq!(history.go_back(page("c", 30.0)), Some(page("b", 20.0)));
+        assert_eq!(history.go_back(page("b", 25.0)), Some(page("a", 10.0)));
+        assert_eq!(history.go_back(page("a", 10.0)), None);
+
+        assert_eq!(history.go_forward(page("a", 15.0)), Some(page("b", 25.0)));
+        assert_eq!(history.go_back(page("b", 25.0)), Some(page("a", 15.0)));
+    }
+
+    #[test]
+    fn visiting_clears_forward() {
+        let mut history = History::default();
+        history.visit(page("a", 0.0));
+        history.go_back(page("b", 0.0));
+        assert!(history.can_go_forward());
+
+        history.visit(page("a", 0.0));
+        assert!(!history.can_go_forward());
+    }
+
+    #[test]
+    fn forgets_deleted_pages() {
+        let mut history = History::default();
+        history.visit(page("a", 0.0));
+        history.visit(HistoryEntry {
+            location: Location::Trash,
+            scroll_offset: 0.0,
+        });
+        history.visit(page("b", 0.0));
+
+        history.forget_page("pk", "b");
+        assert_eq!(history.go_back(page("c", 0.0)).map(|e| e.location), Some(Location::Trash));
+
+        history.forget_page("pk", "a");
+        history.forget_page("other-pk", "c");
+        assert!(!history.can_go_back());
+        assert_eq!(history.go_forward(page("d", 0.0)), Some(page("c", 0.0)));
+    }
+
+    #[test]
+    fn returns_to_the_editor() {
+        let pk = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+        let mut app = PubkyApp::for_tests();
+        app.selected_wiki_user_id = pk.into();
+        app.selected_wiki_page_id = "id-1".into();
+        app.view_state = ViewState::EditWiki;
+
+        app.navigate_to(ViewState::Trash);
+        let entry = app.history.go_back(app.current_history_entry()).unwrap();
+        assert_eq!(
+            entry.location,
+            Location::EditWiki {
+                user_pk: pk.into(),
+                page_id: "id-1".into()
+            }
+        );
+        assert_eq!(app.history.go_forward(entry).map(|e| e.location), Some(Location::Trash));
+    }
+
+    #[test]
+    fn does_not_record_same_location_twice() {
+        let mut history = History::default();
+        history.visit(page("a", 0.0));
+        history.visit(page("a", 50.0));
+
+        assert_eq!(history.go_back(page("b", 0.0)), Some(page("a", 0.0)));
+        assert!(!history.can_go_back());
+    }
+}
@@ -0,0 +1,546 @@
+//! Import of a folder of markdown files, such as an Obsidian vault, as wiki pages.
+//! MediaWiki XML dumps are imported by the [`crate::mediawiki`] module.
+//!
+//! Titles come from the `title` front-matter field, or else from the file name. `[[wikilinks]]`
+//! and relative links between the imported files become links to the matching wiki pages.
+//!
+//! Re-importing a folder updates the pages imported before instead of creating duplicates:
+//! a file is matched with an existing page by the `page_id` front-matter field (as written by
+//! the export), or else by the title the page gets, which is its `# H1` heading if it has one.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use eframe::egui::Context;
+use pubky::{PubkySession, PublicStorage};
+use uuid::Uuid;
+
+use crate::{
+    create_wiki_post,
+    export::{fetch_pages, Page},
+    links::{is_valid_page_id, rewrite_link_urls, rewrite_title_links, slugify},
+    mediawiki,
+    revisions::save_revision_as,
+    update_wiki_post,
+    utils::extract_title,
+    PubkyApp,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ImportAction {
+    Create,
+    Update,
+    Unchanged,
+}
+
+impl ImportAction {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            ImportAction::Create => "create",
+            ImportAction::Update => "update",
+            ImportAction::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// A page to import, with its content converted to markdown
+#[derive(Clone, Debug)]
+pub(crate) struct ImportedPage {
+    /// Where the page comes from, such as the file path relative to the imported folder
+    pub(crate) source: String,
+    pub(crate) title: String,
+    pub(crate) page_id: String,
+    pub(crate) action: ImportAction,
+    pub(crate) content: String,
+    /// Revisions to record in the history of the page as `(revision ID, content)`, oldest first
+    /// and ending with `content`. Without any, the import is recorded as a new revision.
+    pub(crate) revisions: Vec<(String, String)>,
+    /// Links to other pages that could not be matched with an imported page
+    pub(crate) unresolved_links: Vec<String>,
+}
+
+/// Pages to import, as previewed before uploading them
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ImportPlan {
+    pub(crate) pages: Vec<ImportedPage>,
+    /// Remarks about the content that is not imported
+    pub(crate) notes: Vec<String>,
+}
+
+/// Existing pages of the user, by lowercase title
+pub(crate) fn pages_by_title(existing_pages: &[Page]) -> HashMap<String, &Page> {
+    existing_pages
+        .iter()
+        .map(|page| (extract_title(&page.content).trim().to_lowercase(), page))
+        .collect()
+}
+
+/// Whether importing `content` creates a page or updates the existing one
+pub(crate) fn import_action(existing_page: Option<&Page>, content: &str) -> ImportAction {
+    match existing_page {
+        None => ImportAction::Create,
+        Some(page) if page.content == content => ImportAction::Unchanged,
+        Some(_) => ImportAction::Update,
+    }
+}
+
+/// Split the YAML front-matter of a markdown file from its body.
+///
+/// Only simple `key: value` lines are supported, which covers titles and IDs.
+pub(crate) fn parse_front_matter(content: &str) -> (HashMap<String, String>, &str) {
+    let mut fields = HashMap::new();
+
+    let Some(rest) = content.strip_prefix("---\n").or_else(|| content.strip_prefix("---\r\n")) else {
+        return (fields, content);
+    };
+    let Some(end) = rest.find("\n---") else {
+        return (fields, content);
+    };
+
+    for line in rest[..end].lines() {
+        if let Some((key, value)) = line.split_once(':') {
+            fields.insert(key.trim().to_string(), unquote(value.trim()));
+        }
+    }
+
+    let body = &rest[end + 4..];
+    let body = body.split_once('\n').map_or("", |(_, body)| body);
+    (fields, body.trim_start_matches(['\n', '\r']))
+}
+
+fn unquote(value: &str) -> String {
+    if value.starts_with('"') {
+        if let Ok(unquoted) = serde_json::from_str::<String>(value) {
+            return unquoted;
+        }
+    }
+    value.trim_matches(['"', '\'']).to_string()
+}
+
+/// All the markdown files in `dir` and its subfolders, skipping hidden ones such as `.obsidian`
+fn find_markdown_files(dir: &Path, result: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+
+        if is_hidden {
+            continue;
+        } else if path.is_dir() {
+            find_markdown_files(&path, result)?;
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("md")) {
+            result.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Resolve `..` and `.` in a relative path, without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::CurDir => {}
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+/// Key under which a file is found by its links: lowercase path without the `.md` extension
+fn link_key(path: &Path) -> String {
+    path.with_extension("").to_string_lossy().replace('\\', "/").to_lowercase()
+}
+
+/// Page ID linked by a `[[wikilink]]` target, which is a file name, a path, or a title
+fn resolve_wikilink<'a>(
+    target: &str,
+    files: &'a HashMap<String, String>,
+    titles: &'a HashMap<String, String>,
+) -> Option<&'a String> {
+    let key = target.trim().trim_end_matches(".md").to_lowercase();
+
+    files
+        .get(&key)
+        // Obsidian links by file name alone when it's unique in the vault
+        .or_else(|| {
+            let mut matching = files
+                .iter()
+                .filter(|(path, _)| path.rsplit('/').next() == Some(key.as_str()));
+            match (matching.next(), matching.next()) {
+                (Some((_, page_id)), None) => Some(page_id),
+                _ => None,
+            }
+        })
+        .or_else(|| titles.get(&key))
+}
+
+/// Convert the links of a file into wiki links, returning the targets that could not be resolved
+fn convert_links(
+    body: &str,
+    file_dir: &Path,
+    files: &HashMap<String, String>,
+    titles: &HashMap<String, String>,
+) -> (String, Vec<String>) {
+    let mut unresolved = vec![];
+
+    let body = rewrite_link_urls(body, |url| {
+        if url.contains(':') || url.starts_with('#') || url.is_empty() {
+            return None;
+        }
+        let (path, anchor) = url.split_once('#').unwrap_or((url, ""));
+        let path = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+        let key = link_key(&normalize(&file_dir.join(path.as_ref())));
+
+        match files.get(&key) {
+            Some(page_id) if anchor.is_empty() => Some(page_id.clone()),
+            Some(page_id) => {
+                let anchor = percent_encoding::percent_decode_str(anchor).decode_utf8_lossy();
+                Some(format!("{page_id}#{}", slugify(&anchor)))
+            }
+            None => {
+                unresolved.push(url.to_string());
+                None
+            }
+        }
+    });
+
+    let body = rewrite_title_links(&body, |target, label| {
+        let (target, anchor) = target.split_once('#').unwrap_or((target, ""));
+        let label = if label == target { target } else { label };
+
+        match resolve_wikilink(target, files, titles) {
+            Some(page_id) if anchor.is_empty() => Some(format!("[{label}]({page_id})")),
+            Some(page_id) => Some(format!("[{label}]({page_id}#{})", slugify(anchor))),
+            None => {
+                unresolved.push(format!("[[{target}]]"));
+                None
+            }
+        }
+    });
+
+    (body, unresolved)
+}
+
+/// Content of an imported page, starting with its title as the first line
+fn page_content(title: &str, body: &str) -> String {
+    if body.starts_with("# ") {
+        body.to_string()
+    } else {
+        format!("# {title}\n\n{body}")
+    }
+}
+
+/// Plan the import of a folder of markdown files or of a MediaWiki XML dump, without uploading anything
+pub(crate) fn plan_import(path: &Path, existing_pages: &[Page]) -> Result<ImportPlan> {
+    let is_xml = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xml"));
+
+    if path.is_file() && is_xml {
+        mediawiki::plan_import(path, existing_pages)
+    } else if path.is_dir() {
+        plan_folder_import(path, existing_pages)
+    } else {
+        bail!("{} is neither a folder nor a MediaWiki XML dump", path.display())
+    }
+}
+
+fn plan_folder_import(dir: &Path, existing_pages: &[Page]) -> Result<ImportPlan> {
+    let mut paths = vec![];
+    find_markdown_files(dir, &mut paths)?;
+    paths.sort();
+
+    let existing_by_id: HashMap<&str, &Page> = existing_pages
+        .iter()
+        .map(|page| (page.page_id.as_str(), page))
+        .collect();
+    let existing_by_title = pages_by_title(existing_pages);
+
+    // First pass: title and page ID of each file, so that links can be resolved
+    let mut files = vec![];
+    for path in paths {
+        let relative_path = path.strip_prefix(dir)?.to_path_buf();
+        let content = fs::read_to_string(&path)?;
+        let (front_matter, body) = parse_front_matter(&content);
+
+        let title = front_matter
+            .get("title")
+            .filter(|title| !title.is_empty())
+            .cloned()
+            .or_else(|| relative_path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+            .unwrap_or_default();
+
+        // Matched by the title stored with the page, which can differ from the file name
+        let page_title = extract_title(&page_content(&title, body)).trim().to_lowercase();
+        let existing_page = front_matter
+            .get("page_id")
+            .and_then(|page_id| existing_by_id.get(page_id.as_str()))
+            .or_else(|| existing_by_title.get(&page_title))
+            .copied();
+        let page_id = match (existing_page, front_matter.get("page_id")) {
+            (Some(page), _) => page.page_id.clone(),
+            (None, Some(page_id)) if is_valid_page_id(page_id) => page_id.clone(),
+            (None, _) => Uuid::new_v4().to_string(),
+        };
+
+        files.push((relative_path, title, page_id, existing_page, body.to_string()));
+    }
+
+    let page_ids_by_path: HashMap<String, String> = files
+        .iter()
+        .map(|(path, _, page_id, _, _)| (link_key(path), page_id.clone()))
+        .collect();
+    let page_ids_by_title: HashMap<String, String> = files
+        .iter()
+        .map(|(_, title, page_id, _, _)| (title.to_lowercase(), page_id.clone()))
+        .collect();
+
+    // Second pass: convert the content
+    let mut result = vec![];
+    for (path, title, page_id, existing_page, body) in files {
+        let file_dir = path.parent().unwrap_or(Path::new(""));
+        let (body, unresolved_links) =
+            convert_links(&body, file_dir, &page_ids_by_path, &page_ids_by_title);
+
+        let content = page_content(&title, &body);
+
+        result.push(ImportedPage {
+            source: path.display().to_string(),
+            title: extract_title(&content).trim().to_string(),
+            page_id,
+            action: import_action(existing_page, &content),
+            content,
+            revisions: vec![],
+            unresolved_links,
+        });
+    }
+
+    Ok(ImportPlan {
+        pages: result,
+        notes: vec![],
+    })
+}
+
+/// Upload the planned pages, returning how many were created or updated
+pub(crate) async fn run_import(session: &PubkySession, plan: &ImportPlan) -> Result<usize> {
+    let mut count = 0;
+    for page in &plan.pages {
+        match page.action {
+            ImportAction::Unchanged => continue,
+            // The latest revision is already in the history, so the page is written as is
+            _ if !page.revisions.is_empty() => {
+                for (revision_id, content) in &page.revisions {
+                    save_revision_as(session, &page.page_id, revision_id, content).await?;
+                }
+                let path = format!("/pub/wiki.app/{}", page.page_id);
+                session.storage().put(&path, page.content.clone()).await?;
+            }
+            ImportAction::Create => {
+                create_wiki_post(session, &page.content, Some(&page.page_id)).await?;
+            }
+            ImportAction::Update => update_wiki_post(session, &page.page_id, &page.content).await?,
+        }
+        log::info!("Imported {} as {}", page.source, page.page_id);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// One line per imported page with the links that could not be resolved, then a summary
+pub(crate) fn format_report(plan: &ImportPlan) -> String {
+    let mut report = String::new();
+    for page in &plan.pages {
+        report.push_str(&format!(
+            "{:<9}  {}  {}  ({})\n",
+            page.action.label(),
+            page.page_id,
+            page.title,
+            page.source
+        ));
+        for link in &page.unresolved_links {
+            report.push_str(&format!("           unresolved link: {link}\n"));
+        }
+    }
+    for note in &plan.notes {
+        report.push_str(&format!("{note}\n"));
+    }
+
+    let count = |action| plan.pages.iter().filter(|page| page.action == action).count();
+    report.push_str(&format!(
+        "{} to create, {} to update, {} unchanged\n",
+        count(ImportAction::Create),
+        count(ImportAction::Update),
+        count(ImportAction::Unchanged)
+    ));
+    report
+}
+
+/// State of the import window
+#[derive(Default)]
+pub(crate) struct ImportState {
+    pub(crate) dir: String,
+    /// Result of the last dry run, to be confirmed
+    plan: Option<ImportPlan>,
+    status: Option<String>,
+}
+
+/// Pick a folder, preview what would be imported, then import it
+pub(crate) fn show_import_window(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    ctx: &Context,
+) {
+    let Some(mut import) = app.import.take() else {
+        return;
+    };
+    let mut is_open = true;
+
+    egui::Window::new("Import Markdown Folder")
+        .collapsible(false)
+        .resizable(true)
+        .open(&mut is_open)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label("Folder of markdown files, Obsidian vault, or MediaWiki XML dump:");
+            if ui.text_edit_singleline(&mut import.dir).changed() {
+                import.plan = None;
+            }
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                if ui.button("🔍 Preview").clicked() {
+                    let own_pk = session.info().public_key().z32();
+                    let plan = fetch_pages(pub_storage, &own_pk, app.rt.clone())
+                        .and_then(|pages| plan_import(Path::new(&import.dir), &pages));
+                    match plan {
+                        Ok(plan) => {
+                            import.status = None;
+                            import.plan = Some(plan);
+                        }
+                        Err(e) => {
+                            import.status = Some(format!("Cannot import: {e}"));
+                            import.plan = None;
+                        }
+                    }
+                }
+
+                let import_button = ui.add_enabled(import.plan.is_some(), egui::Button::new("📥 Import"));
+                if import_button.clicked() {
+                    if let Some(plan) = import.plan.take() {
+                        import.status = Some(match app.rt.block_on(run_import(session, &plan)) {
+                            Ok(count) => format!("Imported {count} pages"),
+                            Err(e) => {
+                                log::error!("Failed to import: {e}");
+                                format!("Import failed: {e}")
+                            }
+                        });
+                        app.needs_refresh = true;
+                    }
+                }
+            });
+
+            if let Some(plan) = &import.plan {
+                ui.add_space(10.0);
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    ui.label(egui::RichText::new(format_report(plan)).monospace());
+                });
+            }
+            if let Some(status) = &import.status {
+                ui.add_space(5.0);
+                ui.label(egui::RichText::new(status).italics());
+            }
+        });
+
+    if is_open {
+        app.import = Some(import);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_front_matter() {
+        let (fields, body) = parse_front_matter("---\ntitle: \"Lugano \\\"city\\\"\"\npage_id: abc\n---\n\n# Lugano\n");
+        assert_eq!(fields["title"], "Lugano \"city\"");
+        assert_eq!(fields["page_id"], "abc");
+        assert_eq!(body, "# Lugano\n");
+
+        let (fields, body) = parse_front_matter("# No front-matter\n---\n");
+        assert!(fields.is_empty());
+        assert_eq!(body, "# No front-matter\n---\n");
+    }
+
+    #[test]
+    fn converts_links() {
+        let files = HashMap::from([
+            ("notes/lugano".to_string(), "id-1".to_string()),
+            ("places/ticino".to_string(), "id-2".to_string()),
+        ]);
+        let titles = HashMap::from([("canton of ticino".to_string(), "id-2".to_string())]);
+
+        let (body, unresolved) = convert_links(
+            "[a](../places/ticino.md#Lakes and rivers) [b](lugano.md) [[Lugano]] [[Ticino|c]] [[Canton of Ticino]] [[Zurich]] [d](https://x.org)",
+            Path::new("notes"),
+            &files,
+            &titles,
+        );
+        assert_eq!(
+            body,
+            "[a](../places/ticino.md#Lakes and rivers) [b](id-1) [Lugano](id-1) [c](id-2) [Canton of Ticino](id-2) [[Zurich]] [d](https://x.org)"
+        );
+        assert_eq!(unresolved, vec!["[[Zurich]]"]);
+
+        let (body, _) = convert_links("[a](../places/Ticino.md#Lakes%20and%20rivers)", Path::new("notes"), &files, &titles);
+        assert_eq!(body, "[a](id-2#lakes-and-rivers)");
+    }
+
+    #[test]
+    fn reimports_pages_titled_differently_from_their_file() {
+        let dir = std::env::temp_dir().join(format!("pubky-wiki-import-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lugano-notes.md"), "# Lugano\n\nBy the lake").unwrap();
+
+        let plan = plan_import(&dir, &[]).unwrap();
+        assert_eq!(plan.pages[0].action, ImportAction::Create);
+
+        let imported = [Page {
+            page_id: plan.pages[0].page_id.clone(),
+            content: plan.pages[0].content.clone(),
+            updated_at: None,
+        }];
+        let reimport = plan_import(&dir, &imported).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reimport.pages.len(), 1);
+        assert_eq!(reimport.pages[0].page_id, plan.pages[0].page_id);
+        assert_eq!(reimport.pages[0].action, ImportAction::Unchanged);
+    }
+
+    #[test]
+    fn only_keeps_valid_page_ids_from_front_matter() {
+        let dir = std::env::temp_dir().join(format!("pubky-wiki-import-ids-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.md"), "---\npage_id: lugano-notes\n---\n# Lugano").unwrap();
+        fs::write(dir.join("b.md"), "---\npage_id: .trash\n---\n# Trash").unwrap();
+        fs::write(dir.join("c.md"), "---\npage_id: notes.md\n---\n# Notes").unwrap();
+
+        let plan = plan_import(&dir, &[]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut page_ids: Vec<&str> = plan.pages.iter().map(|page| page.page_id.as_str()).collect();
+        page_ids.sort();
+        assert_eq!(page_ids[2], "lugano-notes");
+        assert!(page_ids[..2].iter().all(|page_id| Uuid::parse_str(page_id).is_ok()));
+    }
+}
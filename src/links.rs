@@ -0,0 +1,498 @@
+//! Grammar of the links between wiki pages.
+//!
+//! A wiki link is one of:
+//!
+//! - `pubky://<pk>/pub/wiki.app/<id>`: absolute pubky URL (the `pubky://` prefix is optional)
+//! - `<pk>/<id>`: page of a given author
+//! - `<id>`: page of the same author as the page containing the link
+//!
+//! Page IDs are UUIDs or slugs: letters, digits, `-` and `_` only, so that file names like
+//! `logo.png` and domains like `www.example.com` are not taken for pages.
+//!
+//! Each form accepts a trailing `/`, a revision pin as `@<revision>` right after the page ID
+//! and a section anchor as `#<heading>` at the end, for example `<pk>/<id>@<revision>#history`.
+//!
+//! Pages can also be linked by title with `[[Page Title]]` or `[[Page Title|label]]`.
+//! These are resolved when clicked, against the user's own pages and then their follows' pages.
+
+use std::{collections::HashMap, fmt, ops::Range};
+
+use pubky::PublicKey;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use url::Url;
+
+const WIKI_PATH: [&str; 2] = ["pub", "wiki.app"];
+
+/// URL scheme of the markdown links generated for `[[Page Title]]` links
+pub(crate) const TITLE_LINK_SCHEME: &str = "wiki-title:";
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct WikiLink {
+    /// Author of the page, or `None` if it's the author of the linking page
+    pub(crate) user_pk: Option<String>,
+    pub(crate) page_id: String,
+    /// Pinned revision, or `None` for the latest version
+    pub(crate) revision: Option<String>,
+    /// Heading within the page
+    pub(crate) anchor: Option<String>,
+}
+
+impl WikiLink {
+    /// Parse a link target, returning `None` if it's not a wiki link
+    pub(crate) fn parse(url: &str) -> Option<Self> {
+        let url = url.trim();
+
+        let (target, anchor) = match url.split_once('#') {
+            Some((target, anchor)) => (target, Some(anchor.trim())),
+            None => (url, None),
+        };
+        let anchor = anchor.filter(|a| !a.is_empty()).map(String::from);
+
+        let (target, is_absolute) = match target.split_once("://") {
+            Some(("pubky", rest)) => (rest, true),
+            Some(_) => return None,
+            None => (target, false),
+        };
+        if target.contains(':') {
+            return None;
+        }
+
+        let segments: Vec<&str> = target.trim_end_matches('/').split('/').collect();
+        let (user_pk, page) = match segments.as_slice() {
+            [page] if !is_absolute => (None, *page),
+            [pk, page] if !is_absolute => (Some(*pk), *page),
+            [pk, path @ .., page] if path == WIKI_PATH => (Some(*pk), *page),
+            _ => return None,
+        };
+
+        // Keys are kept in their bare form, as in pubky URLs, even when given with the `pubky` prefix
+        let user_pk = match user_pk {
+            Some(pk) => Some(pk.parse::<PublicKey>().ok()?.z32()),
+            None => None,
+        };
+
+        let (page_id, revision) = match page.split_once('@') {
+            Some((page_id, revision)) if !revision.is_empty() => (page_id, Some(revision)),
+            Some(_) => return None,
+            None => (page, None),
+        };
+
+        if !is_valid_page_id(page_id) {
+            return None;
+        }
+
+        Some(Self {
+            user_pk,
+            page_id: page_id.to_string(),
+            revision: revision.map(String::from),
+            anchor,
+        })
+    }
+
+    /// Author of the page, falling back to the author of the linking page
+    pub(crate) fn user_pk_or<'a>(&'a self, current_user_pk: &'a str) -> &'a str {
+        self.user_pk.as_deref().unwrap_or(current_user_pk)
+    }
+}
+
+/// Whether `page_id` has the shape of a page ID: a UUID or a slug, without `.` or `/`
+pub(crate) fn is_valid_page_id(page_id: &str) -> bool {
+    !page_id.is_empty() && page_id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+impl fmt::Display for WikiLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(user_pk) = &self.user_pk {
+            write!(f, "{user_pk}/")?;
+        }
+        write!(f, "{}", self.page_id)?;
+        if let Some(revision) = &self.revision {
+            write!(f, "@{revision}")?;
+        }
+        if let Some(anchor) = &self.anchor {
+            write!(f, "#{anchor}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a link clicked in a page leads
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum LinkTarget {
+    /// Another wiki page, opened in the app
+    Wiki(WikiLink),
+    /// A `[[Page Title]]` link, to be resolved by title
+    Title(String),
+    /// A section of the current page
+    Anchor(String),
+    /// A web page, opened in the system browser
+    External { url: String, domain: String },
+    /// Any other scheme, which the app can't open
+    Unsupported(String),
+}
+
+impl LinkTarget {
+    pub(crate) fn classify(url: &str) -> Self {
+        let url = url.trim();
+
+        if let Some(title) = url.strip_prefix(TITLE_LINK_SCHEME) {
+            return LinkTarget::Title(title.to_string());
+        }
+        if let Some(anchor) = url.strip_prefix('#') {
+            return LinkTarget::Anchor(anchor.to_string());
+        }
+        if let Some(link) = WikiLink::parse(url) {
+            return LinkTarget::Wiki(link);
+        }
+
+        match Url::parse(url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => match parsed.host_str() {
+                Some(domain) => LinkTarget::External {
+                    url: parsed.to_string(),
+                    domain: domain.to_string(),
+                },
+                None => LinkTarget::Unsupported(url.to_string()),
+            },
+            _ => LinkTarget::Unsupported(url.to_string()),
+        }
+    }
+}
+
+/// Rewrite `[[Page Title]]` and `[[Page Title|label]]` into markdown links with a title URL
+pub(crate) fn expand_title_links(content: &str) -> String {
+    rewrite_title_links(content, |title, label| {
+        Some(format!("[{label}](<{TITLE_LINK_SCHEME}{title}>)"))
+    })
+}
+
+/// Replace each `[[Page Title|label]]` link by `rewrite(title, label)`, or keep it when `None`.
+///
+/// Links in inline code and code blocks are kept as they are, since they are code samples.
+pub(crate) fn rewrite_title_links(
+    content: &str,
+    mut rewrite: impl FnMut(&str, &str) -> Option<String>,
+) -> String {
+    let code_ranges = code_ranges(content);
+    let mut result = String::with_capacity(content.len());
+    let mut pos = 0;
+
+    while let Some(found) = content[pos..].find("[[") {
+        let start = pos + found;
+        let Some(len) = content[start + 2..].find("]]") else {
+            break;
+        };
+        let end = start + 2 + len + 2;
+        result.push_str(&content[pos..start]);
+
+        // Only skip the opening brackets of a link in code, a link may follow on the same line
+        if code_ranges.iter().any(|range| range.start < end && start < range.end) {
+            result.push_str("[[");
+            pos = start + 2;
+            continue;
+        }
+
+        let original = &content[start..end];
+        let inner = &content[start + 2..end - 2];
+        let (title, label) = inner.split_once('|').unwrap_or((inner, inner));
+        let (title, label) = (title.trim(), label.trim());
+
+        let rewritten = if title.is_empty() || inner.contains(['\n', '<', '>', '[']) {
+            None
+        } else {
+            rewrite(title, label)
+        };
+        result.push_str(rewritten.as_deref().unwrap_or(original));
+        pos = end;
+    }
+
+    result.push_str(&content[pos..]);
+    result
+}
+
+/// Byte ranges of the inline code and code blocks in the content
+fn code_ranges(content: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(content, markdown_options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Replace the target of each inline markdown link by `rewrite(url)`, or keep it when `None`.
+///
+/// Image targets are kept as they are, since they point at assets rather than pages.
+pub(crate) fn rewrite_link_urls(
+    content: &str,
+    mut rewrite: impl FnMut(&str) -> Option<String>,
+) -> String {
+    // Byte ranges of the link targets in the content, with their replacement
+    let mut replacements = vec![];
+    for (event, range) in Parser::new_ext(content, markdown_options()).into_offset_iter() {
+        let dest_url = match event {
+            Event::Start(Tag::Link { dest_url, .. }) => dest_url,
+            _ => continue,
+        };
+        let source = &content[range.clone()];
+        // The target follows the link text, possibly between angle brackets
+        let Some(text_end) = source.rfind("](") else {
+            continue;
+        };
+        let Some(url_start) = source[text_end..].find(dest_url.as_ref()) else {
+            continue;
+        };
+        if let Some(new_url) = rewrite(&dest_url) {
+            let start = range.start + text_end + url_start;
+            replacements.push((start..start + dest_url.len(), new_url));
+        }
+    }
+
+    // Apply from the end so that the earlier ranges stay valid
+    replacements.sort_by_key(|(range, _)| range.start);
+    let mut result = content.to_string();
+    for (range, new_url) in replacements.into_iter().rev() {
+        result.replace_range(range, &new_url);
+    }
+    result
+}
+
+/// Pages whose title matches `title` (ignoring case), as `pk/page_id` links
+pub(crate) fn find_pages_by_title(file_cache: &HashMap<String, String>, title: &str) -> Vec<String> {
+    let mut result: Vec<String> = file_cache
+        .iter()
+        .filter(|(_, file_title)| file_title.trim().eq_ignore_ascii_case(title.trim()))
+        .filter_map(|(file_url, _)| WikiLink::parse(file_url))
+        .map(|link| link.to_string())
+        .collect();
+    result.sort();
+    result
+}
+
+/// Markdown extensions enabled when rendering pages, same as the egui_commonmark viewer
+pub(crate) fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_DEFINITION_LIST
+}
+
+/// Targets of all links in a page, with `[[Page Title]]` links expanded
+pub(crate) fn extract_links(content: &str) -> Vec<String> {
+    Parser::new_ext(&expand_title_links(content), markdown_options())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link { dest_url, .. }) => Some(dest_url.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Position of the heading matching `anchor`, as a fraction of the content's lines.
+///
+/// Headings match either verbatim or by their slug (lowercase, spaces as dashes).
+pub(crate) fn find_anchor(content: &str, anchor: &str) -> Option<f32> {
+    let wanted = slugify(anchor);
+    let line_count = content.lines().count().max(1);
+
+    content
+        .lines()
+        .position(|line| {
+            let line = line.trim_start();
+            line.starts_with('#') && slugify(line.trim_start_matches('#')) == wanted
+        })
+        .map(|line_idx| line_idx as f32 / line_count as f32)
+}
+
+pub(crate) fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() => Some(c),
+            ' ' | '-' | '_' => Some('-'),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PK: &str = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+    const ID: &str = "8143c732-35a6-4a86-96f5-3ffb17b80ad0";
+
+    fn link(user_pk: Option<&str>, revision: Option<&str>, anchor: Option<&str>) -> WikiLink {
+        WikiLink {
+            user_pk: user_pk.map(String::from),
+            page_id: ID.into(),
+            revision: revision.map(String::from),
+            anchor: anchor.map(String::from),
+        }
+    }
+
+    #[test]
+    fn parses_absolute_pubky_url() {
+        let url = format!("pubky://{PK}/pub/wiki.app/{ID}");
+        assert_eq!(WikiLink::parse(&url), Some(link(Some(PK), None, None)));
+
+        let url = format!("{PK}/pub/wiki.app/{ID}/");
+        assert_eq!(WikiLink::parse(&url), Some(link(Some(PK), None, None)));
+    }
+
+    #[test]
+    fn parses_pk_and_id() {
+        let url = format!("{PK}/{ID}");
+        assert_eq!(WikiLink::parse(&url), Some(link(Some(PK), None, None)));
+
+        let url = format!(" {PK}/{ID}/ ");
+        assert_eq!(WikiLink::parse(&url), Some(link(Some(PK), None, None)));
+
+        let url = format!("pubky{PK}/{ID}");
+        assert_eq!(WikiLink::parse(&url), Some(link(Some(PK), None, None)));
+    }
+
+    #[test]
+    fn parses_bare_id() {
+        assert_eq!(WikiLink::parse(ID), Some(link(None, None, None)));
+        assert_eq!(link(None, None, None).user_pk_or(PK), PK);
+    }
+
+    #[test]
+    fn parses_anchor() {
+        let url = format!("{ID}#History of Lugano");
+        assert_eq!(
+            WikiLink::parse(&url),
+            Some(link(None, None, Some("History of Lugano")))
+        );
+
+        let url = format!("pubky://{PK}/pub/wiki.app/{ID}#intro");
+        assert_eq!(WikiLink::parse(&url), Some(link(Some(PK), None, Some("intro"))));
+
+        let url = format!("{ID}#");
+        assert_eq!(WikiLink::parse(&url), Some(link(None, None, None)));
+    }
+
+    #[test]
+    fn parses_revision_pin() {
+        let url = format!("{PK}/{ID}@1760000000#intro");
+        assert_eq!(
+            WikiLink::parse(&url),
+            Some(link(Some(PK), Some("1760000000"), Some("intro")))
+        );
+
+        let url = format!("{ID}@abc");
+        assert_eq!(WikiLink::parse(&url), Some(link(None, Some("abc"), None)));
+
+        assert_eq!(WikiLink::parse(&format!("{ID}@")), None);
+    }
+
+    #[test]
+    fn rejects_non_wiki_links() {
+        assert_eq!(WikiLink::parse("https://example.com/page"), None);
+        assert_eq!(WikiLink::parse("mailto:someone@example.com"), None);
+        assert_eq!(WikiLink::parse(""), None);
+        assert_eq!(WikiLink::parse("not-a-pk/page"), None);
+        assert_eq!(WikiLink::parse(&format!("pubky://{PK}/{ID}")), None);
+        assert_eq!(WikiLink::parse(&format!("{PK}/pub/other.app/{ID}")), None);
+        assert_eq!(WikiLink::parse("logo.png"), None);
+        assert_eq!(WikiLink::parse("../img/a.png"), None);
+        assert_eq!(WikiLink::parse("www.example.com"), None);
+        assert_eq!(WikiLink::parse(&format!("{PK}/notes.md")), None);
+        assert_eq!(WikiLink::parse("my-notes_2"), Some(WikiLink { page_id: "my-notes_2".into(), ..link(None, None, None) }));
+    }
+
+    #[test]
+    fn displays_canonical_form() {
+        let url = format!("pubky://{PK}/pub/wiki.app/{ID}@2#intro");
+        let parsed = WikiLink::parse(&url).unwrap();
+        assert_eq!(parsed.to_string(), format!("{PK}/{ID}@2#intro"));
+        assert_eq!(WikiLink::parse(&parsed.to_string()), Some(parsed));
+    }
+
+    #[test]
+    fn classifies_links() {
+        assert_eq!(
+            LinkTarget::classify(&format!("{PK}/{ID}")),
+            LinkTarget::Wiki(link(Some(PK), None, None))
+        );
+        assert_eq!(
+            LinkTarget::classify("wiki-title:Lugano"),
+            LinkTarget::Title("Lugano".into())
+        );
+        assert_eq!(
+            LinkTarget::classify("#intro"),
+            LinkTarget::Anchor("intro".into())
+        );
+        assert_eq!(
+            LinkTarget::classify("https://user@Example.com:8080/a?b#c"),
+            LinkTarget::External {
+                url: "https://user@example.com:8080/a?b#c".into(),
+                domain: "example.com".into(),
+            }
+        );
+        assert_eq!(
+            LinkTarget::classify("ftp://example.com/file"),
+            LinkTarget::Unsupported("ftp://example.com/file".into())
+        );
+        assert_eq!(
+            LinkTarget::classify("javascript:alert(1)"),
+            LinkTarget::Unsupported("javascript:alert(1)".into())
+        );
+    }
+
+    #[test]
+    fn expands_title_links() {
+        assert_eq!(
+            expand_title_links("See [[Lugano]] and [[Bitcoin history|the history]]."),
+            "See [Lugano](<wiki-title:Lugano>) and [the history](<wiki-title:Bitcoin history>)."
+        );
+        assert_eq!(expand_title_links("[[ ]] and [[unclosed"), "[[ ]] and [[unclosed");
+        assert_eq!(expand_title_links("[[a\nb]]"), "[[a\nb]]");
+    }
+
+    #[test]
+    fn keeps_title_links_in_code() {
+        assert_eq!(
+            expand_title_links("Write `[[Lugano]]` for [[Lugano]]."),
+            "Write `[[Lugano]]` for [Lugano](<wiki-title:Lugano>)."
+        );
+        assert_eq!(
+            expand_title_links("`[[` opens [[Lugano]]"),
+            "`[[` opens [Lugano](<wiki-title:Lugano>)"
+        );
+        let code_block = "```\n[[Lugano]]\n```\n\n    [[Lugano]]\n";
+        assert_eq!(expand_title_links(code_block), code_block);
+    }
+
+    #[test]
+    fn rewrites_link_urls() {
+        let content = "[a](x) [b](<y z> \"x\") [![c](x)](x) [d][ref] <x>\n\n[ref]: x";
+        assert_eq!(
+            rewrite_link_urls(content, |url| (url == "x").then(|| "new".to_string())),
+            "[a](new) [b](<y z> \"x\") [![c](x)](new) [d][ref] <x>\n\n[ref]: x"
+        );
+    }
+
+    #[test]
+    fn extracts_links() {
+        let content = format!("# Title\n\n[Carol]({PK}/{ID}), [[Lugano]] and <https://example.com>");
+        assert_eq!(
+            extract_links(&content),
+            vec![
+                format!("{PK}/{ID}"),
+                "wiki-title:Lugano".to_string(),
+                "https://example.com".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_anchor_heading() {
+        let content = "# Title\n\nIntro\n\n## History of Lugano\n\nText";
+        assert_eq!(find_anchor(content, "history-of-lugano"), Some(4.0 / 7.0));
+        assert_eq!(find_anchor(content, "History of Lugano"), Some(4.0 / 7.0));
+        assert_eq!(find_anchor(content, "missing"), None);
+    }
+}
@@ -1,27 +1,76 @@
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
 use eframe::egui;
 use egui_commonmark::*;
-use pubky::{Capabilities, Pubky, PubkyAuthFlow, PubkySession, PublicStorage};
+use pubky::{AuthFlowKind, Capabilities, Pubky, PubkyAuthFlow, PubkySession, PublicStorage};
 use tokio::runtime::Runtime;
 use uuid::Uuid;
 
-use crate::utils::{extract_title, generate_qr_image, get_list};
+use crate::{
+    api::ApiServer,
+    capabilities::{initial_capabilities, CapsRequestState},
+    drafts::{Autosave, Draft},
+    edit_wiki::EditConflict,
+    editor::EditorMode,
+    history::History,
+    import::ImportState,
+    links::{find_pages_by_title, LinkTarget, WikiLink},
+    migrate::MigrationState,
+    profiles::{show_user, Profiles},
+    tags::TagsState,
+    trash::{TrashedPage, UndoDelete},
+    user_wiki::UserWiki,
+    utils::{extract_title, generate_qr_image, get_own_content, list_all, list_pages},
+};
 
+mod api;
+mod capabilities;
+mod cli;
 mod create_wiki;
+mod diff;
+mod drafts;
 mod edit_wiki;
+mod editor;
+mod export;
+mod feed;
+mod follows;
+mod gateway;
+mod git_export;
+mod highlight;
+mod history;
+mod import;
+mod links;
+mod mediawiki;
+mod migrate;
+mod posts;
+mod profiles;
+mod revisions;
+mod site;
+mod sync;
+mod tags;
+mod trash;
+mod user_wiki;
 mod utils;
 mod view_wiki;
 
 const APP_NAME: &str = "Pubky Wiki";
 
+/// How long the titles of the follows' pages are used before they are fetched again
+const FOLLOWS_FILE_CACHE_TTL: Duration = Duration::from_secs(300);
+
 fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args);
+    }
+
     let rt = Runtime::new()?;
     let app = PubkyApp::new(rt);
 
@@ -52,8 +101,8 @@ fn load_icon() -> Result<egui::IconData> {
 
     Ok(egui::IconData {
         rgba,
-        width: width as u32,
-        height: height as u32,
+        width,
+        height,
     })
 }
 
@@ -68,6 +117,7 @@ fn load_logo_image() -> Option<egui::ColorImage> {
 }
 
 #[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum AuthState {
     Initializing,
     ShowingQR {
@@ -88,6 +138,27 @@ pub(crate) enum ViewState {
     CreateWiki,
     ViewWiki,
     EditWiki,
+    Trash,
+    UserWiki,
+    Tags,
+}
+
+/// Pages matching the title of a clicked `[[Page Title]]` link
+#[derive(Clone)]
+pub(crate) struct TitleLinkPicker {
+    pub(crate) title: String,
+    /// Matching pages as `pk/page_id` links, empty if the title is unresolved
+    pub(crate) candidates: Vec<String>,
+    /// Whether no own page matched, and the follows' pages are still to be searched
+    pub(crate) searching_follows: bool,
+}
+
+/// Titles of the follows' pages by file URL, fetched in the background on first use
+pub(crate) enum FollowsFileCache {
+    /// Set by the background task once the titles are fetched
+    Loading(Arc<Mutex<Option<HashMap<String, String>>>>),
+    /// Titles, and when they were fetched
+    Loaded(HashMap<String, String>, Instant),
 }
 
 pub(crate) struct PubkyApp {
@@ -98,29 +169,97 @@ pub(crate) struct PubkyApp {
     pub(crate) view_state: ViewState,
     /// Content for the Edit Wiki view
     pub(crate) edit_wiki_content: String,
+    pub(crate) editor_mode: EditorMode,
+    /// Last synchronised scroll position of the editor and its preview, from 0 to 1
+    pub(crate) editor_scroll_ratio: f32,
+    /// Content of the page when it was loaded in the Edit Wiki view, to detect changes made elsewhere
+    pub(crate) edit_base_content: String,
+    /// Why the page being edited was not saved, when it changed elsewhere or that could not be checked
+    pub(crate) edit_conflict: Option<EditConflict>,
+    /// Local draft of the content being edited
+    pub(crate) autosave: Autosave,
+    /// Unfinished drafts listed in the Wiki List view, read again after a draft is saved or deleted
+    pub(crate) drafts: Option<Vec<Draft>>,
+    /// Folder to export the wiki to, while the export window is open
+    pub(crate) export_dir: Option<String>,
+    /// Outcome of the last export
+    pub(crate) export_status: Option<String>,
+    /// State of the import window, while it is open
+    pub(crate) import: Option<ImportState>,
+    /// Local JSON API, while it runs
+    pub(crate) api: Option<ApiServer>,
+    /// Why the API could not be started
+    pub(crate) api_error: Option<String>,
+    /// Step of the homeserver migration wizard, updated by the migration thread
+    pub(crate) migration: Arc<Mutex<MigrationState>>,
+    migration_qr_texture: Option<egui::TextureHandle>,
+    /// Whether the "move to trash" confirmation of the page being edited is shown
+    pub(crate) confirm_delete: bool,
+    /// Trashed page waiting for confirmation before being deleted forever
+    pub(crate) confirm_purge: Option<TrashedPage>,
+    /// Pages in the trash, fetched when the Trash view is opened
+    pub(crate) trash: Option<Vec<TrashedPage>>,
+    /// Last deletion, while it can be undone
+    pub(crate) undo_delete: Option<UndoDelete>,
+    /// Search query of the "insert wiki link" picker, when open
+    pub(crate) link_picker_query: Option<String>,
+    /// Section of the editor preview to scroll to, after a click on an anchor link
+    pub(crate) preview_anchor: Option<String>,
     pub(crate) selected_wiki_fork_urls: Vec<String>,
     pub(crate) selected_wiki_page_id: String,
     pub(crate) selected_wiki_content: String,
     pub(crate) selected_wiki_user_id: String,
+    /// Revision of the selected page shown read-only, or `None` for its latest version
+    pub(crate) selected_wiki_revision: Option<String>,
+    /// Section to scroll to once the selected page is shown
+    pub(crate) selected_wiki_anchor: Option<String>,
+    /// Pages and views visited before (back) and after (forward) the current one
+    pub(crate) history: History,
+    /// Current vertical scroll offset of the selected page content
+    pub(crate) page_scroll_offset: f32,
+    /// Scroll offset to apply to the selected page content on the next frame
+    pub(crate) pending_page_scroll: Option<f32>,
+    /// Disambiguation (or page creation) prompt for a clicked title link
+    pub(crate) title_link_picker: Option<TitleLinkPicker>,
+    /// Clicked link waiting for confirmation (external) or acknowledgement (unsupported)
+    pub(crate) pending_link: Option<LinkTarget>,
+    /// Public key typed or pasted in the User Wiki view
+    pub(crate) user_wiki_input: String,
+    /// Pages of the user shown in the User Wiki view
+    pub(crate) user_wiki: Option<UserWiki>,
+    /// Public keys of the user's follows, fetched on first use
+    pub(crate) follows: Option<Vec<String>>,
+    /// Tags of the user and their follows, fetched on first use
+    pub(crate) tags: Option<TagsState>,
+    /// New tag typed for the selected page
+    pub(crate) tag_input: String,
+    /// Names and avatars of the users shown
+    pub(crate) profiles: Profiles,
+    /// Map file URL to file title, for the pages of the user's follows
+    pub(crate) follows_file_cache: Option<FollowsFileCache>,
     pub(crate) needs_refresh: bool,
     cache: CommonMarkCache,
     rt: Arc<Runtime>,
     pub(crate) show_copy_tooltip: bool,
     /// Page ID from which content is being forked (when forking)
     pub(crate) forked_from_page_id: Option<String>,
+    /// Whether to announce the page being created as a pubky.app post
+    pub(crate) announce: bool,
+    /// Incremental auth flow requesting capabilities beyond the initial ones
+    pub(crate) caps_request: Arc<Mutex<CapsRequestState>>,
+    caps_qr_texture: Option<egui::TextureHandle>,
 }
 
 impl PubkyApp {
     fn new(rt: Runtime) -> Self {
-        let state = Arc::new(Mutex::new(AuthState::Initializing));
+        let app = Self::with_state(Arc::new(Mutex::new(AuthState::Initializing)), Arc::new(rt));
 
         // Start the auth flow in a background task
-        let state_clone = state.clone();
-
-        let rt_arc = Arc::new(rt);
-        let rt_arc_clone = rt_arc.clone();
+        let state_clone = app.state.clone();
+        let rt_arc_clone = app.rt.clone();
         std::thread::spawn(move || {
-            let initialize_auth_fut = initialize_auth();
+            let caps = initial_capabilities();
+            let initialize_auth_fut = initialize_auth(&caps);
             match rt_arc_clone.block_on(initialize_auth_fut) {
                 Ok((pubky, flow, auth_url)) => {
                     *state_clone.lock().unwrap() = AuthState::ShowingQR {
@@ -151,6 +290,18 @@ impl PubkyApp {
             }
         });
 
+        app
+    }
+
+    /// App waiting for sign in, without starting the auth flow, for tests
+    #[cfg(test)]
+    pub(crate) fn for_tests() -> Self {
+        let rt = Runtime::new().expect("Failed to create the runtime");
+        Self::with_state(Arc::new(Mutex::new(AuthState::Initializing)), Arc::new(rt))
+    }
+
+    /// App in the given auth state, without starting the auth flow
+    fn with_state(state: Arc<Mutex<AuthState>>, rt: Arc<Runtime>) -> Self {
         // Load logo image
         let logo_image = load_logo_image();
 
@@ -161,15 +312,51 @@ impl PubkyApp {
             logo_image,
             view_state: ViewState::WikiList,
             edit_wiki_content: String::new(),
+            editor_mode: EditorMode::Split,
+            editor_scroll_ratio: 0.0,
+            edit_base_content: String::new(),
+            edit_conflict: None,
+            autosave: Autosave::default(),
+            drafts: None,
+            export_dir: None,
+            export_status: None,
+            import: None,
+            api: None,
+            api_error: None,
+            migration: Arc::new(Mutex::new(MigrationState::Closed)),
+            migration_qr_texture: None,
+            confirm_delete: false,
+            confirm_purge: None,
+            trash: None,
+            undo_delete: None,
+            link_picker_query: None,
+            preview_anchor: None,
             selected_wiki_page_id: String::new(),
             selected_wiki_content: String::new(),
+            selected_wiki_revision: None,
             selected_wiki_user_id: String::new(),
+            selected_wiki_anchor: None,
+            history: History::default(),
+            page_scroll_offset: 0.0,
+            pending_page_scroll: None,
+            title_link_picker: None,
+            pending_link: None,
+            user_wiki_input: String::new(),
+            user_wiki: None,
+            follows: None,
+            tags: None,
+            tag_input: String::new(),
+            profiles: Profiles::default(),
+            follows_file_cache: None,
             selected_wiki_fork_urls: vec![],
             needs_refresh: false,
             cache: CommonMarkCache::default(),
-            rt: rt_arc,
+            rt,
             show_copy_tooltip: false,
             forked_from_page_id: None,
+            announce: false,
+            caps_request: Arc::new(Mutex::new(CapsRequestState::Idle)),
+            caps_qr_texture: None,
         }
     }
 
@@ -182,7 +369,8 @@ impl PubkyApp {
     ) {
         let mut file_cache = HashMap::new();
 
-        match get_list(session, "/pub/wiki.app/", rt_arc_clone.clone()) {
+        let storage = session.storage();
+        match rt_arc_clone.block_on(list_pages(|| storage.list("/pub/wiki.app/"))) {
             Ok(file_urls) => {
                 for file_url in &file_urls {
                     // Synchronously fetch the content
@@ -219,56 +407,152 @@ impl PubkyApp {
         page_id: &str,
         session: &PubkySession,
         pub_storage: &PublicStorage,
+    ) {
+        let current = self.current_history_entry();
+        self.history.visit(current);
+
+        self.show_wiki_page(user_pk, page_id, session, pub_storage);
+    }
+
+    /// Load and show a page, without recording it in the history
+    fn show_wiki_page(
+        &mut self,
+        user_pk: &str,
+        page_id: &str,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
     ) {
         self.selected_wiki_user_id = user_pk.to_string();
         self.selected_wiki_page_id = page_id.to_string();
-        self.selected_wiki_fork_urls = self.discover_fork_urls(session, pub_storage, page_id);
+        self.selected_wiki_fork_urls = self.rt.block_on(discover_fork_urls(session, pub_storage, page_id));
         self.selected_wiki_content.clear();
+        self.selected_wiki_revision = None;
+        self.selected_wiki_anchor = None;
+        self.pending_page_scroll = Some(0.0);
 
         self.view_state = ViewState::ViewWiki;
     }
 
-    fn navigate_to_edit_selected_wiki_page(&mut self) {
-        self.edit_wiki_content = self.selected_wiki_content.clone();
-        self.view_state = ViewState::EditWiki;
+    /// Follow a link from the selected page, resolving links without author against its author.
+    /// A link pinned to a revision shows that revision, read-only.
+    pub(crate) fn navigate_to_wiki_link(
+        &mut self,
+        link: &WikiLink,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
+    ) {
+        let user_pk = link.user_pk_or(&self.selected_wiki_user_id).to_string();
+        self.leave_editor(session);
+        self.navigate_to_view_wiki_page(&user_pk, &link.page_id, session, pub_storage);
+        self.selected_wiki_revision = link.revision.clone();
+        self.selected_wiki_anchor = link.anchor.clone();
     }
 
-    fn get_my_follows(&self, session: &PubkySession) -> Vec<String> {
-        get_list(session, "/pub/pubky.app/follows/", self.rt.clone())
-            .inspect_err(|e| log::error!("Failed to get follows: {e}"))
-            .map(|list| {
-                list.iter()
-                    .map(|path| path.split('/').last().unwrap_or(&path).to_string())
-                    .collect()
-            })
-            .unwrap_or_default()
+    /// Follow a `[[Page Title]]` link, looking the title up in own pages first. If none matches,
+    /// the title picker searches the follows' pages once their titles are fetched.
+    fn navigate_to_title_link(
+        &mut self,
+        title: &str,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
+        file_cache: &HashMap<String, String>,
+    ) {
+        let candidates = find_pages_by_title(file_cache, title);
+
+        match candidates.as_slice() {
+            [single] => {
+                if let Some(link) = WikiLink::parse(single) {
+                    self.navigate_to_wiki_link(&link, session, pub_storage);
+                }
+            }
+            _ => {
+                self.title_link_picker = Some(TitleLinkPicker {
+                    title: title.to_string(),
+                    searching_follows: candidates.is_empty(),
+                    candidates,
+                })
+            }
+        }
     }
 
-    fn discover_fork_urls(
-        &self,
+    /// Titles of the follows' pages, or `None` while they are fetched in the background
+    pub(crate) fn get_follows_file_cache(
+        &mut self,
         session: &PubkySession,
         pub_storage: &PublicStorage,
-        page_id: &str,
-    ) -> Vec<String> {
-        let follows = self.get_my_follows(session);
+        ctx: &egui::Context,
+    ) -> Option<&HashMap<String, String>> {
+        if let Some(FollowsFileCache::Loaded(_, fetched_at)) = &self.follows_file_cache {
+            if fetched_at.elapsed() >= FOLLOWS_FILE_CACHE_TTL {
+                self.follows_file_cache = None;
+            }
+        }
 
-        let mut result = vec![];
+        let state = self.follows_file_cache.get_or_insert_with(|| {
+            let result = Arc::new(Mutex::new(None));
+            let task_result = result.clone();
+            let (session, pub_storage, ctx) = (session.clone(), pub_storage.clone(), ctx.clone());
+            self.rt.spawn(async move {
+                let follows_file_cache = fetch_follows_file_cache(&session, &pub_storage).await;
+                *task_result.lock().unwrap() = Some(follows_file_cache);
+                ctx.request_repaint();
+            });
+            FollowsFileCache::Loading(result)
+        });
+
+        if let FollowsFileCache::Loading(result) = state {
+            let follows_file_cache = result.lock().unwrap().take()?;
+            *state = FollowsFileCache::Loaded(follows_file_cache, Instant::now());
+        }
+        match state {
+            FollowsFileCache::Loaded(follows_file_cache, _) => Some(follows_file_cache),
+            FollowsFileCache::Loading(_) => None,
+        }
+    }
 
-        // Add the current user's version as a fork (root version)
-        let own_pk = session.info().public_key().to_string();
-        result.push(format!("{own_pk}/{page_id}"));
+    fn navigate_to_edit_selected_wiki_page(&mut self, session: &PubkySession) {
+        let current = self.current_history_entry();
+        self.history.visit(current);
 
-        for follow_pk in follows {
-            let fork_path = format!("pubky://{follow_pk}/pub/wiki.app/{page_id}");
-            log::info!("fork_path = {fork_path}");
-            let exists_fut = pub_storage.get(fork_path);
+        self.edit_selected_wiki_page(session);
+    }
 
-            match self.rt.block_on(exists_fut) {
-                Ok(_) => result.push(format!("{follow_pk}/{page_id}")),
-                Err(e) => log::error!("Failed to check if file exists: {e}"),
+    /// Load a page and open it in the editor, without recording it in the history
+    fn show_edit_wiki_page(
+        &mut self,
+        user_pk: &str,
+        page_id: &str,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
+    ) {
+        self.show_wiki_page(user_pk, page_id, session, pub_storage);
+
+        // Only own pages are edited, so they are read through the session
+        let path = format!("/pub/wiki.app/{page_id}");
+        match get_own_content(session, &path, self.rt.clone()) {
+            Ok(content) => {
+                self.selected_wiki_content = content;
+                self.edit_selected_wiki_page(session);
             }
+            Err(e) => log::error!("Error fetching path {path}: {e}"),
         }
-        result
+    }
+
+    /// Open the selected page in the editor, without recording it in the history
+    fn edit_selected_wiki_page(&mut self, session: &PubkySession) {
+        self.edit_wiki_content = self.selected_wiki_content.clone();
+        self.edit_base_content = self.selected_wiki_content.clone();
+        self.edit_conflict = None;
+        self.confirm_delete = false;
+        self.start_edit_draft(session);
+        self.view_state = ViewState::EditWiki;
+    }
+
+    fn get_my_follows(&self, session: &PubkySession) -> Vec<String> {
+        self.rt
+            .block_on(get_follows(session))
+            .inspect_err(|e| log::error!("Failed to get follows: {e}"))
+            .unwrap_or_default()
     }
 }
 
@@ -352,20 +636,113 @@ impl eframe::App for PubkyApp {
                             self.needs_refresh = false;
                         }
 
-                        let own_pk = session.info().public_key();
+                        let own_pk = session.info().public_key().z32();
+
+                        capabilities::show_request_window(self, ctx);
+                        trash::show_undo_toast(self, &session, ctx);
+                        export::show_export_window(self, &own_pk, pub_storage, ctx);
+                        import::show_import_window(self, &session, pub_storage, ctx);
+                        migrate::show_wizard(self, &session, pub_storage, ctx);
+
+                        if matches!(
+                            self.view_state,
+                            ViewState::WikiList | ViewState::ViewWiki | ViewState::Trash | ViewState::UserWiki | ViewState::Tags
+                        ) {
+                            history::handle_shortcuts(self, &session, pub_storage, ctx);
+                        }
 
                         // Show different views based on view_state
                         match self.view_state {
                             ViewState::WikiList => {
+                                history::show_nav_buttons(self, &session, pub_storage, ui);
+                                ui.add_space(10.0);
+                                ui.horizontal_wrapped(|ui| {
+                                    let create_button = ui.add_sized(
+                                        [200.0, 40.0],
+                                        egui::Button::new(egui::RichText::new("✨ Create New Wiki Page").size(16.0))
+                                    );
+                                    if create_button.clicked() {
+                                        self.navigate_to(ViewState::CreateWiki);
+                                    }
+
+                                    let trash_button = ui.add_sized(
+                                        [100.0, 40.0],
+                                        egui::Button::new(egui::RichText::new("🗑 Trash").size(16.0))
+                                    );
+                                    if trash_button.clicked() {
+                                        self.navigate_to(ViewState::Trash);
+                                    }
+
+                                    let browse_button = ui.add_sized(
+                                        [100.0, 40.0],
+                                        egui::Button::new(egui::RichText::new("👥 Browse").size(16.0))
+                                    ).on_hover_text("Browse the wiki of another user");
+                                    if browse_button.clicked() {
+                                        self.user_wiki_input.clear();
+                                        self.user_wiki = None;
+                                        self.navigate_to(ViewState::UserWiki);
+                                    }
+
+                                    let tags_button = ui.add_sized(
+                                        [100.0, 40.0],
+                                        egui::Button::new(egui::RichText::new("🏷 Tags").size(16.0))
+                                    ).on_hover_text("Browse the pages tagged by you and your follows");
+                                    if tags_button.clicked() {
+                                        self.navigate_to(ViewState::Tags);
+                                    }
+
+                                    let export_button = ui.add_sized(
+                                        [100.0, 40.0],
+                                        egui::Button::new(egui::RichText::new("📦 Export").size(16.0))
+                                    );
+                                    if export_button.clicked() {
+                                        let export_dir = export::default_export_dir(&own_pk);
+                                        self.export_dir = Some(export_dir.display().to_string());
+                                    }
+
+                                    let import_button = ui.add_sized(
+                                        [100.0, 40.0],
+                                        egui::Button::new(egui::RichText::new("📥 Import").size(16.0))
+                                    );
+                                    if import_button.clicked() {
+                                        self.import = Some(ImportState::default());
+                                    }
+
+                                    let migrate_button = ui.add_sized(
+                                        [100.0, 40.0],
+                                        egui::Button::new(egui::RichText::new("🚚 Move").size(16.0))
+                                    ).on_hover_text("Move your wiki to a new homeserver");
+                                    if migrate_button.clicked() {
+                                        migrate::open_wizard(self, &own_pk);
+                                    }
+                                });
+                                ui.add_space(15.0);
+
+                                capabilities::show_held(self, &session, ui);
                                 ui.add_space(10.0);
-                                let create_button = ui.add_sized(
-                                    [200.0, 40.0],
-                                    egui::Button::new(egui::RichText::new("✨ Create New Wiki Page").size(16.0))
-                                );
-                                if create_button.clicked() {
-                                    self.view_state = ViewState::CreateWiki;
+                                api::show_settings(self, ui);
+                                ui.add_space(10.0);
+
+                                if let Some(draft) = drafts::show_drafts(self, &session, ui) {
+                                    match draft.page_id.clone() {
+                                        Some(page_id) => {
+                                            let current = self.current_history_entry();
+                                            self.history.visit(current);
+                                            self.show_edit_wiki_page(&own_pk, &page_id, &session, pub_storage);
+                                        }
+                                        None => {
+                                            self.resume_new_page_draft(draft);
+                                            self.navigate_to(ViewState::CreateWiki);
+                                        }
+                                    }
                                 }
-                                ui.add_space(30.0);
+                                ui.add_space(20.0);
+
+                                ui.horizontal(|ui| {
+                                    ui.label("Signed in as");
+                                    show_user(self, ui, &own_pk, pub_storage, 24.0);
+                                });
+                                ui.add_space(10.0);
 
                                 ui.label(egui::RichText::new("My Wiki Posts").size(18.0).strong());
                                 ui.add_space(15.0);
@@ -376,11 +753,11 @@ impl eframe::App for PubkyApp {
                                         ui.add_space(10.0);
                                         ui.label(egui::RichText::new("No wiki posts yet. Create your first one!").italics().color(egui::Color32::GRAY));
                                     } else {
-                                        let pk = own_pk.to_string();
+                                        let pk = own_pk.clone();
                                         for (file_url, file_title) in file_cache {
                                             // Extract just the filename from the URL
                                             let file_name =
-                                                file_url.split('/').last().unwrap_or(file_url);
+                                                file_url.split('/').next_back().unwrap_or(file_url);
 
                                             ui.horizontal(|ui| {
                                                 if ui.button(egui::RichText::new(file_name).monospace()).clicked() {
@@ -399,11 +776,18 @@ impl eframe::App for PubkyApp {
                                     }
                                 });
                             }
-                            ViewState::CreateWiki => create_wiki::update(self, &session, ctx, ui),
-                            ViewState::EditWiki => edit_wiki::update(self, &session, ctx, ui),
+                            ViewState::CreateWiki => {
+                                create_wiki::update(self, &session, pub_storage, file_cache, ctx, ui)
+                            }
+                            ViewState::EditWiki => {
+                                edit_wiki::update(self, &session, pub_storage, file_cache, ctx, ui)
+                            }
                             ViewState::ViewWiki => {
-                                view_wiki::update(self, &session, &pub_storage, ctx, ui)
+                                view_wiki::update(self, &session, pub_storage, file_cache, ctx, ui)
                             }
+                            ViewState::Trash => trash::update(self, &session, pub_storage, ctx, ui),
+                            ViewState::UserWiki => user_wiki::update(self, &session, pub_storage, ui),
+                            ViewState::Tags => tags::update(self, &session, pub_storage, ui),
                         }
                     }
                     AuthState::Error(ref error) => {
@@ -417,28 +801,97 @@ impl eframe::App for PubkyApp {
     }
 }
 
-async fn initialize_auth() -> Result<(Pubky, PubkyAuthFlow, String)> {
+pub(crate) async fn initialize_auth(caps: &Capabilities) -> Result<(Pubky, PubkyAuthFlow, String)> {
     let pubky = Pubky::new()?;
-    let caps = Capabilities::builder().write("/pub/wiki.app/").finish();
-    let flow = pubky.start_auth_flow(&caps)?;
+    let flow = pubky.start_auth_flow(caps, AuthFlowKind::signin())?;
     let auth_url = flow.authorization_url().to_string();
 
     Ok((pubky, flow, auth_url))
 }
 
+/// Public keys of the users followed by the session's user
+pub(crate) async fn get_follows(session: &PubkySession) -> Result<Vec<String>> {
+    let storage = session.storage();
+    let list = list_all(|| storage.list("/pub/pubky.app/follows/"), false).await?;
+
+    Ok(list
+        .iter()
+        .map(|url| url.split('/').next_back().unwrap_or(url).to_string())
+        .collect())
+}
+
+/// Titles of the pages of the session's user's follows, by file URL
+async fn fetch_follows_file_cache(session: &PubkySession, pub_storage: &PublicStorage) -> HashMap<String, String> {
+    let follows = get_follows(session)
+        .await
+        .inspect_err(|e| log::error!("Failed to get follows: {e}"))
+        .unwrap_or_default();
+
+    let mut follows_file_cache = HashMap::new();
+    for follow_pk in follows {
+        let folder_url = format!("pubky://{follow_pk}/pub/wiki.app/");
+        let file_urls = list_pages(|| pub_storage.list(&folder_url))
+            .await
+            .inspect_err(|e| log::error!("Failed to list files of {follow_pk}: {e}"))
+            .unwrap_or_default();
+
+        for file_url in file_urls {
+            let content = async { anyhow::Ok(pub_storage.get(&file_url).await?.text().await?) }.await;
+            match content {
+                Ok(content) => {
+                    let file_title = extract_title(&content).to_string();
+                    follows_file_cache.insert(file_url, file_title);
+                }
+                Err(e) => log::error!("Error fetching path {file_url}: {e}"),
+            }
+        }
+    }
+    follows_file_cache
+}
+
+/// Versions of a page by the session's user and their follows, as `pk/page_id` links
+pub(crate) async fn discover_fork_urls(
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    page_id: &str,
+) -> Vec<String> {
+    let follows = get_follows(session)
+        .await
+        .inspect_err(|e| log::error!("Failed to get follows: {e}"))
+        .unwrap_or_default();
+
+    let mut result = vec![];
+
+    // Add the current user's version as a fork (root version)
+    let own_pk = session.info().public_key().z32();
+    result.push(format!("{own_pk}/{page_id}"));
+
+    for follow_pk in follows {
+        let fork_path = format!("pubky://{follow_pk}/pub/wiki.app/{page_id}");
+        log::info!("fork_path = {fork_path}");
+
+        match pub_storage.get(fork_path).await {
+            Ok(_) => result.push(format!("{follow_pk}/{page_id}")),
+            Err(e) => log::error!("Failed to check if file exists: {e}"),
+        }
+    }
+    result
+}
+
 pub(crate) async fn create_wiki_post(
     session: &PubkySession,
     content: &str,
     filename: Option<&str>,
 ) -> Result<String> {
-    let path = if let Some(fname) = filename {
-        format!("/pub/wiki.app/{}", fname)
-    } else {
-        format!("/pub/wiki.app/{}", Uuid::new_v4())
+    let page_id = match filename {
+        Some(fname) => fname.to_string(),
+        None => Uuid::new_v4().to_string(),
     };
+    let path = format!("/pub/wiki.app/{}", page_id);
 
     // Create the post with the provided content
     session.storage().put(&path, content.to_string()).await?;
+    keep_revision(session, &page_id, content).await;
 
     log::info!("Created post at path: {}", path);
 
@@ -454,12 +907,21 @@ pub(crate) async fn update_wiki_post(
 
     // Update the post with the provided content
     session.storage().put(&path, content.to_string()).await?;
+    keep_revision(session, page_id, content).await;
 
     log::info!("Updated post at path: {}", path);
 
     Ok(())
 }
 
+/// Add the saved content of a page to its history. The page itself is already saved, so a
+/// failure is only logged.
+async fn keep_revision(session: &PubkySession, page_id: &str, content: &str) {
+    if let Err(e) = revisions::save_revision(session, page_id, content).await {
+        log::error!("Failed to save a revision of {page_id}: {e}");
+    }
+}
+
 pub(crate) async fn delete_wiki_post(session: &PubkySession, page_id: &str) -> Result<()> {
     let path = format!("/pub/wiki.app/{}", page_id);
 
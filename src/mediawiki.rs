@@ -0,0 +1,859 @@
+//! Import of MediaWiki XML dumps, converting wikitext to markdown.
+//!
+//! Headings, links, lists, tables, references and the most common templates are converted.
+//! Only the pages of the main namespace are imported, with all their revisions in the dump:
+//! each one is recorded in the page history, dated from the dump, and the latest becomes the
+//! page. Redirects are not imported as pages, but links to them lead to their target.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use pubky_timestamp::Timestamp;
+use quick_xml::{escape::resolve_predefined_entity, events::Event, Reader, XmlVersion};
+use uuid::Uuid;
+
+use crate::{
+    export::Page,
+    import::{import_action, pages_by_title, ImportPlan, ImportedPage},
+    links::slugify,
+    utils::parse_rfc3339,
+};
+
+/// Namespaces whose links are not links to articles
+const FILE_NAMESPACES: [&str; 3] = ["file", "image", "media"];
+const CATEGORY_NAMESPACE: &str = "category";
+
+#[derive(Debug, Default)]
+struct DumpPage {
+    title: String,
+    namespace: String,
+    /// Title of the page this one redirects to
+    redirect: Option<String>,
+    revisions: Vec<Revision>,
+}
+
+#[derive(Debug, Default)]
+struct Revision {
+    timestamp: String,
+    text: String,
+}
+
+fn parse_dump(xml: &str) -> Result<Vec<DumpPage>> {
+    let mut reader = Reader::from_str(xml);
+
+    let mut pages = vec![];
+    let mut page: Option<DumpPage> = None;
+    let mut revision: Option<Revision> = None;
+    // Text of the element being read
+    let mut text: Option<String> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"page" => page = Some(DumpPage::default()),
+                b"revision" => revision = Some(Revision::default()),
+                b"title" | b"ns" | b"timestamp" | b"text" => text = Some(String::new()),
+                _ => {}
+            },
+            Event::Empty(e) if e.local_name().as_ref() == b"redirect" => {
+                if let (Some(page), Some(title)) = (page.as_mut(), e.try_get_attribute("title")?) {
+                    page.redirect =
+                        Some(title.normalized_value(XmlVersion::default())?.to_string());
+                }
+            }
+            Event::Text(e) => {
+                if let Some(text) = text.as_mut() {
+                    text.push_str(&e.decode()?);
+                }
+            }
+            Event::CData(e) => {
+                if let Some(text) = text.as_mut() {
+                    text.push_str(&e.decode()?);
+                }
+            }
+            Event::GeneralRef(e) => {
+                if let Some(text) = text.as_mut() {
+                    match e.resolve_char_ref()? {
+                        Some(c) => text.push(c),
+                        None => text
+                            .push_str(resolve_predefined_entity(&e.decode()?).unwrap_or_default()),
+                    }
+                }
+            }
+            Event::End(e) => {
+                let value = text.take().unwrap_or_default();
+                match (e.local_name().as_ref(), page.as_mut(), revision.as_mut()) {
+                    (b"title", Some(page), None) => page.title = value,
+                    (b"ns", Some(page), None) => page.namespace = value,
+                    (b"timestamp", _, Some(revision)) => revision.timestamp = value,
+                    (b"text", _, Some(revision)) => revision.text = value,
+                    (b"revision", Some(page), Some(_)) => page.revisions.extend(revision.take()),
+                    (b"page", Some(_), _) => pages.extend(page.take()),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Title as MediaWiki compares them: underscores as spaces, first letter in uppercase
+fn normalize_title(title: &str) -> String {
+    let title = title.replace('_', " ");
+    let mut chars = title.trim().chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Length of the `{{...}}` starting `text`, including nested templates
+fn template_len(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i + 1 < text.len() {
+        if text[i..].starts_with("{{") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("}}") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return Some(i);
+            }
+        } else {
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    None
+}
+
+/// Split on the `separator`s that are not within a `[[link]]`
+fn split_outside_links<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("[[") {
+            depth += 1;
+            i += 2;
+        } else if rest.starts_with("]]") {
+            depth -= 1;
+            i += 2;
+        } else if depth <= 0 && rest.starts_with(separator) {
+            parts.push(&text[start..i]);
+            i += separator.len();
+            start = i;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Replace templates, innermost first
+fn convert_templates(text: &str, page_title: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = template_len(&rest[start..]) else {
+            break;
+        };
+        let inner = convert_templates(&rest[start + 2..start + len - 2], page_title);
+
+        result.push_str(&rest[..start]);
+        result.push_str(&convert_template(&inner, page_title));
+        rest = &rest[start + len..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn convert_template(inner: &str, page_title: &str) -> String {
+    let parts = split_outside_links(inner, "|");
+    let name = parts[0].trim().to_lowercase();
+    let positional: Vec<&str> = parts[1..]
+        .iter()
+        .filter(|arg| !arg.contains('='))
+        .map(|arg| arg.trim())
+        .collect();
+    let named = |key: &str| {
+        parts[1..].iter().find_map(|arg| {
+            let (k, v) = arg.split_once('=')?;
+            (k.trim().eq_ignore_ascii_case(key)).then(|| v.trim())
+        })
+    };
+
+    match name.as_str() {
+        "pagename" => page_title.to_string(),
+        "reflist" | "notelist" | "toc" | "clear" | "-" => String::new(),
+        _ if name.starts_with("defaultsort:") => String::new(),
+        "main" | "see also" | "further" => {
+            let label = if name == "main" {
+                "Main article"
+            } else {
+                "See also"
+            };
+            let links: Vec<String> = positional
+                .iter()
+                .map(|title| format!("[[{title}]]"))
+                .collect();
+            format!("_{label}: {}_", links.join(", "))
+        }
+        "lang" => positional.get(1).copied().unwrap_or_default().to_string(),
+        "nowrap" | "small" | "big" => positional.first().copied().unwrap_or_default().to_string(),
+        "code" | "mono" => format!("`{}`", positional.first().copied().unwrap_or_default()),
+        "quote" | "blockquote" | "cquote" => {
+            let quote = named("text")
+                .or(positional.first().copied())
+                .unwrap_or_default();
+            format!("\n> {quote}\n")
+        }
+        _ if name.starts_with("cite") || name == "citation" => {
+            let title = named("title").unwrap_or("source");
+            match named("url") {
+                Some(url) => format!("[{title}]({url})"),
+                None => format!("*{title}*"),
+            }
+        }
+        // Keep unknown templates visible, rather than losing their content
+        _ => format!("`{{{{{}}}}}`", inner.replace('`', "'")),
+    }
+}
+
+/// Replace each `<tag ...>inner</tag>` or `<tag ... />` by `f(attributes, inner)`
+fn replace_tags(text: &str, tag: &str, mut f: impl FnMut(&str, Option<&str>) -> String) -> String {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        // Skip longer tag names sharing the prefix, such as <references> for <ref>
+        if !after.starts_with(['>', '/', ' ', '\t', '\n']) {
+            result.push_str(&rest[..start + open.len()]);
+            rest = after;
+            continue;
+        }
+        let Some(tag_end) = after.find('>') else {
+            break;
+        };
+        let attributes = &after[..tag_end];
+
+        if let Some(attributes) = attributes.strip_suffix('/') {
+            result.push_str(&rest[..start]);
+            result.push_str(&f(attributes, None));
+            rest = &after[tag_end + 1..];
+        } else {
+            let body = &after[tag_end + 1..];
+            let Some(len) = body.find(&close) else {
+                break;
+            };
+            result.push_str(&rest[..start]);
+            result.push_str(&f(attributes, Some(&body[..len])));
+            rest = &body[len + close.len()..];
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Value of an HTML attribute, quoted or not
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let start = attributes.find(&format!("{name}="))? + name.len() + 1;
+    let value = &attributes[start..];
+    match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next(),
+        _ => value.split_whitespace().next(),
+    }
+}
+
+/// Convert `<ref>` references into markdown footnotes, listed at the end
+fn convert_references(text: &str) -> String {
+    let mut notes: Vec<String> = vec![];
+    let mut numbers_by_name: HashMap<String, usize> = HashMap::new();
+
+    let text = replace_tags(text, "references", |_, _| String::new());
+    let mut text = replace_tags(&text, "ref", |attributes, inner| {
+        let name = attribute(attributes, "name").map(String::from);
+        let number = match (inner, &name) {
+            (Some(inner), _) => {
+                notes.push(inner.trim().replace('\n', " "));
+                notes.len()
+            }
+            (None, Some(name)) => match numbers_by_name.get(name) {
+                Some(number) => *number,
+                None => return String::new(),
+            },
+            (None, None) => return String::new(),
+        };
+        if let Some(name) = name {
+            numbers_by_name.entry(name).or_insert(number);
+        }
+        format!("[^{number}]")
+    });
+
+    if !notes.is_empty() {
+        text.truncate(text.trim_end().len());
+        text.push_str("\n\n");
+        for (i, note) in notes.iter().enumerate() {
+            text.push_str(&format!("[^{}]: {note}\n", i + 1));
+        }
+    }
+    text
+}
+
+/// Wikitext to markdown converter for the pages of a dump
+struct Converter<'a> {
+    /// Page ID of each imported title, including redirects
+    page_ids: &'a HashMap<String, String>,
+    /// Titles of the linked pages that are not imported
+    unresolved_links: Vec<String>,
+}
+
+impl Converter<'_> {
+    fn convert(&mut self, wikitext: &str, page_title: &str) -> String {
+        let mut text = convert_templates(wikitext, page_title);
+
+        // Comments and behavior switches
+        while let Some(start) = text.find("<!--") {
+            let end = text[start..]
+                .find("-->")
+                .map_or(text.len(), |end| start + end + 3);
+            text.replace_range(start..end, "");
+        }
+        for magic_word in ["__TOC__", "__NOTOC__", "__FORCETOC__", "__NOEDITSECTION__"] {
+            text = text.replace(magic_word, "");
+        }
+
+        let text = convert_references(&text);
+        let text = replace_tags(&text, "nowiki", |_, inner| {
+            inner.unwrap_or_default().to_string()
+        });
+        let text = replace_tags(&text, "code", |_, inner| {
+            format!("`{}`", inner.unwrap_or_default())
+        });
+        let text = replace_tags(&text, "pre", |_, inner| {
+            format!(
+                "\n```\n{}\n```\n",
+                inner.unwrap_or_default().trim_matches('\n')
+            )
+        });
+        let text = replace_tags(&text, "syntaxhighlight", |attributes, inner| {
+            let lang = attribute(attributes, "lang").unwrap_or_default();
+            format!(
+                "\n```{lang}\n{}\n```\n",
+                inner.unwrap_or_default().trim_matches('\n')
+            )
+        });
+        let text = ["<br>", "<br/>", "<br />"]
+            .iter()
+            .fold(text, |text, br| text.replace(br, "\n"));
+
+        self.convert_blocks(&text).trim().to_string()
+    }
+
+    fn convert_blocks(&mut self, text: &str) -> String {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut result = vec![];
+        let mut in_code_block = false;
+        let mut i = 0;
+
+        while i < lines.len() {
+            let line = lines[i];
+            let trimmed = line.trim_start();
+
+            if trimmed.starts_with("```") {
+                in_code_block = !in_code_block;
+                result.push(line.to_string());
+            } else if in_code_block {
+                result.push(line.to_string());
+            } else if trimmed.starts_with("{|") {
+                let start = i + 1;
+                while i < lines.len() && !lines[i].trim_start().starts_with("|}") {
+                    i += 1;
+                }
+                result.push(self.convert_table(&lines[start..i.min(lines.len())]));
+            } else {
+                result.push(self.convert_line(line));
+            }
+            i += 1;
+        }
+
+        result.join("\n")
+    }
+
+    fn convert_line(&mut self, line: &str) -> String {
+        let trimmed = line.trim();
+
+        // Headings: == Title ==
+        if trimmed.len() > 2 && trimmed.starts_with('=') && trimmed.ends_with('=') {
+            let leading = trimmed.chars().take_while(|c| *c == '=').count();
+            let trailing = trimmed.chars().rev().take_while(|c| *c == '=').count();
+            let level = leading.min(trailing).min(6);
+            if trimmed.len() > 2 * level {
+                let title = &trimmed[level..trimmed.len() - level];
+                return format!(
+                    "{} {}",
+                    "#".repeat(level),
+                    self.convert_inline(title.trim())
+                );
+            }
+        }
+
+        if trimmed.len() >= 4 && trimmed.chars().all(|c| c == '-') {
+            return "---".into();
+        }
+
+        // Lists, indents and definitions: *, #, :, ;
+        let prefix: String = line.chars().take_while(|c| "*#:;".contains(*c)).collect();
+        let Some(marker) = prefix.chars().last() else {
+            return self.convert_inline(line);
+        };
+        let depth = prefix.len();
+        let rest = line[prefix.len()..].trim_start();
+
+        match marker {
+            '*' => format!("{}- {}", "  ".repeat(depth - 1), self.convert_inline(rest)),
+            '#' => format!(
+                "{}1. {}",
+                "   ".repeat(depth - 1),
+                self.convert_inline(rest)
+            ),
+            ':' => format!("{}{}", "> ".repeat(depth), self.convert_inline(rest)),
+            _ => match rest.split_once(" : ") {
+                Some((term, definition)) => format!(
+                    "**{}**: {}",
+                    self.convert_inline(term.trim()),
+                    self.convert_inline(definition.trim())
+                ),
+                None => format!("**{}**", self.convert_inline(rest)),
+            },
+        }
+    }
+
+    fn convert_table(&mut self, lines: &[&str]) -> String {
+        let mut caption = None;
+        let mut rows: Vec<Vec<String>> = vec![];
+        let mut row: Vec<String> = vec![];
+
+        for line in lines {
+            let line = line.trim();
+            if let Some(text) = line.strip_prefix("|+") {
+                caption = Some(self.convert_cell(text));
+            } else if line.starts_with("|-") {
+                if !row.is_empty() {
+                    rows.push(std::mem::take(&mut row));
+                }
+            } else if let Some(cells) = line.strip_prefix('!') {
+                for cell in split_outside_links(cells, "!!")
+                    .into_iter()
+                    .flat_map(|c| split_outside_links(c, "||"))
+                {
+                    row.push(self.convert_cell(cell));
+                }
+            } else if let Some(cells) = line.strip_prefix('|') {
+                for cell in split_outside_links(cells, "||") {
+                    row.push(self.convert_cell(cell));
+                }
+            } else if let Some(last_cell) = row.last_mut() {
+                // Continuation of a multi-line cell
+                let text = self.convert_inline(line);
+                last_cell.push(' ');
+                last_cell.push_str(text.trim());
+            }
+        }
+        if !row.is_empty() {
+            rows.push(row);
+        }
+
+        let width = rows.iter().map(Vec::len).max().unwrap_or_default();
+        if width == 0 {
+            return String::new();
+        }
+
+        let format_row = |row: &[String]| {
+            let cells: Vec<String> = (0..width)
+                .map(|i| {
+                    row.get(i)
+                        .map_or(String::new(), |cell| cell.replace('|', "\\|"))
+                })
+                .collect();
+            format!("| {} |", cells.join(" | "))
+        };
+
+        let mut result = vec![];
+        if let Some(caption) = caption {
+            result.push(format!("**{caption}**\n"));
+        }
+        result.push(format_row(&rows[0]));
+        result.push(format!("|{}", " --- |".repeat(width)));
+        result.extend(rows[1..].iter().map(|row| format_row(row)));
+        format!("\n{}\n", result.join("\n"))
+    }
+
+    /// Cell content, without its attributes as in `style="..." | content`
+    fn convert_cell(&mut self, cell: &str) -> String {
+        let parts = split_outside_links(cell, "|");
+        let content = match parts.as_slice() {
+            [attributes, content] if attributes.contains('=') => content,
+            _ => cell,
+        };
+        self.convert_inline(content.trim())
+    }
+
+    fn convert_inline(&mut self, text: &str) -> String {
+        let text = self.convert_internal_links(text);
+        let text = convert_external_links(&text);
+        text.replace("'''''", "***")
+            .replace("'''", "**")
+            .replace("''", "*")
+    }
+
+    fn convert_internal_links(&mut self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find("[[") {
+            let Some(len) = rest[start + 2..].find("]]") else {
+                break;
+            };
+            let inner = &rest[start + 2..start + 2 + len];
+            let after = &rest[start + 2 + len + 2..];
+            // Link trail: [[apple]]s is labelled "apples"
+            let trail_len: usize = after
+                .chars()
+                .take_while(|c| c.is_alphabetic())
+                .map(char::len_utf8)
+                .sum();
+
+            result.push_str(&rest[..start]);
+            result.push_str(&self.convert_internal_link(inner, &after[..trail_len]));
+            rest = &after[trail_len..];
+        }
+
+        result.push_str(rest);
+        result
+    }
+
+    fn convert_internal_link(&mut self, inner: &str, trail: &str) -> String {
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target.trim(), label.trim()),
+            None => (inner.trim(), inner.trim().trim_start_matches(':')),
+        };
+        let label = format!("{label}{trail}");
+        let target = target.trim_start_matches(':');
+
+        if let Some((namespace, name)) = target.split_once(':') {
+            let namespace = namespace.trim().to_lowercase();
+            if namespace == CATEGORY_NAMESPACE {
+                return String::new();
+            }
+            if FILE_NAMESPACES.contains(&namespace.as_str()) {
+                // Files are not imported: keep their caption
+                let caption = inner.rsplit('|').next().unwrap_or(name).trim();
+                return format!(
+                    "*[{}]*",
+                    if caption == target {
+                        name.trim()
+                    } else {
+                        caption
+                    }
+                );
+            }
+        }
+
+        let (title, section) = target.split_once('#').unwrap_or((target, ""));
+        let anchor = if section.is_empty() {
+            String::new()
+        } else {
+            format!("#{}", slugify(section))
+        };
+
+        if title.trim().is_empty() {
+            return format!("[{label}]({anchor})");
+        }
+
+        let title = normalize_title(title);
+        match self.page_ids.get(&title) {
+            Some(page_id) => format!("[{label}]({page_id}{anchor})"),
+            None => {
+                self.unresolved_links.push(format!("[[{title}]]"));
+                format!("[[{title}|{label}]]")
+            }
+        }
+    }
+}
+
+/// Convert `[https://example.com label]` links
+fn convert_external_links(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        let after = &rest[start + 1..];
+        let is_url = ["http://", "https://", "//"]
+            .iter()
+            .any(|scheme| after.starts_with(scheme));
+        let end = after.find(']').filter(|_| is_url);
+        let Some(end) = end else {
+            result.push_str(&rest[..start + 1]);
+            rest = after;
+            continue;
+        };
+
+        result.push_str(&rest[..start]);
+        match after[..end].split_once(' ') {
+            Some((url, label)) => result.push_str(&format!("[{}]({url})", label.trim())),
+            None => result.push_str(&format!("<{}>", &after[..end])),
+        }
+        rest = &after[end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Plan the import of the articles of a MediaWiki XML dump
+pub(crate) fn plan_import(path: &Path, existing_pages: &[Page]) -> Result<ImportPlan> {
+    let dump_pages = parse_dump(&fs::read_to_string(path)?)?;
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let existing_by_title = pages_by_title(existing_pages);
+
+    let (articles, other_pages): (Vec<DumpPage>, Vec<DumpPage>) = dump_pages
+        .into_iter()
+        .partition(|page| page.namespace.is_empty() || page.namespace == "0");
+    let (redirects, articles): (Vec<DumpPage>, Vec<DumpPage>) = articles
+        .into_iter()
+        .partition(|page| page.redirect.is_some());
+
+    // Page ID of each title, reusing the existing pages with the same title
+    let mut page_ids: HashMap<String, String> = HashMap::new();
+    for article in &articles {
+        let title = normalize_title(&article.title);
+        let page_id = existing_by_title
+            .get(&title.to_lowercase())
+            .map(|page| page.page_id.clone())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        page_ids.insert(title, page_id);
+    }
+    for redirect in &redirects {
+        let target = normalize_title(redirect.redirect.as_deref().unwrap_or_default());
+        let target = target.split('#').next().unwrap_or_default();
+        if let Some(page_id) = page_ids.get(target).cloned() {
+            page_ids.insert(normalize_title(&redirect.title), page_id);
+        }
+    }
+
+    let mut older_revisions = 0;
+    let mut result = vec![];
+    for article in &articles {
+        let title = normalize_title(&article.title);
+        let mut dump_revisions: Vec<&Revision> = article.revisions.iter().collect();
+        dump_revisions.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        if dump_revisions.is_empty() {
+            continue;
+        }
+        older_revisions += dump_revisions.len() - 1;
+
+        let mut revisions = vec![];
+        let mut unresolved_links = vec![];
+        let mut previous_micros = 0;
+        for revision in dump_revisions {
+            // Revisions without a valid date, or saved within the same second, follow the previous one
+            let micros = parse_rfc3339(&revision.timestamp).unwrap_or_default() * 1_000_000;
+            let micros = micros.max(previous_micros + 1);
+            previous_micros = micros;
+
+            let mut converter = Converter {
+                page_ids: &page_ids,
+                unresolved_links: vec![],
+            };
+            let content = format!(
+                "# {title}\n\n{}\n",
+                converter.convert(&revision.text, &title)
+            );
+            // Only the links of the latest revision are reported
+            unresolved_links = converter.unresolved_links;
+            revisions.push((Timestamp::from(micros).to_string(), content));
+        }
+
+        let content = revisions
+            .last()
+            .map(|(_, content)| content.clone())
+            .unwrap_or_default();
+        let existing_page = existing_by_title.get(&title.to_lowercase()).copied();
+
+        result.push(ImportedPage {
+            source: format!("{file_name}: {title}"),
+            title: title.clone(),
+            page_id: page_ids[&title].clone(),
+            action: import_action(existing_page, &content),
+            content,
+            revisions,
+            unresolved_links,
+        });
+    }
+
+    let mut notes = vec![];
+    if !redirects.is_empty() {
+        notes.push(format!(
+            "{} redirects not imported, links to them lead to their target",
+            redirects.len()
+        ));
+    }
+    if !other_pages.is_empty() {
+        notes.push(format!(
+            "{} pages of other namespaces (talk pages, templates, files...) not imported",
+            other_pages.len()
+        ));
+    }
+    if older_revisions > 0 {
+        notes.push(format!(
+            "{older_revisions} older revisions imported into the page histories"
+        ));
+    }
+
+    Ok(ImportPlan {
+        pages: result,
+        notes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(wikitext: &str) -> (String, Vec<String>) {
+        let page_ids = HashMap::from([
+            ("Lugano".to_string(), "id-1".to_string()),
+            ("Ticino".to_string(), "id-2".to_string()),
+        ]);
+        let mut converter = Converter {
+            page_ids: &page_ids,
+            unresolved_links: vec![],
+        };
+        let markdown = converter.convert(wikitext, "Test");
+        (markdown, converter.unresolved_links)
+    }
+
+    #[test]
+    fn parses_dump() {
+        let xml = r#"<mediawiki><siteinfo><sitename>Wiki</sitename></siteinfo>
+            <page><title>Lugano</title><ns>0</ns>
+              <revision><timestamp>2020-01-01T00:00:00Z</timestamp><text>Old &amp; new</text></revision>
+              <revision><timestamp>2021-01-01T00:00:00Z</timestamp><text xml:space="preserve">New</text></revision>
+            </page>
+            <page><title>Lugan</title><ns>0</ns><redirect title="Lugano" />
+              <revision><timestamp>2020-01-01T00:00:00Z</timestamp><text>#REDIRECT [[Lugano]]</text></revision>
+            </page></mediawiki>"#;
+        let pages = parse_dump(xml).unwrap();
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].title, "Lugano");
+        assert_eq!(pages[0].namespace, "0");
+        assert_eq!(pages[0].revisions[0].text, "Old & new");
+        assert_eq!(pages[0].revisions[1].timestamp, "2021-01-01T00:00:00Z");
+        assert_eq!(pages[1].redirect.as_deref(), Some("Lugano"));
+    }
+
+    #[test]
+    fn imports_revisions_oldest_first() {
+        let path = std::env::temp_dir().join(format!("pubky-wiki-dump-{}.xml", std::process::id()));
+        fs::write(
+            &path,
+            r#"<mediawiki><page><title>Lugano</title><ns>0</ns>
+              <revision><timestamp>2021-01-01T00:00:00Z</timestamp><text>New</text></revision>
+              <revision><timestamp>2020-01-01T00:00:00Z</timestamp><text>Old</text></revision>
+              <revision><timestamp>2021-01-01T00:00:00Z</timestamp><text>Newest</text></revision>
+            </page></mediawiki>"#,
+        )
+        .unwrap();
+        let plan = plan_import(&path, &[]).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let page = &plan.pages[0];
+        let dates: Vec<u64> = page
+            .revisions
+            .iter()
+            .map(|(revision_id, _)| Timestamp::try_from(revision_id.clone()).unwrap().as_u64())
+            .collect();
+        assert_eq!(
+            dates,
+            [1577836800000000, 1609459200000000, 1609459200000001]
+        );
+        let contents: Vec<&str> = page
+            .revisions
+            .iter()
+            .map(|(_, content)| content.as_str())
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                "# Lugano\n\nOld\n",
+                "# Lugano\n\nNew\n",
+                "# Lugano\n\nNewest\n"
+            ]
+        );
+        assert_eq!(page.content, "# Lugano\n\nNewest\n");
+        assert_eq!(
+            plan.notes,
+            ["2 older revisions imported into the page histories"]
+        );
+    }
+
+    #[test]
+    fn converts_blocks() {
+        let (markdown, _) =
+            convert("== History ==\n* one\n** two\n# first\n; term : definition\n----");
+        assert_eq!(
+            markdown,
+            "## History\n- one\n  - two\n1. first\n**term**: definition\n---"
+        );
+    }
+
+    #[test]
+    fn converts_inline() {
+        let (markdown, unresolved) = convert(
+            "'''Lugano''' is in [[ticino|the canton]], near [[Lake Lugano]]s. [[Lugano#History]] [https://lugano.ch site] ''x'' [[Category:Cities]]",
+        );
+        assert_eq!(
+            markdown,
+            "**Lugano** is in [the canton](id-2), near [[Lake Lugano|Lake Luganos]]. [Lugano#History](id-1#history) [site](https://lugano.ch) *x*"
+        );
+        assert_eq!(unresolved, vec!["[[Lake Lugano]]"]);
+    }
+
+    #[test]
+    fn converts_tables() {
+        let (markdown, _) = convert("{| class=\"wikitable\"\n|+ Cities\n! Name !! Canton\n|-\n| [[Lugano]] || style=\"x\" | TI\n|}");
+        assert_eq!(
+            markdown,
+            "**Cities**\n\n| Name | Canton |\n| --- | --- |\n| [Lugano](id-1) | TI |"
+        );
+    }
+
+    #[test]
+    fn converts_templates_and_references() {
+        let (markdown, _) = convert(
+            "{{Main|Ticino}} In {{PAGENAME}}.<ref name=\"a\">{{cite web|url=https://x.org|title=X}}</ref> Again<ref name=\"a\"/> {{Infobox city|name=L}}\n<references />",
+        );
+        assert_eq!(
+            markdown,
+            "_Main article: [Ticino](id-2)_ In Test.[^1] Again[^1] `{{Infobox city|name=L}}`\n\n[^1]: [X](https://x.org)"
+        );
+    }
+}
@@ -0,0 +1,620 @@
+//! Migration of a user's wiki to a new homeserver.
+//!
+//! Every file under `/pub/wiki.app/` is first saved locally with its SHA-256 hash, in
+//! `<local data dir>/pubky-wiki/migrations/<user pk>/`. The user then signs up on the new
+//! homeserver, where the files are written at the same paths, so page IDs and `pk/id` links
+//! keep working, and read back to verify their hash. The local copy is only removed once the
+//! new homeserver lists every file, and lets an interrupted migration resume, even once the
+//! user's key already points to the new homeserver.
+
+use std::{collections::HashSet, fs, path::PathBuf, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use eframe::egui::Context;
+use pubky::{
+    AuthFlowKind, Capabilities, Pubky, PubkyAuthFlow, PubkySession, PublicKey, PublicStorage,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::runtime::Runtime;
+
+use crate::{
+    capabilities::initial_capabilities,
+    utils::{generate_qr_image, get_public_list, list_all, now_timestamp},
+    AuthState, PubkyApp,
+};
+
+const MIGRATION_FILE: &str = "migration.json";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct MigratedFile {
+    /// Absolute path of the file on the homeserver, such as `/pub/wiki.app/<page id>`
+    pub(crate) path: String,
+    pub(crate) sha256: String,
+    pub(crate) copied: bool,
+    pub(crate) verified: bool,
+}
+
+/// A migration in progress, saved after each step so it can be resumed
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Migration {
+    pub(crate) user_pk: String,
+    pub(crate) new_homeserver: String,
+    /// Unix timestamp (seconds) of the start of the migration
+    pub(crate) started_at: u64,
+    pub(crate) files: Vec<MigratedFile>,
+}
+
+impl Migration {
+    pub(crate) fn copied_count(&self) -> usize {
+        self.files.iter().filter(|file| file.copied).count()
+    }
+}
+
+/// Step of the migration wizard
+#[derive(Clone, Default)]
+pub(crate) enum MigrationState {
+    #[default]
+    Closed,
+    Setup {
+        new_homeserver: String,
+        signup_token: String,
+        /// Copied and total files of an interrupted migration
+        interrupted: Option<(usize, usize)>,
+    },
+    Saving {
+        done: usize,
+        total: usize,
+    },
+    ShowingQR {
+        auth_url: String,
+    },
+    Copying {
+        done: usize,
+        total: usize,
+    },
+    Verifying {
+        done: usize,
+        total: usize,
+    },
+    Done {
+        files: usize,
+    },
+    Error(String),
+}
+
+pub(crate) fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Absolute path of a `pubky://<pk>/pub/...` URL
+fn url_path(url: &str) -> Option<&str> {
+    url.find("/pub/").map(|start| &url[start..])
+}
+
+fn migration_dir(user_pk: &str) -> Result<PathBuf> {
+    let data_dir = dirs::data_local_dir().ok_or(anyhow!("No local data directory"))?;
+    Ok(data_dir.join("pubky-wiki").join("migrations").join(user_pk))
+}
+
+fn local_file_path(user_pk: &str, sha256: &str) -> Result<PathBuf> {
+    Ok(migration_dir(user_pk)?.join("files").join(sha256))
+}
+
+fn save_migration(migration: &Migration) -> Result<()> {
+    let dir = migration_dir(&migration.user_pk)?;
+    fs::create_dir_all(&dir)?;
+    fs::write(
+        dir.join(MIGRATION_FILE),
+        serde_json::to_string_pretty(migration)?,
+    )?;
+    Ok(())
+}
+
+/// The interrupted migration of a user, if any
+pub(crate) fn load_migration(user_pk: &str) -> Option<Migration> {
+    let path = migration_dir(user_pk).ok()?.join(MIGRATION_FILE);
+    let json = fs::read_to_string(path).ok()?;
+
+    serde_json::from_str(&json)
+        .inspect_err(|e| log::error!("Invalid migration of {user_pk}: {e}"))
+        .ok()
+}
+
+/// Remove the local copy of a completed migration
+fn finish_migration(user_pk: &str) -> Result<()> {
+    fs::remove_dir_all(migration_dir(user_pk)?)?;
+    Ok(())
+}
+
+/// Save all the files of the user's wiki locally, from the current homeserver
+pub(crate) fn save_files(
+    pub_storage: &PublicStorage,
+    user_pk: &str,
+    new_homeserver: &str,
+    rt: Arc<Runtime>,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<Migration> {
+    let folder_url = format!("pubky://{user_pk}/pub/wiki.app/");
+    let file_urls = get_public_list(pub_storage, &folder_url, rt.clone())?;
+
+    let mut migration = Migration {
+        user_pk: user_pk.to_string(),
+        new_homeserver: new_homeserver.to_string(),
+        started_at: now_timestamp(),
+        files: vec![],
+    };
+
+    for (i, file_url) in file_urls.iter().enumerate() {
+        on_progress(i, file_urls.len());
+        let Some(path) = url_path(file_url) else {
+            continue;
+        };
+
+        let bytes = rt.block_on(async {
+            pub_storage
+                .get(file_url)
+                .await?
+                .bytes()
+                .await
+                .map_err(anyhow::Error::from)
+        })?;
+        let sha256 = sha256(&bytes);
+        let local_path = local_file_path(user_pk, &sha256)?;
+        if let Some(parent) = local_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(local_path, &bytes)?;
+
+        migration.files.push(MigratedFile {
+            path: path.to_string(),
+            sha256,
+            copied: false,
+            verified: false,
+        });
+    }
+    save_migration(&migration)?;
+
+    log::info!(
+        "Saved {} files to migrate for {user_pk}",
+        migration.files.len()
+    );
+
+    Ok(migration)
+}
+
+/// Write the files not copied yet to the homeserver of the session
+pub(crate) fn copy_files(
+    session: &PubkySession,
+    migration: &mut Migration,
+    rt: Arc<Runtime>,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<()> {
+    let total = migration.files.len();
+
+    for i in 0..total {
+        on_progress(i, total);
+        if migration.files[i].copied {
+            continue;
+        }
+
+        let file = &migration.files[i];
+        let bytes = fs::read(local_file_path(&migration.user_pk, &file.sha256)?)?;
+        rt.block_on(session.storage().put(&file.path, bytes))?;
+
+        migration.files[i].copied = true;
+        save_migration(migration)?;
+    }
+
+    Ok(())
+}
+
+/// Read back the copied files and compare their hash, marking the differing ones to be copied again
+pub(crate) fn verify_files(
+    session: &PubkySession,
+    migration: &mut Migration,
+    rt: Arc<Runtime>,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<()> {
+    let total = migration.files.len();
+    let mut mismatches = vec![];
+
+    for (i, file) in migration.files.iter_mut().enumerate() {
+        on_progress(i, total);
+        if file.verified {
+            continue;
+        }
+
+        let bytes = rt.block_on(async {
+            session
+                .storage()
+                .get(&file.path)
+                .await?
+                .bytes()
+                .await
+                .map_err(anyhow::Error::from)
+        });
+        match bytes {
+            Ok(bytes) if sha256(&bytes) == file.sha256 => file.verified = true,
+            Ok(_) => {
+                file.copied = false;
+                mismatches.push(file.path.clone());
+            }
+            Err(e) => {
+                log::error!("Failed to read back {}: {e}", file.path);
+                file.copied = false;
+                mismatches.push(file.path.clone());
+            }
+        }
+    }
+    save_migration(migration)?;
+
+    if !mismatches.is_empty() {
+        bail!(
+            "{} files differ on the new homeserver ({}), run the migration again to copy them again",
+            mismatches.len(),
+            mismatches.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Paths of the saved files that a listing of the new homeserver doesn't have
+fn missing_files<'a>(migration: &'a Migration, listed_urls: &[String]) -> Vec<&'a str> {
+    let listed_paths: HashSet<&str> = listed_urls.iter().filter_map(|url| url_path(url)).collect();
+
+    migration
+        .files
+        .iter()
+        .map(|file| file.path.as_str())
+        .filter(|path| !listed_paths.contains(path))
+        .collect()
+}
+
+/// List the files on the new homeserver, to make sure that every saved file is there before the
+/// local copy is removed. The missing ones are marked to be copied again.
+pub(crate) fn check_listing(
+    session: &PubkySession,
+    migration: &mut Migration,
+    rt: Arc<Runtime>,
+) -> Result<()> {
+    let storage = session.storage();
+    let listed_urls = rt.block_on(list_all(|| storage.list("/pub/wiki.app/"), false))?;
+
+    let missing: Vec<String> = missing_files(migration, &listed_urls)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    for file in migration
+        .files
+        .iter_mut()
+        .filter(|file| missing.contains(&file.path))
+    {
+        file.copied = false;
+        file.verified = false;
+    }
+    save_migration(migration)?;
+
+    bail!(
+        "{} of {} files are not listed on the new homeserver ({}), run the migration again to copy them again",
+        missing.len(),
+        migration.files.len(),
+        missing.join(", ")
+    );
+}
+
+/// Current homeserver of a user, as published for their key
+pub(crate) async fn homeserver_of(user_pk: &PublicKey) -> Result<Option<PublicKey>> {
+    Ok(Pubky::new()?.get_homeserver_of(user_pk).await)
+}
+
+/// Start an auth flow signing the user up on `homeserver`, which then becomes their homeserver
+pub(crate) fn initialize_signup(
+    caps: &Capabilities,
+    homeserver: &PublicKey,
+    signup_token: Option<String>,
+) -> Result<(PubkyAuthFlow, String)> {
+    let pubky = Pubky::new()?;
+    let flow =
+        pubky.start_auth_flow(caps, AuthFlowKind::signup(homeserver.clone(), signup_token))?;
+    let auth_url = flow.authorization_url().to_string();
+
+    Ok((flow, auth_url))
+}
+
+impl PubkyApp {
+    /// Run the migration in the background, reporting its progress in the wizard
+    fn start_migration(
+        &mut self,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
+        new_homeserver: PublicKey,
+        signup_token: Option<String>,
+    ) {
+        self.migration_qr_texture = None;
+        *self.migration.lock().unwrap() = MigrationState::Saving { done: 0, total: 0 };
+
+        let migration_state = self.migration.clone();
+        let state = self.state.clone();
+        let rt = self.rt.clone();
+        let session = session.clone();
+        let pub_storage = pub_storage.clone();
+        std::thread::spawn(move || {
+            let set_state = |step| *migration_state.lock().unwrap() = step;
+            let user_pk = session.info().public_key().clone();
+
+            let outcome = migrate(
+                &user_pk,
+                &pub_storage,
+                &new_homeserver,
+                rt.clone(),
+                set_state,
+                |already_moved| {
+                    // After an interruption, the session may already be on the new homeserver
+                    if already_moved {
+                        return Ok(session);
+                    }
+
+                    let (flow, auth_url) =
+                        initialize_signup(&initial_capabilities(), &new_homeserver, signup_token)?;
+                    set_state(MigrationState::ShowingQR { auth_url });
+
+                    let new_session = rt.block_on(flow.await_approval())?;
+                    if let AuthState::Authenticated {
+                        ref mut session, ..
+                    } = *state.lock().unwrap()
+                    {
+                        *session = new_session.clone();
+                    }
+                    Ok(new_session)
+                },
+            );
+            match outcome {
+                Ok(files) => set_state(MigrationState::Done { files }),
+                Err(e) => {
+                    log::error!("Migration failed: {e}");
+                    set_state(MigrationState::Error(e.to_string()));
+                }
+            }
+        });
+    }
+}
+
+/// Migrate the wiki of a user to `new_homeserver`, resuming the interrupted migration if any.
+///
+/// `session_on_new_homeserver` is given whether the user's key already points to the new
+/// homeserver, and returns a session on it, signing the user up if needed.
+pub(crate) fn migrate(
+    user_pk: &PublicKey,
+    pub_storage: &PublicStorage,
+    new_homeserver: &PublicKey,
+    rt: Arc<Runtime>,
+    on_progress: impl Fn(MigrationState),
+    session_on_new_homeserver: impl FnOnce(bool) -> Result<PubkySession>,
+) -> Result<usize> {
+    let pk = user_pk.z32();
+    let already_moved = rt.block_on(homeserver_of(user_pk))?.as_ref() == Some(new_homeserver);
+
+    let mut migration = match load_migration(&pk) {
+        Some(migration) if migration.new_homeserver == new_homeserver.z32() => migration,
+        _ if already_moved => bail!("The wiki is already hosted on {new_homeserver}"),
+        _ => save_files(
+            pub_storage,
+            &pk,
+            &new_homeserver.z32(),
+            rt.clone(),
+            |done, total| on_progress(MigrationState::Saving { done, total }),
+        )?,
+    };
+
+    let session = session_on_new_homeserver(already_moved)?;
+    if session.info().public_key() != user_pk {
+        bail!("Signed in with a different key than {pk}");
+    }
+
+    copy_files(&session, &mut migration, rt.clone(), |done, total| {
+        on_progress(MigrationState::Copying { done, total })
+    })?;
+    verify_files(&session, &mut migration, rt.clone(), |done, total| {
+        on_progress(MigrationState::Verifying { done, total })
+    })?;
+    check_listing(&session, &mut migration, rt)?;
+    finish_migration(&pk)?;
+
+    log::info!(
+        "Migrated {} files to {new_homeserver}",
+        migration.files.len()
+    );
+
+    Ok(migration.files.len())
+}
+
+/// Open the wizard, offering to resume an interrupted migration
+pub(crate) fn open_wizard(app: &mut PubkyApp, user_pk: &str) {
+    let interrupted = load_migration(user_pk);
+
+    *app.migration.lock().unwrap() = MigrationState::Setup {
+        new_homeserver: interrupted
+            .as_ref()
+            .map(|migration| migration.new_homeserver.clone())
+            .unwrap_or_default(),
+        signup_token: String::new(),
+        interrupted: interrupted.map(|migration| (migration.copied_count(), migration.files.len())),
+    };
+}
+
+/// Show the migration wizard, if open
+pub(crate) fn show_wizard(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    ctx: &Context,
+) {
+    let mut state = app.migration.lock().unwrap().clone();
+    let is_running = matches!(
+        state,
+        MigrationState::Saving { .. }
+            | MigrationState::Copying { .. }
+            | MigrationState::Verifying { .. }
+    );
+    let is_showing_qr = matches!(state, MigrationState::ShowingQR { .. });
+    let mut is_open = true;
+    let mut start = None;
+
+    let mut window = egui::Window::new("Move to a New Homeserver")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0]);
+    // Files are being transferred: the wizard stays open until they are all copied and verified
+    if !is_running {
+        window = window.open(&mut is_open);
+    }
+    window.show(ctx, |ui| match &mut state {
+        MigrationState::Closed => {}
+        MigrationState::Setup {
+            new_homeserver,
+            signup_token,
+            interrupted,
+        } => {
+            ui.label("Copy all your pages to a new homeserver. Page IDs stay the same, so links to them keep working.");
+            ui.add_space(10.0);
+            if let Some((copied, total)) = interrupted {
+                ui.label(
+                    egui::RichText::new(format!("A migration was interrupted after copying {copied} of {total} files, it will resume."))
+                        .color(egui::Color32::from_rgb(200, 150, 50)),
+                );
+                ui.add_space(5.0);
+            }
+
+            ui.label("Public key of the new homeserver:");
+            ui.text_edit_singleline(new_homeserver);
+            ui.label("Signup token, if the homeserver requires one:");
+            ui.text_edit_singleline(signup_token);
+            ui.add_space(10.0);
+
+            let new_homeserver_pk = new_homeserver.trim().parse::<PublicKey>();
+            if ui
+                .add_enabled(new_homeserver_pk.is_ok(), egui::Button::new("🚚 Start"))
+                .clicked()
+            {
+                let token = Some(signup_token.trim().to_string()).filter(|t| !t.is_empty());
+                start = new_homeserver_pk.ok().map(|pk| (pk, token));
+            }
+        }
+        MigrationState::Saving { done, total } => {
+            ui.label(format!("Saving your files locally: {done} of {total}"));
+            ui.spinner();
+        }
+        MigrationState::ShowingQR { auth_url } => {
+            if app.migration_qr_texture.is_none() {
+                if let Some(qr_image) = generate_qr_image(auth_url) {
+                    app.migration_qr_texture =
+                        Some(ctx.load_texture("migration_qr_code", qr_image, Default::default()));
+                }
+            }
+            ui.label("Scan this QR code with your Pubky app to sign up on the new homeserver:");
+            ui.add_space(10.0);
+            if let Some(texture) = &app.migration_qr_texture {
+                ui.add(egui::Image::from_texture(texture).max_size(egui::vec2(250.0, 250.0)));
+            }
+        }
+        MigrationState::Copying { done, total } => {
+            ui.label(format!("Copying files: {done} of {total}"));
+            ui.add(egui::ProgressBar::new(*done as f32 / (*total).max(1) as f32));
+        }
+        MigrationState::Verifying { done, total } => {
+            ui.label(format!("Verifying files: {done} of {total}"));
+            ui.add(egui::ProgressBar::new(*done as f32 / (*total).max(1) as f32));
+        }
+        MigrationState::Done { files } => {
+            ui.label(format!("✓ Migrated and verified {files} files."));
+        }
+        MigrationState::Error(error) => {
+            ui.colored_label(egui::Color32::RED, error.as_str());
+            ui.label("Open the migration again to resume it.");
+        }
+    });
+
+    if !is_open {
+        app.migration_qr_texture = None;
+        *app.migration.lock().unwrap() = MigrationState::Closed;
+        app.needs_refresh = true;
+        return;
+    }
+
+    let mut current = app.migration.lock().unwrap();
+    if let (MigrationState::Setup { .. }, MigrationState::Setup { .. }) = (&*current, &state) {
+        *current = state;
+    }
+    drop(current);
+
+    if let Some((new_homeserver, signup_token)) = start {
+        app.start_migration(session, pub_storage, new_homeserver, signup_token);
+    }
+    // Repaint to follow the progress of the background thread
+    if is_running || is_showing_qr {
+        ctx.request_repaint_after(std::time::Duration::from_millis(200));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_content() {
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn keeps_paths() {
+        assert_eq!(
+            url_path(
+                "pubky://6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y/pub/wiki.app/id-1"
+            ),
+            Some("/pub/wiki.app/id-1")
+        );
+        assert_eq!(
+            url_path("pubky://pk/pub/wiki.app/.trash/id-1"),
+            Some("/pub/wiki.app/.trash/id-1")
+        );
+        assert_eq!(url_path("https://example.com"), None);
+    }
+
+    #[test]
+    fn finds_files_missing_from_the_listing() {
+        let file = |path: &str| MigratedFile {
+            path: path.to_string(),
+            sha256: String::new(),
+            copied: true,
+            verified: true,
+        };
+        let migration = Migration {
+            user_pk: "pk".into(),
+            new_homeserver: "hs".into(),
+            started_at: 0,
+            files: vec![
+                file("/pub/wiki.app/id-1"),
+                file("/pub/wiki.app/.revisions/id-1/0000"),
+            ],
+        };
+
+        assert_eq!(
+            missing_files(&migration, &["pubky://pk/pub/wiki.app/id-1".to_string()]),
+            ["/pub/wiki.app/.revisions/id-1/0000"]
+        );
+        let listed = [
+            "pubky://pk/pub/wiki.app/.revisions/id-1/0000",
+            "pubky://pk/pub/wiki.app/id-1",
+        ]
+        .map(String::from);
+        assert!(missing_files(&migration, &listed).is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+//! Announcing new pages and forks as pubky.app posts, so followers see them in their feed.
+
+use anyhow::Result;
+use pubky::PubkySession;
+use pubky_timestamp::Timestamp;
+use serde::Serialize;
+
+/// Post of the pubky.app data model, stored at `/pub/pubky.app/posts/<timestamp id>`
+#[derive(Serialize)]
+struct Post {
+    content: String,
+    kind: &'static str,
+    parent: Option<String>,
+    embed: Option<String>,
+    attachments: Option<Vec<String>>,
+}
+
+/// Text of the post announcing a page, or a fork of the page at `forked_from`
+pub(crate) fn announcement(title: &str, page_url: &str, forked_from: Option<&str>) -> String {
+    let title = match title.trim() {
+        "" => "Untitled",
+        title => title,
+    };
+    match forked_from {
+        Some(original_url) => format!("🍴 Forked a wiki page: {title}\n{page_url}\n\nOriginal: {original_url}"),
+        None => format!("📝 New wiki page: {title}\n{page_url}"),
+    }
+}
+
+/// Write a short pubky.app post, returning its path
+pub(crate) async fn announce(session: &PubkySession, content: &str) -> Result<String> {
+    let post = Post {
+        content: content.to_string(),
+        kind: "short",
+        parent: None,
+        embed: None,
+        attachments: None,
+    };
+    let path = format!("/pub/pubky.app/posts/{}", Timestamp::now());
+    session.storage().put(&path, serde_json::to_string(&post)?).await?;
+
+    log::info!("Announced at path: {path}");
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_announcements() {
+        let url = "pubky://6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y/pub/wiki.app/id-1";
+        assert_eq!(announcement(" Lugano ", url, None), format!("📝 New wiki page: Lugano\n{url}"));
+        assert_eq!(
+            announcement("", url, Some("pubky://pk/pub/wiki.app/id-1")),
+            format!("🍴 Forked a wiki page: Untitled\n{url}\n\nOriginal: pubky://pk/pub/wiki.app/id-1")
+        );
+
+        let post = Post {
+            content: "Hi".into(),
+            kind: "short",
+            parent: None,
+            embed: None,
+            attachments: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&post).unwrap(),
+            r#"{"content":"Hi","kind":"short","parent":null,"embed":null,"attachments":null}"#
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Profiles of the users, to show their name, avatar and bio instead of their public key.
+//!
+//! Profiles are read from `/pub/pubky.app/profile.json`, as written by pubky.app, in the
+//! background on first use and cached for the rest of the session.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use eframe::egui::{self, ColorImage, Context, TextureHandle, Ui};
+use pubky::PublicStorage;
+use serde::Deserialize;
+use tokio::runtime::Runtime;
+
+use crate::{user_wiki::parse_user_pk, PubkyApp};
+
+/// Size of the avatars, in pixels
+const AVATAR_SIZE: u32 = 64;
+
+#[derive(Clone, Deserialize)]
+pub(crate) struct Profile {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) bio: Option<String>,
+    /// URL of the pubky.app file object of the avatar
+    #[serde(default)]
+    pub(crate) image: Option<String>,
+}
+
+/// File object of pubky.app, pointing to the blob with the file content
+#[derive(Deserialize)]
+struct FileObject {
+    src: String,
+}
+
+enum ProfileState {
+    Loading,
+    Loaded(Box<(Profile, Option<ColorImage>)>),
+    Missing,
+}
+
+/// Profiles fetched so far, by bare public key
+#[derive(Default)]
+pub(crate) struct Profiles {
+    /// Updated by the background tasks fetching the profiles
+    states: Arc<Mutex<HashMap<String, ProfileState>>>,
+    avatars: HashMap<String, TextureHandle>,
+}
+
+impl Profiles {
+    /// Profile and avatar of a user, or `None` until it is fetched or if the user has none
+    fn get(
+        &mut self,
+        user_pk: &str,
+        pub_storage: &PublicStorage,
+        rt: &Runtime,
+        ctx: &Context,
+    ) -> Option<(Profile, Option<TextureHandle>)> {
+        let key = parse_user_pk(user_pk)?.z32();
+
+        let mut states = self.states.lock().unwrap();
+        let (profile, avatar) = match states.get(&key) {
+            Some(ProfileState::Loaded(loaded)) => loaded.as_ref(),
+            Some(_) => return None,
+            None => {
+                states.insert(key.clone(), ProfileState::Loading);
+                let (states, pub_storage, ctx) = (self.states.clone(), pub_storage.clone(), ctx.clone());
+                rt.spawn(async move {
+                    let state = match fetch_profile(&pub_storage, &key).await {
+                        Ok(profile) => ProfileState::Loaded(Box::new(profile)),
+                        Err(e) => {
+                            log::info!("No profile for {key}: {e}");
+                            ProfileState::Missing
+                        }
+                    };
+                    states.lock().unwrap().insert(key, state);
+                    ctx.request_repaint();
+                });
+                return None;
+            }
+        };
+
+        let avatar = match (self.avatars.get(&key), avatar) {
+            (Some(texture), _) => Some(texture.clone()),
+            (None, Some(image)) => {
+                let texture = ctx.load_texture(format!("avatar-{key}"), image.clone(), Default::default());
+                self.avatars.insert(key, texture.clone());
+                Some(texture)
+            }
+            (None, None) => None,
+        };
+        Some((profile.clone(), avatar))
+    }
+}
+
+async fn fetch_profile(pub_storage: &PublicStorage, user_pk: &str) -> Result<(Profile, Option<ColorImage>)> {
+    let profile_url = format!("pubky://{user_pk}/pub/pubky.app/profile.json");
+    let profile: Profile = serde_json::from_str(&pub_storage.get(profile_url).await?.text().await?)?;
+
+    let avatar = match &profile.image {
+        Some(file_url) => fetch_avatar(pub_storage, file_url)
+            .await
+            .inspect_err(|e| log::error!("Failed to fetch avatar of {user_pk}: {e}"))
+            .ok(),
+        None => None,
+    };
+    Ok((profile, avatar))
+}
+
+async fn fetch_avatar(pub_storage: &PublicStorage, file_url: &str) -> Result<ColorImage> {
+    let file: FileObject = serde_json::from_str(&pub_storage.get(file_url).await?.text().await?)?;
+    let bytes = pub_storage.get(&file.src).await?.bytes().await?;
+
+    let image = image::load_from_memory(&bytes)?
+        .thumbnail(AVATAR_SIZE, AVATAR_SIZE)
+        .into_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    Ok(ColorImage::from_rgba_unmultiplied(size, &image.into_raw()))
+}
+
+/// Shortened public key, for users without a profile
+pub(crate) fn short_pk(user_pk: &str) -> String {
+    let key = user_pk.strip_prefix("pubky").filter(|key| key.len() == 52).unwrap_or(user_pk);
+    match key.len() > 16 && key.is_ascii() {
+        true => format!("{}…{}", &key[..8], &key[key.len() - 4..]),
+        false => key.to_string(),
+    }
+}
+
+impl PubkyApp {
+    /// Name of a user from their profile, or their shortened public key
+    pub(crate) fn display_name(&mut self, user_pk: &str, pub_storage: &PublicStorage, ctx: &Context) -> String {
+        match self.profiles.get(user_pk, pub_storage, &self.rt, ctx) {
+            Some((profile, _)) if !profile.name.trim().is_empty() => profile.name,
+            _ => short_pk(user_pk),
+        }
+    }
+
+    /// Bio of a user from their profile, if any
+    pub(crate) fn bio(&mut self, user_pk: &str, pub_storage: &PublicStorage, ctx: &Context) -> Option<String> {
+        let (profile, _) = self.profiles.get(user_pk, pub_storage, &self.rt, ctx)?;
+        profile.bio.filter(|bio| !bio.trim().is_empty())
+    }
+}
+
+/// Avatar and name of a user, showing the public key and bio on hover and copying the key on click
+pub(crate) fn show_user(app: &mut PubkyApp, ui: &mut Ui, user_pk: &str, pub_storage: &PublicStorage, avatar_size: f32) {
+    let (profile, avatar) = match app.profiles.get(user_pk, pub_storage, &app.rt, ui.ctx()) {
+        Some((profile, avatar)) => (Some(profile), avatar),
+        None => (None, None),
+    };
+    let name = match &profile {
+        Some(profile) if !profile.name.trim().is_empty() => profile.name.clone(),
+        _ => short_pk(user_pk),
+    };
+    let hover_text = match profile.and_then(|profile| profile.bio) {
+        Some(bio) => format!("{bio}\n\n{user_pk}"),
+        None => user_pk.to_string(),
+    };
+
+    ui.horizontal(|ui| {
+        if let Some(avatar) = avatar {
+            let size = egui::vec2(avatar_size, avatar_size);
+            ui.add(egui::Image::from_texture(&avatar).fit_to_exact_size(size).corner_radius(avatar_size / 2.0));
+        }
+        let label = ui
+            .add(egui::Label::new(egui::RichText::new(name).strong()).sense(egui::Sense::click()))
+            .on_hover_text(format!("{hover_text}\n\nClick to copy the public key"));
+        if label.clicked() {
+            ui.ctx().copy_text(user_pk.to_string());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortens_public_keys() {
+        let pk = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+        assert_eq!(short_pk(pk), "6ookcbki…zo4y");
+        assert_eq!(short_pk(&format!("pubky{pk}")), "6ookcbki…zo4y");
+        assert_eq!(short_pk("alice"), "alice");
+    }
+
+    #[test]
+    fn parses_pubky_app_profiles() {
+        let json = r#"{"name":"Alice","bio":"Lugano","image":"pubky://pk/pub/pubky.app/files/0034","links":[],"status":null}"#;
+        let profile: Profile = serde_json::from_str(json).unwrap();
+        assert_eq!(profile.name, "Alice");
+        assert_eq!(profile.bio.as_deref(), Some("Lugano"));
+        assert_eq!(profile.image.as_deref(), Some("pubky://pk/pub/pubky.app/files/0034"));
+    }
+}
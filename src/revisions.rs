@@ -0,0 +1,263 @@
+//! Revision history of pages, and the fork point of forked pages.
+//!
+//! Homeservers only keep the latest version of a file, so every save of a page also writes its
+//! content to `/pub/wiki.app/.revisions/<page id>/<timestamp>`, the timestamp being the sortable
+//! ID of the revision. A fork records the page and revision it was forked from in
+//! `/pub/wiki.app/.forks/<page id>`. Both folders are hidden from the page listings.
+//!
+//! Pages saved before revisions were recorded, or by other apps, have their latest version as
+//! their only known revision.
+
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use pubky::{PubkySession, PublicKey, PublicStorage};
+use pubky_timestamp::Timestamp;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::{
+    export::fetch_page,
+    utils::{get_content, get_page_list, list_all},
+};
+
+const REVISIONS_PATH: &str = "/pub/wiki.app/.revisions/";
+const FORKS_PATH: &str = "/pub/wiki.app/.forks/";
+
+/// A version of a page
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Revision {
+    /// Timestamp ID of the revision, `None` for a latest version missing from the history
+    pub(crate) id: Option<String>,
+    pub(crate) content: String,
+    /// Unix timestamp (seconds) of the revision, if known
+    pub(crate) created_at: Option<u64>,
+}
+
+/// Known revisions of a page, oldest first
+pub(crate) struct PageHistory {
+    pub(crate) page_id: String,
+    pub(crate) revisions: Vec<Revision>,
+    /// Whether the revisions start at the first recorded one, rather than leaving older ones out
+    pub(crate) is_complete: bool,
+}
+
+impl PageHistory {
+    /// Latest known version of the page
+    pub(crate) fn content(&self) -> &str {
+        self.revisions.last().map(|revision| revision.content.as_str()).unwrap_or_default()
+    }
+}
+
+/// Page and revision a fork was made from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ForkPoint {
+    /// Pubky URL of the original page
+    pub(crate) forked_from: String,
+    /// Latest revision of the original page when it was forked, if it had any
+    pub(crate) revision: Option<String>,
+}
+
+/// Pubky URL of a revision of a page of `user_pk`
+pub(crate) fn revision_url(user_pk: &PublicKey, page_id: &str, revision_id: &str) -> String {
+    format!("pubky://{}{REVISIONS_PATH}{page_id}/{revision_id}", user_pk.z32())
+}
+
+/// Unix timestamp (seconds) of a revision ID
+pub(crate) fn revision_time(revision_id: &str) -> Option<u64> {
+    let timestamp = Timestamp::try_from(revision_id.to_string()).ok()?;
+    Some(timestamp.as_u64() / 1_000_000)
+}
+
+/// Record `content` as a new revision of the signed in user's page, returning the revision ID
+pub(crate) async fn save_revision(session: &PubkySession, page_id: &str, content: &str) -> Result<String> {
+    let revision_id = Timestamp::now().to_string();
+    save_revision_as(session, page_id, &revision_id, content).await?;
+    Ok(revision_id)
+}
+
+/// Record `content` as the revision `revision_id` of the signed in user's page, such as an
+/// imported revision dated from its source
+pub(crate) async fn save_revision_as(
+    session: &PubkySession,
+    page_id: &str,
+    revision_id: &str,
+    content: &str,
+) -> Result<()> {
+    session
+        .storage()
+        .put(format!("{REVISIONS_PATH}{page_id}/{revision_id}"), content.to_string())
+        .await?;
+    Ok(())
+}
+
+/// Delete the revisions and fork point of the signed in user's page
+pub(crate) async fn delete_history(session: &PubkySession, page_id: &str) -> Result<()> {
+    let storage = session.storage();
+    for file_url in list_all(|| storage.list(format!("{REVISIONS_PATH}{page_id}/")), false).await? {
+        let revision_id = file_url.split('/').next_back().unwrap_or(&file_url);
+        storage.delete(format!("{REVISIONS_PATH}{page_id}/{revision_id}")).await?;
+    }
+    if session.storage().exists(format!("{FORKS_PATH}{page_id}")).await? {
+        session.storage().delete(format!("{FORKS_PATH}{page_id}")).await?;
+    }
+    Ok(())
+}
+
+/// IDs of the revisions of a page of `user_pk`, oldest first
+pub(crate) async fn list_revisions(
+    pub_storage: &PublicStorage,
+    user_pk: &PublicKey,
+    page_id: &str,
+) -> Result<Vec<String>> {
+    let folder_url = format!("pubky://{}{REVISIONS_PATH}{page_id}/", user_pk.z32());
+    let mut revision_ids: Vec<String> = list_all(|| pub_storage.list(&folder_url), false)
+        .await?
+        .iter()
+        .filter_map(|url| url.split('/').next_back().map(str::to_string))
+        .filter(|revision_id| revision_time(revision_id).is_some())
+        .collect();
+    revision_ids.sort();
+    Ok(revision_ids)
+}
+
+/// Record that the signed in user's page `page_id` is a fork of the same page of `original_pk`,
+/// at its latest revision
+pub(crate) async fn record_fork(
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    page_id: &str,
+    original_pk: &PublicKey,
+) -> Result<()> {
+    let original_pk_z32 = original_pk.z32();
+    let revision = list_revisions(pub_storage, original_pk, page_id)
+        .await
+        .inspect_err(|e| log::error!("Failed to list the revisions of {original_pk_z32}/{page_id}: {e}"))
+        .unwrap_or_default()
+        .pop();
+    let fork_point = ForkPoint {
+        forked_from: format!("pubky://{original_pk_z32}/pub/wiki.app/{page_id}"),
+        revision,
+    };
+    session
+        .storage()
+        .put(format!("{FORKS_PATH}{page_id}"), serde_json::to_string(&fork_point)?)
+        .await?;
+    Ok(())
+}
+
+/// Fork point of a page of `user_pk`, if it was recorded
+pub(crate) fn fetch_fork_point(
+    pub_storage: &PublicStorage,
+    user_pk: &PublicKey,
+    page_id: &str,
+    rt: Arc<Runtime>,
+) -> Option<ForkPoint> {
+    let user_pk = user_pk.z32();
+    let json = get_content(pub_storage, &format!("pubky://{user_pk}{FORKS_PATH}{page_id}"), rt).ok()?;
+    serde_json::from_str(&json)
+        .inspect_err(|e| log::error!("Invalid fork point of {user_pk}/{page_id}: {e}"))
+        .ok()
+}
+
+/// Add the latest version of a page to its recorded revisions, unless it is the latest revision
+pub(crate) fn complete_history(revisions: &mut Vec<Revision>, content: String, updated_at: Option<u64>) {
+    if revisions.last().is_none_or(|revision| revision.content != content) {
+        revisions.push(Revision {
+            id: None,
+            content,
+            created_at: updated_at,
+        });
+    }
+}
+
+/// Known revisions of a page of `user_pk`, oldest first, ending with its latest version. Only
+/// the `limit` latest recorded revisions are fetched if given, and deleted pages only have their
+/// recorded revisions.
+pub(crate) fn fetch_history(
+    pub_storage: &PublicStorage,
+    user_pk: &PublicKey,
+    page_id: &str,
+    limit: Option<usize>,
+    rt: Arc<Runtime>,
+) -> Result<PageHistory> {
+    let mut revision_ids = rt
+        .block_on(list_revisions(pub_storage, user_pk, page_id))
+        .inspect_err(|e| log::error!("Failed to list the revisions of {}/{page_id}: {e}", user_pk.z32()))
+        .unwrap_or_default();
+    let first_id = revision_ids.first().cloned();
+    if let Some(limit) = limit {
+        revision_ids.drain(..revision_ids.len().saturating_sub(limit));
+    }
+
+    let mut revisions = vec![];
+    for revision_id in revision_ids {
+        let url = revision_url(user_pk, page_id, &revision_id);
+        match get_content(pub_storage, &url, rt.clone()) {
+            Ok(content) => revisions.push(Revision {
+                created_at: revision_time(&revision_id),
+                id: Some(revision_id),
+                content,
+            }),
+            Err(e) => log::error!("Error fetching path {url}: {e}"),
+        }
+    }
+
+    // The first recorded revision is left out beyond the limit, or if it can't be fetched
+    let is_complete = revisions.first().and_then(|revision| revision.id.as_ref()) == first_id.as_ref();
+
+    if let Ok(page) = fetch_page(pub_storage, &user_pk.z32(), page_id, rt) {
+        complete_history(&mut revisions, page.content, page.updated_at);
+    }
+
+    if revisions.is_empty() {
+        bail!("No version of {}/{page_id} found", user_pk.z32());
+    }
+    Ok(PageHistory {
+        page_id: page_id.to_string(),
+        revisions,
+        is_complete,
+    })
+}
+
+/// History of all the wiki pages of a user, see [`fetch_history`]
+pub(crate) fn fetch_histories(
+    pub_storage: &PublicStorage,
+    user_pk: &PublicKey,
+    limit: Option<usize>,
+    rt: Arc<Runtime>,
+) -> Result<Vec<PageHistory>> {
+    let mut pages = vec![];
+    for file_url in get_page_list(pub_storage, &user_pk.z32(), rt.clone())? {
+        let page_id = file_url.split('/').next_back().unwrap_or(&file_url);
+
+        match fetch_history(pub_storage, user_pk, page_id, limit, rt.clone()) {
+            Ok(page) => pages.push(page),
+            Err(e) => log::error!("Error fetching the history of {file_url}: {e}"),
+        }
+    }
+    pages.sort_by(|a, b| a.page_id.cmp(&b.page_id));
+
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_revisions_by_their_id() {
+        let timestamp = Timestamp::from(1_700_000_000_123_456);
+        assert_eq!(revision_time(&timestamp.to_string()), Some(1_700_000_000));
+        assert_eq!(revision_time("id-1"), None);
+    }
+
+    #[test]
+    fn builds_revision_urls_with_the_bare_key() {
+        let pk = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+        assert_eq!(
+            revision_url(&format!("pubky{pk}").parse().unwrap(), "id-1", "0033"),
+            format!("pubky://{pk}/pub/wiki.app/.revisions/id-1/0033")
+        );
+    }
+}
@@ -0,0 +1,516 @@
+//! Static HTML site generated from the wiki of one or more users, as a read-only mirror for
+//! people who don't use Pubky.
+//!
+//! Each page is written to `<user pk>/<page id>.html`, rendered with the same markdown dialect
+//! as the app and followed by its forks (the same page ID by the other users of the site) and
+//! its backlinks. Links between the pages of the site become relative links, and `index.html`
+//! lists all the pages.
+
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use anyhow::Result;
+use pubky::PublicStorage;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Parser, Tag, TagEnd};
+use tokio::runtime::Runtime;
+
+use crate::{
+    export::fetch_pages,
+    links::{expand_title_links, extract_links, markdown_options, slugify, LinkTarget},
+    utils::extract_title,
+};
+
+const STYLE: &str = "body { max-width: 48em; margin: 2em auto; padding: 0 1em; font-family: sans-serif; line-height: 1.5; }
+nav, footer, .meta { color: #777; font-size: 0.9em; }
+pre { background: #f4f4f4; padding: 0.8em; overflow-x: auto; }
+code { background: #f4f4f4; padding: 0.1em 0.3em; }
+pre code { padding: 0; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; }
+blockquote { border-left: 3px solid #ccc; margin-left: 0; padding-left: 1em; color: #555; }
+aside { border-top: 1px solid #ccc; margin-top: 2em; }
+img { max-width: 100%; }";
+
+struct SitePage {
+    user_pk: String,
+    page_id: String,
+    title: String,
+    content: String,
+}
+
+impl SitePage {
+    /// Path of the page, relative to the root of the site
+    fn file(&self) -> String {
+        format!("{}/{}.html", self.user_pk, self.page_id)
+    }
+}
+
+struct Site {
+    pages: Vec<SitePage>,
+    /// Index of each page by user and page ID
+    by_key: HashMap<(String, String), usize>,
+}
+
+impl Site {
+    fn new(pages: Vec<SitePage>) -> Self {
+        let by_key = pages
+            .iter()
+            .enumerate()
+            .map(|(i, page)| ((page.user_pk.clone(), page.page_id.clone()), i))
+            .collect();
+
+        Self { pages, by_key }
+    }
+
+    /// Page of the site linked by `url` in a page of `author`, with the anchor of the link
+    fn resolve(&self, url: &str, author: &str) -> Option<(usize, Option<String>)> {
+        match LinkTarget::classify(url) {
+            LinkTarget::Wiki(link) => {
+                let key = (link.user_pk_or(author).to_string(), link.page_id.clone());
+                self.by_key.get(&key).map(|i| (*i, link.anchor))
+            }
+            LinkTarget::Title(title) => {
+                // The author's own pages first, as in the app
+                let matches = |page: &SitePage| page.title.eq_ignore_ascii_case(title.trim());
+                self.pages
+                    .iter()
+                    .position(|page| page.user_pk == author && matches(page))
+                    .or_else(|| self.pages.iter().position(matches))
+                    .map(|i| (i, None))
+            }
+            _ => None,
+        }
+    }
+
+    /// Target of a link in a page of `author`: a relative link for the pages of the site,
+    /// an absolute pubky URL for the other wiki pages
+    fn href(&self, url: &str, author: &str) -> String {
+        if let Some((i, anchor)) = self.resolve(url, author) {
+            let anchor = anchor.map(|a| format!("#{}", slugify(&a))).unwrap_or_default();
+            return format!("../{}{anchor}", self.pages[i].file());
+        }
+
+        match LinkTarget::classify(url) {
+            LinkTarget::Wiki(link) => {
+                let anchor = link.anchor.as_ref().map(|a| format!("#{a}")).unwrap_or_default();
+                format!("pubky://{}/pub/wiki.app/{}{anchor}", link.user_pk_or(author), link.page_id)
+            }
+            LinkTarget::Anchor(anchor) => format!("#{}", slugify(&anchor)),
+            // Title not matching any page of the site
+            LinkTarget::Title(_) => "#".into(),
+            _ => url.to_string(),
+        }
+    }
+
+    /// Pages of the site linking to each page
+    fn backlinks(&self) -> Vec<Vec<usize>> {
+        let mut result = vec![vec![]; self.pages.len()];
+
+        for (i, page) in self.pages.iter().enumerate() {
+            for url in extract_links(&page.content) {
+                if let Some((target, _)) = self.resolve(&url, &page.user_pk) {
+                    if target != i && !result[target].contains(&i) {
+                        result[target].push(i);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Same page by the other users of the site
+    fn forks(&self, i: usize) -> Vec<usize> {
+        let page = &self.pages[i];
+        (0..self.pages.len())
+            .filter(|j| *j != i && self.pages[*j].page_id == page.page_id)
+            .collect()
+    }
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Whether a link or image target can be written in a page: a relative URL, an anchor, or an
+/// http, https, mailto or pubky URL, so that pages can't run scripts with `javascript:` links
+fn is_safe_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters within the scheme
+    let url: String = url.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control()).collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => {
+            matches!(url[..i].to_lowercase().as_str(), "http" | "https" | "mailto" | "pubky")
+        }
+        _ => true,
+    }
+}
+
+/// Render a page to HTML, with the same markdown dialect as the app.
+///
+/// Link and image targets go through `rewrite_url`, and are dropped unless they are safe to
+/// follow. Raw HTML is escaped, as the app shows it as text.
+pub(crate) fn render_html(content: &str, mut rewrite_url: impl FnMut(&str) -> String) -> String {
+    let mut html = String::new();
+    // Heading being rendered, with its HTML and text, written once its anchor is known
+    let mut heading: Option<(HeadingLevel, String, String)> = None;
+    let mut alignments: Vec<Alignment> = vec![];
+    let mut cell = 0;
+    let mut in_table_head = false;
+    // Within an image, text is its alt attribute
+    let mut in_image = false;
+
+    for event in Parser::new_ext(&expand_title_links(content), markdown_options()) {
+        let out = match heading.as_mut() {
+            Some((_, heading_html, _)) => heading_html,
+            None => &mut html,
+        };
+
+        if in_image {
+            match event {
+                Event::End(TagEnd::Image) => {
+                    out.push_str("\" />");
+                    in_image = false;
+                }
+                Event::Text(text) | Event::Code(text) => out.push_str(&escape_html(&text)),
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph | Tag::HtmlBlock => out.push_str("<p>"),
+                Tag::Heading { level, .. } => heading = Some((level, String::new(), String::new())),
+                Tag::BlockQuote(_) => out.push_str("<blockquote>\n"),
+                Tag::CodeBlock(CodeBlockKind::Fenced(lang)) if !lang.is_empty() => {
+                    out.push_str(&format!("<pre><code class=\"language-{}\">", escape_html(&lang)))
+                }
+                Tag::CodeBlock(_) => out.push_str("<pre><code>"),
+                Tag::List(Some(1)) => out.push_str("<ol>\n"),
+                Tag::List(Some(start)) => out.push_str(&format!("<ol start=\"{start}\">\n")),
+                Tag::List(None) => out.push_str("<ul>\n"),
+                Tag::Item => out.push_str("<li>"),
+                Tag::FootnoteDefinition(label) => {
+                    let label = escape_html(&label);
+                    out.push_str(&format!("<div class=\"footnote\" id=\"fn-{label}\"><sup>{label}</sup> "));
+                }
+                Tag::DefinitionList => out.push_str("<dl>\n"),
+                Tag::DefinitionListTitle => out.push_str("<dt>"),
+                Tag::DefinitionListDefinition => out.push_str("<dd>"),
+                Tag::Table(table_alignments) => {
+                    alignments = table_alignments;
+                    out.push_str("<table>\n");
+                }
+                Tag::TableHead => {
+                    in_table_head = true;
+                    cell = 0;
+                    out.push_str("<thead><tr>");
+                }
+                Tag::TableRow => {
+                    cell = 0;
+                    out.push_str("<tr>");
+                }
+                Tag::TableCell => {
+                    let element = if in_table_head { "th" } else { "td" };
+                    let style = match alignments.get(cell) {
+                        Some(Alignment::Left) => " style=\"text-align: left\"",
+                        Some(Alignment::Center) => " style=\"text-align: center\"",
+                        Some(Alignment::Right) => " style=\"text-align: right\"",
+                        _ => "",
+                    };
+                    out.push_str(&format!("<{element}{style}>"));
+                }
+                Tag::Emphasis => out.push_str("<em>"),
+                Tag::Strong => out.push_str("<strong>"),
+                Tag::Strikethrough => out.push_str("<del>"),
+                Tag::Superscript => out.push_str("<sup>"),
+                Tag::Subscript => out.push_str("<sub>"),
+                Tag::Link { dest_url, title, .. } => {
+                    let href = rewrite_url(&dest_url);
+                    match is_safe_url(&href) {
+                        true => out.push_str(&format!("<a href=\"{}\"", escape_html(&href))),
+                        false => out.push_str("<a"),
+                    }
+                    if !title.is_empty() {
+                        out.push_str(&format!(" title=\"{}\"", escape_html(&title)));
+                    }
+                    out.push('>');
+                }
+                Tag::Image { dest_url, .. } => {
+                    let src = rewrite_url(&dest_url);
+                    match is_safe_url(&src) {
+                        true => out.push_str(&format!("<img src=\"{}\" alt=\"", escape_html(&src))),
+                        false => out.push_str("<img alt=\""),
+                    }
+                    in_image = true;
+                }
+                Tag::MetadataBlock(_) => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Paragraph | TagEnd::HtmlBlock => out.push_str("</p>\n"),
+                TagEnd::Heading(_) => {
+                    if let Some((level, heading_html, text)) = heading.take() {
+                        html.push_str(&format!("<{level} id=\"{}\">{heading_html}</{level}>\n", slugify(&text)));
+                    }
+                }
+                TagEnd::BlockQuote(_) => out.push_str("</blockquote>\n"),
+                TagEnd::CodeBlock => out.push_str("</code></pre>\n"),
+                TagEnd::List(true) => out.push_str("</ol>\n"),
+                TagEnd::List(false) => out.push_str("</ul>\n"),
+                TagEnd::Item => out.push_str("</li>\n"),
+                TagEnd::FootnoteDefinition => out.push_str("</div>\n"),
+                TagEnd::DefinitionList => out.push_str("</dl>\n"),
+                TagEnd::DefinitionListTitle => out.push_str("</dt>\n"),
+                TagEnd::DefinitionListDefinition => out.push_str("</dd>\n"),
+                TagEnd::Table => out.push_str("</tbody>\n</table>\n"),
+                TagEnd::TableHead => {
+                    in_table_head = false;
+                    out.push_str("</tr></thead>\n<tbody>\n");
+                }
+                TagEnd::TableRow => out.push_str("</tr>\n"),
+                TagEnd::TableCell => {
+                    out.push_str(if in_table_head { "</th>" } else { "</td>" });
+                    cell += 1;
+                }
+                TagEnd::Emphasis => out.push_str("</em>"),
+                TagEnd::Strong => out.push_str("</strong>"),
+                TagEnd::Strikethrough => out.push_str("</del>"),
+                TagEnd::Superscript => out.push_str("</sup>"),
+                TagEnd::Subscript => out.push_str("</sub>"),
+                TagEnd::Link => out.push_str("</a>"),
+                TagEnd::Image | TagEnd::MetadataBlock(_) => {}
+            },
+            Event::Text(text) => {
+                out.push_str(&escape_html(&text));
+                if let Some((_, _, heading_text)) = heading.as_mut() {
+                    heading_text.push_str(&text);
+                }
+            }
+            Event::Code(code) => {
+                out.push_str(&format!("<code>{}</code>", escape_html(&code)));
+                if let Some((_, _, heading_text)) = heading.as_mut() {
+                    heading_text.push_str(&code);
+                }
+            }
+            Event::InlineMath(text) | Event::DisplayMath(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                out.push_str(&escape_html(&text))
+            }
+            Event::FootnoteReference(label) => {
+                let label = escape_html(&label);
+                out.push_str(&format!("<sup><a href=\"#fn-{label}\">{label}</a></sup>"));
+            }
+            Event::SoftBreak => out.push('\n'),
+            Event::HardBreak => out.push_str("<br />\n"),
+            Event::Rule => out.push_str("<hr />\n"),
+            Event::TaskListMarker(checked) => {
+                let checked = if checked { " checked" } else { "" };
+                out.push_str(&format!("<input type=\"checkbox\" disabled{checked} /> "));
+            }
+        }
+    }
+
+    html
+}
+
+/// Complete HTML document, with a link to the list of pages at `index_url`
+pub(crate) fn html_document(title: &str, index_url: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\" />
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />
+<title>{}</title>
+<style>
+{STYLE}
+</style>
+</head>
+<body>
+<nav><a href=\"{index_url}\">All pages</a></nav>
+{body}</body>
+</html>
+",
+        escape_html(title)
+    )
+}
+
+/// List of links to pages of the site, from a page
+fn page_list(site: &Site, pages: &[usize], empty: &str) -> String {
+    if pages.is_empty() {
+        return format!("<p class=\"meta\">{empty}</p>\n");
+    }
+
+    let mut result = String::from("<ul>\n");
+    for i in pages {
+        let page = &site.pages[*i];
+        result.push_str(&format!(
+            "<li><a href=\"../{}\">{}</a> <span class=\"meta\">by {}</span></li>\n",
+            page.file(),
+            escape_html(&page.title),
+            page.user_pk
+        ));
+    }
+    result.push_str("</ul>\n");
+    result
+}
+
+fn page_document(site: &Site, i: usize, backlinks: &[usize]) -> String {
+    let page = &site.pages[i];
+    let content = render_html(&page.content, |url| site.href(url, &page.user_pk));
+    let source = format!("pubky://{}/pub/wiki.app/{}", page.user_pk, page.page_id);
+
+    let body = format!(
+        "<main>
+{content}</main>
+<aside>
+<h2>Forks</h2>
+{}<h2>Backlinks</h2>
+{}</aside>
+<footer>Mirrored from <code>{source}</code></footer>
+",
+        page_list(site, &site.forks(i), "No forks."),
+        page_list(site, backlinks, "No pages link here.")
+    );
+    html_document(&page.title, "../index.html", &body)
+}
+
+fn index_document(site: &Site, user_pks: &[String]) -> String {
+    let mut body = String::from("<h1>Wiki</h1>\n");
+
+    for user_pk in user_pks {
+        let mut pages: Vec<&SitePage> = site.pages.iter().filter(|page| page.user_pk == *user_pk).collect();
+        pages.sort_by_key(|page| page.title.to_lowercase());
+
+        body.push_str(&format!("<h2>Pages by <code>{user_pk}</code></h2>\n<ul>\n"));
+        for page in pages {
+            body.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                page.file(),
+                escape_html(&page.title)
+            ));
+        }
+        body.push_str("</ul>\n");
+    }
+    html_document("Wiki", "index.html", &body)
+}
+
+/// Render the wiki pages of `user_pks` as a static HTML site in `dir`, returning the number of pages
+pub(crate) fn generate_site(
+    pub_storage: &PublicStorage,
+    user_pks: &[String],
+    dir: &Path,
+    rt: Arc<Runtime>,
+) -> Result<usize> {
+    let mut pages = vec![];
+    for user_pk in user_pks {
+        for page in fetch_pages(pub_storage, user_pk, rt.clone())? {
+            let title = match extract_title(&page.content).trim() {
+                "" => page.page_id.clone(),
+                title => title.to_string(),
+            };
+            pages.push(SitePage {
+                user_pk: user_pk.clone(),
+                page_id: page.page_id,
+                title,
+                content: page.content,
+            });
+        }
+    }
+    let site = Site::new(pages);
+    let backlinks = site.backlinks();
+
+    for user_pk in user_pks {
+        fs::create_dir_all(dir.join(user_pk))?;
+    }
+    for (i, page) in site.pages.iter().enumerate() {
+        fs::write(dir.join(page.file()), page_document(&site, i, &backlinks[i]))?;
+    }
+    fs::write(dir.join("index.html"), index_document(&site, user_pks))?;
+
+    log::info!("Generated a site of {} pages in {}", site.pages.len(), dir.display());
+
+    Ok(site.pages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PK: &str = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+    const OTHER_PK: &str = "pxnu33x7jtpx9ar1ytsi4yxbp6a5o36gwhffs8zoxmbuptici1jy";
+
+    fn page(user_pk: &str, page_id: &str, content: &str) -> SitePage {
+        SitePage {
+            user_pk: user_pk.into(),
+            page_id: page_id.into(),
+            title: extract_title(content).trim().into(),
+            content: content.into(),
+        }
+    }
+
+    #[test]
+    fn renders_markdown() {
+        let html = render_html(
+            "# Lugano `city`\n\n| A | B |\n|:--|--:|\n| 1 | 2 |\n\n<b>x</b> ~~y~~ ![alt *text*](a.png)\n",
+            |url| url.to_string(),
+        );
+        assert_eq!(
+            html,
+            "<h1 id=\"lugano-city\">Lugano <code>city</code></h1>\n\
+             <table>\n<thead><tr><th style=\"text-align: left\">A</th><th style=\"text-align: right\">B</th></tr></thead>\n<tbody>\n\
+             <tr><td style=\"text-align: left\">1</td><td style=\"text-align: right\">2</td></tr>\n</tbody>\n</table>\n\
+             <p>&lt;b&gt;x&lt;/b&gt; <del>y</del> <img src=\"a.png\" alt=\"alt text\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn drops_unsafe_urls() {
+        let html = render_html(
+            "[x](javascript:alert(1)) [y](<JaVa\tScript:alert(1)>) [z](vbscript:x) ![i](data:text/html,x)\n\n\
+             [a](https://example.com) [b](mailto:a@example.com) [c](pubky://pk/pub/wiki.app/id-1) [d](../a:b.html) [e](#top)",
+            |url| url.to_string(),
+        );
+        assert_eq!(
+            html,
+            "<p><a>x</a> <a>y</a> <a>z</a> <img alt=\"i\" /></p>\n\
+             <p><a href=\"https://example.com\">a</a> <a href=\"mailto:a@example.com\">b</a> \
+             <a href=\"pubky://pk/pub/wiki.app/id-1\">c</a> <a href=\"../a:b.html\">d</a> <a href=\"#top\">e</a></p>\n"
+        );
+
+        let site = Site::new(vec![page(PK, "id-1", "# Lugano\n\n[x](javascript:alert(1))")]);
+        assert!(!page_document(&site, 0, &[]).contains("javascript"));
+    }
+
+    #[test]
+    fn links_pages_of_the_site() {
+        let site = Site::new(vec![
+            page(PK, "id-1", "# Lugano\n\nSee [[Ticino]] and [lake](id-3#Lake Side)."),
+            page(PK, "id-2", "# Ticino\n\n[Lugano](id-1) [fork](pubky://pxnu33x7jtpx9ar1ytsi4yxbp6a5o36gwhffs8zoxmbuptici1jy/pub/wiki.app/id-1)"),
+            page(OTHER_PK, "id-1", "# Lugano\n\n[[Ticino]]"),
+        ]);
+
+        assert_eq!(site.href("id-2", PK), "../6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y/id-2.html");
+        assert_eq!(
+            site.href("wiki-title:Ticino", OTHER_PK),
+            "../6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y/id-2.html"
+        );
+        assert_eq!(
+            site.href("id-3#Lake Side", PK),
+            "pubky://6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y/pub/wiki.app/id-3#Lake Side"
+        );
+        assert_eq!(site.href("#Lake Side", PK), "#lake-side");
+        assert_eq!(site.href("wiki-title:Zurich", PK), "#");
+
+        assert_eq!(site.backlinks(), vec![vec![1], vec![0, 2], vec![1]]);
+        assert_eq!(site.forks(0), vec![2]);
+    }
+}
@@ -0,0 +1,378 @@
+//! Two-way sync between the user's wiki and a local folder, to edit pages in any editor.
+//!
+//! Each page is mirrored as `<page id>.md`. The SHA-256 hash of every page at the last sync is
+//! kept in `.pubky-wiki-sync.json` in the folder, which tells which side changed since:
+//!
+//! - a page changed on one side only is copied to the other side
+//! - a page deleted on one side only is deleted on the other side, moving it to the trash on the
+//!   homeserver, unless it was also edited on the other side, then the edit wins
+//! - a page edited on both sides is left as is locally, and the homeserver version is written
+//!   next to it as `<page id>.conflict.md`. Once the user merged both versions in the page and
+//!   deleted the side file, the next sync uploads the result.
+//!
+//! New local `.md` files are uploaded with their file name as page ID, so their name must be a
+//! valid page ID: letters, digits, `-` and `_`. Other files are skipped and reported, as are
+//! homeserver pages whose ID can't be such a file name. A skipped page is left as is on both sides.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use pubky::PubkySession;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    links::is_valid_page_id, migrate::sha256, trash::trash_wiki_post, update_wiki_post, utils::list_pages,
+};
+
+const SYNC_FILE: &str = ".pubky-wiki-sync.json";
+const CONFLICT_SUFFIX: &str = ".conflict.md";
+
+/// Time between two syncs when watching a folder
+pub(crate) const WATCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What the last sync of a folder left behind
+#[derive(Default, Serialize, Deserialize)]
+struct SyncState {
+    user_pk: String,
+    /// Hash of each page at the last sync, by page ID
+    pages: BTreeMap<String, String>,
+    /// Hash of the homeserver version written to a side file, by page ID of the conflicting page
+    conflicts: BTreeMap<String, String>,
+}
+
+/// Change to apply to a page, from its hash at the last sync, locally and on the homeserver
+#[derive(Debug, PartialEq)]
+enum SyncAction {
+    Unchanged,
+    Download,
+    Upload,
+    DeleteLocal,
+    DeleteRemote,
+    Conflict,
+}
+
+fn plan_page(base: Option<&str>, local: Option<&str>, remote: Option<&str>) -> SyncAction {
+    match (local, remote) {
+        _ if local == remote => SyncAction::Unchanged,
+        (_, None) if local == base => SyncAction::DeleteLocal,
+        (None, _) if remote == base => SyncAction::DeleteRemote,
+        _ if local == base => SyncAction::Download,
+        _ if remote == base => SyncAction::Upload,
+        (Some(_), Some(_)) => SyncAction::Conflict,
+        // Edited on one side and deleted on the other: the edit wins
+        (Some(_), None) => SyncAction::Upload,
+        _ => SyncAction::Download,
+    }
+}
+
+/// Content of the pages in the folder by page ID, and the markdown files that can't be synced
+/// with the reason why, by page ID
+fn local_pages(dir: &Path) -> Result<(BTreeMap<String, String>, BTreeMap<String, String>)> {
+    let mut pages = BTreeMap::new();
+    let mut skipped = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some(page_id) = file_name.strip_suffix(".md") else {
+            continue;
+        };
+        if !path.is_file() || file_name.starts_with('.') || file_name.ends_with(CONFLICT_SUFFIX) {
+            continue;
+        }
+
+        if !is_valid_page_id(page_id) {
+            let reason = format!("{file_name} (page IDs may only contain letters, digits, - and _)");
+            skipped.insert(page_id.to_string(), reason);
+            continue;
+        }
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                pages.insert(page_id.to_string(), content);
+            }
+            Err(e) => {
+                skipped.insert(page_id.to_string(), format!("{file_name} ({e})"));
+            }
+        }
+    }
+    Ok((pages, skipped))
+}
+
+/// Content of the user's pages on the homeserver by page ID, and the pages that can't be
+/// synced with the reason why, by page ID
+async fn remote_pages(session: &PubkySession) -> Result<(BTreeMap<String, String>, BTreeMap<String, String>)> {
+    let mut pages = BTreeMap::new();
+    let mut skipped = BTreeMap::new();
+    let storage = session.storage();
+    for url in list_pages(|| storage.list("/pub/wiki.app/")).await? {
+        let page_id = url.split('/').next_back().unwrap_or(&url).to_string();
+        if !is_valid_page_id(&page_id) {
+            let reason = format!("{page_id} on the homeserver (its page ID can't be used as a file name)");
+            skipped.insert(page_id, reason);
+            continue;
+        }
+        let content = storage.get(format!("/pub/wiki.app/{page_id}")).await?.text().await?;
+        pages.insert(page_id, content);
+    }
+    Ok((pages, skipped))
+}
+
+/// Changes a sync makes, worked out from the pages on both sides and the state of the last sync
+struct SyncPlan {
+    /// Change to apply to each page, by page ID, leaving out the unchanged pages
+    actions: Vec<(String, SyncAction)>,
+    /// Pages in conflict whose homeserver version is not yet in their side file
+    side_files: BTreeSet<String>,
+    /// State to keep once the changes are applied
+    state: SyncState,
+}
+
+/// Plan the sync of the folder `dir`. Pages skipped on either side are left alone: missing on
+/// that side, they would otherwise look deleted, and be deleted on the other side.
+fn plan_sync(
+    dir: &Path,
+    mut state: SyncState,
+    local: &BTreeMap<String, String>,
+    remote: &BTreeMap<String, String>,
+    skipped: &BTreeMap<String, String>,
+) -> SyncPlan {
+    let local_hash = |page_id: &str| local.get(page_id).map(|content| sha256(content.as_bytes()));
+    let remote_hash = |page_id: &str| remote.get(page_id).map(|content| sha256(content.as_bytes()));
+
+    let page_ids: BTreeSet<String> = local
+        .keys()
+        .chain(remote.keys())
+        .chain(state.pages.keys())
+        .filter(|page_id| is_valid_page_id(page_id) && !skipped.contains_key(*page_id))
+        .cloned()
+        .collect();
+
+    let mut actions = vec![];
+    let mut side_files = BTreeSet::new();
+    for page_id in page_ids {
+        // A conflict is resolved by deleting its side file, the homeserver version it holds
+        // then counts as synced
+        let mut base = state.pages.get(&page_id).cloned();
+        if let Some(hash) = state.conflicts.get(&page_id) {
+            if !dir.join(format!("{page_id}{CONFLICT_SUFFIX}")).exists() {
+                base = Some(hash.clone());
+                state.conflicts.remove(&page_id);
+            }
+        }
+
+        let (local_hash, remote_hash) = (local_hash(&page_id), remote_hash(&page_id));
+        if local_hash == remote_hash {
+            state.conflicts.remove(&page_id);
+        }
+        let action = plan_page(base.as_deref(), local_hash.as_deref(), remote_hash.as_deref());
+        let synced_hash = match action {
+            SyncAction::Unchanged | SyncAction::Upload => local_hash,
+            SyncAction::Download => remote_hash,
+            SyncAction::DeleteLocal | SyncAction::DeleteRemote => None,
+            SyncAction::Conflict => {
+                if state.conflicts.get(&page_id) != remote_hash.as_ref() {
+                    side_files.insert(page_id.clone());
+                    state.conflicts.insert(page_id.clone(), remote_hash.unwrap_or_default());
+                }
+                base
+            }
+        };
+
+        match synced_hash {
+            Some(hash) => state.pages.insert(page_id.clone(), hash),
+            None => state.pages.remove(&page_id),
+        };
+        if action != SyncAction::Unchanged {
+            actions.push((page_id, action));
+        }
+    }
+
+    SyncPlan {
+        actions,
+        side_files,
+        state,
+    }
+}
+
+/// Pages changed by a sync, by page ID
+#[derive(Default)]
+pub(crate) struct SyncReport {
+    pub(crate) downloaded: Vec<String>,
+    pub(crate) uploaded: Vec<String>,
+    pub(crate) deleted_locally: Vec<String>,
+    pub(crate) deleted_remotely: Vec<String>,
+    pub(crate) conflicts: Vec<String>,
+    /// Local files that were not synced, with the reason why
+    pub(crate) skipped: Vec<String>,
+}
+
+impl SyncReport {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.downloaded.is_empty()
+            && self.uploaded.is_empty()
+            && self.deleted_locally.is_empty()
+            && self.deleted_remotely.is_empty()
+            && self.conflicts.is_empty()
+            && self.skipped.is_empty()
+    }
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("Downloaded", &self.downloaded),
+            ("Uploaded", &self.uploaded),
+            ("Deleted locally", &self.deleted_locally),
+            ("Moved to the trash", &self.deleted_remotely),
+            ("Conflicts, see the .conflict.md files", &self.conflicts),
+        ];
+        for (label, page_ids) in sections {
+            if !page_ids.is_empty() {
+                writeln!(f, "{label}: {}", page_ids.join(", "))?;
+            }
+        }
+        for file in &self.skipped {
+            writeln!(f, "Skipped {file}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Sync the wiki of the signed in user with the folder `dir`, creating it if needed
+pub(crate) async fn sync(session: &PubkySession, dir: &Path) -> Result<SyncReport> {
+    let user_pk = session.info().public_key().z32();
+    fs::create_dir_all(dir)?;
+
+    let state_path = dir.join(SYNC_FILE);
+    let state: SyncState = match fs::read_to_string(&state_path) {
+        Ok(json) => serde_json::from_str(&json)?,
+        Err(_) => SyncState {
+            user_pk: user_pk.clone(),
+            ..Default::default()
+        },
+    };
+    if state.user_pk != user_pk {
+        bail!("{} is synced with the wiki of {}", dir.display(), state.user_pk);
+    }
+
+    let (local, mut skipped) = local_pages(dir)?;
+    let (remote, remote_skipped) = remote_pages(session).await?;
+    skipped.extend(remote_skipped);
+
+    let plan = plan_sync(dir, state, &local, &remote, &skipped);
+
+    let mut report = SyncReport {
+        skipped: skipped.into_values().collect(),
+        ..Default::default()
+    };
+    for (page_id, action) in plan.actions {
+        let local_path = dir.join(format!("{page_id}.md"));
+        match action {
+            SyncAction::Unchanged => {}
+            SyncAction::Download => {
+                fs::write(&local_path, &remote[&page_id])?;
+                report.downloaded.push(page_id);
+            }
+            SyncAction::Upload => {
+                update_wiki_post(session, &page_id, &local[&page_id]).await?;
+                report.uploaded.push(page_id);
+            }
+            SyncAction::DeleteLocal => {
+                fs::remove_file(&local_path)?;
+                report.deleted_locally.push(page_id);
+            }
+            SyncAction::DeleteRemote => {
+                trash_wiki_post(session, &page_id).await?;
+                report.deleted_remotely.push(page_id);
+            }
+            SyncAction::Conflict => {
+                if plan.side_files.contains(&page_id) {
+                    fs::write(dir.join(format!("{page_id}{CONFLICT_SUFFIX}")), &remote[&page_id])?;
+                }
+                report.conflicts.push(page_id);
+            }
+        }
+    }
+
+    fs::write(&state_path, serde_json::to_string_pretty(&plan.state)?)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_changes_on_either_side() {
+        use SyncAction::*;
+
+        let (a, b, c) = (Some("a"), Some("b"), Some("c"));
+        assert_eq!(plan_page(a, a, a), Unchanged);
+        assert_eq!(plan_page(None, a, a), Unchanged);
+        assert_eq!(plan_page(a, a, b), Download);
+        assert_eq!(plan_page(a, b, a), Upload);
+        assert_eq!(plan_page(None, None, a), Download);
+        assert_eq!(plan_page(None, a, None), Upload);
+        assert_eq!(plan_page(a, a, None), DeleteLocal);
+        assert_eq!(plan_page(a, None, a), DeleteRemote);
+        assert_eq!(plan_page(a, b, c), Conflict);
+        assert_eq!(plan_page(None, b, c), Conflict);
+        assert_eq!(plan_page(a, b, None), Upload);
+        assert_eq!(plan_page(a, None, c), Download);
+    }
+
+    #[test]
+    fn lists_local_pages() {
+        let dir = std::env::temp_dir().join(format!("pubky-wiki-sync-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["id-1.md", "id-1.conflict.md", ".hidden.md", "notes.txt", "my notes.md", SYNC_FILE] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        fs::write(dir.join("latin1.md"), b"caf\xe9").unwrap();
+
+        let (pages, skipped) = local_pages(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(pages.keys().collect::<Vec<_>>(), ["id-1"]);
+        assert_eq!(pages["id-1"], "id-1.md");
+        assert_eq!(skipped.keys().collect::<Vec<_>>(), ["latin1", "my notes"]);
+        assert!(skipped["latin1"].starts_with("latin1.md ("));
+    }
+
+    #[test]
+    fn leaves_skipped_pages_alone() {
+        let dir = std::env::temp_dir().join(format!("pubky-wiki-sync-plan-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("latin1.md"), b"caf\xe9").unwrap();
+        fs::write(dir.join("my.page.md"), "# My page").unwrap();
+
+        let page = |content: &str| (content.to_string(), sha256(content.as_bytes()));
+        let (latin1, latin1_hash) = page("# Café");
+        let (my_page, my_page_hash) = page("# My page");
+        let (new_page, _) = page("# New page");
+        let state = SyncState {
+            pages: BTreeMap::from([("latin1".into(), latin1_hash.clone()), ("my.page".into(), my_page_hash.clone())]),
+            ..Default::default()
+        };
+        let remote = BTreeMap::from([
+            ("latin1".to_string(), latin1),
+            ("my.page".to_string(), my_page),
+            ("new-page".to_string(), new_page),
+        ]);
+
+        let (local, skipped) = local_pages(&dir).unwrap();
+        let plan = plan_sync(&dir, state, &local, &remote, &skipped);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Unreadable or misnamed locally, the pages are neither trashed nor downloaded again
+        assert_eq!(plan.actions, [("new-page".to_string(), SyncAction::Download)]);
+        assert_eq!(plan.state.pages.get("latin1"), Some(&latin1_hash));
+        assert_eq!(plan.state.pages.get("my.page"), Some(&my_page_hash));
+    }
+}
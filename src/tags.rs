@@ -0,0 +1,516 @@
+//! Tagging pages, the user's own or others', with the tag records of pubky.app, and browsing
+//! the pages tagged by the user and their follows. The tags are fetched in the background.
+//!
+//! A tag is stored at `/pub/pubky.app/tags/<id>` in the homeserver of the user who tagged, and
+//! points at the `pubky://` URL of the page. As in pubky.app, the ID is derived from the URL
+//! and the label, so tagging a page twice with the same label writes the same record.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use eframe::egui::{CollapsingHeader, Context, Ui};
+use pubky::{PubkySession, PublicStorage};
+use pubky_timestamp::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capabilities::Feature,
+    get_follows, history,
+    links::WikiLink,
+    user_wiki::parse_user_pk,
+    utils::{extract_title, get_content, list_all},
+    PubkyApp,
+};
+
+/// Longest label accepted by pubky.app
+const MAX_LABEL_LENGTH: usize = 20;
+
+/// Tag record of the pubky.app data model
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Tag {
+    /// URL of the tagged page
+    pub(crate) uri: String,
+    pub(crate) label: String,
+    /// Unix timestamp in microseconds
+    pub(crate) created_at: i64,
+}
+
+/// Tags with the public key of the user who wrote them, as `(tagger pk, tag)`
+type UserTags = Vec<(String, Tag)>;
+
+/// Tags of the user and their follows
+pub(crate) struct TagCache {
+    pub(crate) tags: UserTags,
+    /// Title of the tagged pages, by URL, fetched when shown
+    titles: HashMap<String, String>,
+}
+
+/// Tags of the user and their follows, fetched in the background on first use
+pub(crate) enum TagsState {
+    /// Set by the background task once the tags are fetched
+    Loading(Arc<Mutex<Option<UserTags>>>),
+    Loaded(TagCache),
+}
+
+/// Label as stored by pubky.app: trimmed and lowercase, or `None` if it's not a valid label
+pub(crate) fn normalize_label(label: &str) -> Option<String> {
+    let label = label.trim().trim_start_matches('#').to_lowercase();
+    let is_valid = !label.is_empty()
+        && label.chars().count() <= MAX_LABEL_LENGTH
+        && !label.contains(|c: char| c.is_whitespace() || c == ',' || c == ':');
+    is_valid.then_some(label)
+}
+
+/// ID of a tag, the first half of the Blake3 hash of `<uri>:<label>` in Crockford base32
+fn tag_id(uri: &str, label: &str) -> String {
+    let hash = blake3::hash(format!("{uri}:{label}").as_bytes());
+    base32::encode(base32::Alphabet::Crockford, &hash.as_bytes()[..16])
+}
+
+/// `pubky://` URL of a page, as tagged
+pub(crate) fn page_url(user_pk: &str, page_id: &str) -> Option<String> {
+    Some(format!(
+        "pubky://{}/pub/wiki.app/{page_id}",
+        parse_user_pk(user_pk)?.z32()
+    ))
+}
+
+pub(crate) async fn add_tag(session: &PubkySession, uri: &str, label: &str) -> Result<Tag> {
+    let tag = Tag {
+        uri: uri.to_string(),
+        label: label.to_string(),
+        created_at: Timestamp::now().as_u64() as i64,
+    };
+    let path = format!("/pub/pubky.app/tags/{}", tag_id(uri, label));
+    session
+        .storage()
+        .put(&path, serde_json::to_string(&tag)?)
+        .await?;
+
+    log::info!("Tagged {uri} with {label}");
+    Ok(tag)
+}
+
+pub(crate) async fn remove_tag(session: &PubkySession, uri: &str, label: &str) -> Result<()> {
+    let path = format!("/pub/pubky.app/tags/{}", tag_id(uri, label));
+    session.storage().delete(&path).await?;
+
+    log::info!("Removed tag {label} from {uri}");
+    Ok(())
+}
+
+/// Whether a tag points at a wiki page, rather than at a pubky.app post or user
+fn is_page_uri(uri: &str) -> bool {
+    uri.starts_with("pubky://") && WikiLink::parse(uri).is_some()
+}
+
+/// Tags of wiki pages written by a user, skipping their other pubky.app tags
+async fn fetch_user_tags(pub_storage: &PublicStorage, user_pk: &str) -> Result<Vec<Tag>> {
+    let folder_url = format!("pubky://{user_pk}/pub/pubky.app/tags/");
+
+    let mut tags = vec![];
+    for file_url in list_all(|| pub_storage.list(&folder_url), false).await? {
+        let tag = async {
+            anyhow::Ok(serde_json::from_str::<Tag>(
+                &pub_storage.get(&file_url).await?.text().await?,
+            )?)
+        };
+        match tag.await {
+            Ok(tag) if is_page_uri(&tag.uri) => tags.push(tag),
+            Ok(_) => {}
+            Err(e) => log::error!("Invalid tag {file_url}: {e}"),
+        }
+    }
+    Ok(tags)
+}
+
+/// Tags of the user and their follows
+async fn fetch_tags(session: &PubkySession, pub_storage: &PublicStorage) -> UserTags {
+    let own_pk = session.info().public_key().z32();
+    let follows = get_follows(session)
+        .await
+        .inspect_err(|e| log::error!("Failed to get follows: {e}"))
+        .unwrap_or_default();
+
+    let mut tags = vec![];
+    for user_pk in std::iter::once(own_pk).chain(follows) {
+        match fetch_user_tags(pub_storage, &user_pk).await {
+            Ok(user_tags) => tags.extend(user_tags.into_iter().map(|tag| (user_pk.clone(), tag))),
+            Err(e) => log::error!("Failed to list tags of {user_pk}: {e}"),
+        }
+    }
+    tags
+}
+
+/// Tagged page URLs by label, with the number of users who tagged each page, most used labels first
+pub(crate) fn group_by_label(tags: &[(String, Tag)]) -> Vec<(String, Vec<(String, usize)>)> {
+    let mut by_label: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+    for (_, tag) in tags {
+        *by_label
+            .entry(&tag.label)
+            .or_default()
+            .entry(&tag.uri)
+            .or_default() += 1;
+    }
+
+    let mut groups: Vec<(String, Vec<(String, usize)>)> = by_label
+        .into_iter()
+        .map(|(label, pages)| {
+            let pages = pages
+                .into_iter()
+                .map(|(uri, count)| (uri.to_string(), count))
+                .collect();
+            (label.to_string(), pages)
+        })
+        .collect();
+    groups.sort_by_key(|(_, pages)| std::cmp::Reverse(pages.len()));
+    groups
+}
+
+impl PubkyApp {
+    /// Tags of the user and their follows, or `None` while they are fetched in the background
+    pub(crate) fn get_tags(
+        &mut self,
+        session: &PubkySession,
+        pub_storage: &PublicStorage,
+        ctx: &Context,
+    ) -> Option<&mut TagCache> {
+        let state = self.tags.get_or_insert_with(|| {
+            let result = Arc::new(Mutex::new(None));
+            let task_result = result.clone();
+            let (session, pub_storage, ctx) = (session.clone(), pub_storage.clone(), ctx.clone());
+            self.rt.spawn(async move {
+                let tags = fetch_tags(&session, &pub_storage).await;
+                *task_result.lock().unwrap() = Some(tags);
+                ctx.request_repaint();
+            });
+            TagsState::Loading(result)
+        });
+
+        if let TagsState::Loading(result) = state {
+            let tags = result.lock().unwrap().take()?;
+            *state = TagsState::Loaded(TagCache {
+                tags,
+                titles: HashMap::new(),
+            });
+        }
+        match state {
+            TagsState::Loaded(cache) => Some(cache),
+            TagsState::Loading(_) => None,
+        }
+    }
+
+    /// Tag a page with a label, or remove the user's tag if `remove`, asking for the tags capability first if needed
+    fn update_tag(&mut self, session: &PubkySession, uri: &str, label: &str, remove: bool) {
+        if !self.ensure_capabilities(session, Feature::Tags) {
+            return;
+        }
+
+        let own_pk = session.info().public_key().z32();
+        let result = match remove {
+            true => self
+                .rt
+                .block_on(remove_tag(session, uri, label))
+                .map(|()| None),
+            false => self.rt.block_on(add_tag(session, uri, label)).map(Some),
+        };
+        match result {
+            Ok(added) => {
+                // A fetch in progress may miss the change, so it is started again
+                match &mut self.tags {
+                    Some(TagsState::Loaded(cache)) => {
+                        cache.tags.retain(|(tagger, tag)| {
+                            !(*tagger == own_pk && tag.uri == uri && tag.label == label)
+                        });
+                        cache.tags.extend(added.map(|tag| (own_pk, tag)));
+                    }
+                    _ => self.tags = None,
+                }
+            }
+            Err(e) => log::error!("Failed to update tag {label} of {uri}: {e}"),
+        }
+    }
+
+    /// Title of a tagged page, fetched on first use
+    fn tagged_page_title(&mut self, pub_storage: &PublicStorage, uri: &str) -> String {
+        let rt = self.rt.clone();
+        let Some(TagsState::Loaded(cache)) = &mut self.tags else {
+            return String::new();
+        };
+        let titles = &mut cache.titles;
+
+        titles
+            .entry(uri.to_string())
+            .or_insert_with(|| match get_content(pub_storage, uri, rt) {
+                Ok(content) => extract_title(&content).trim().to_string(),
+                Err(e) => {
+                    log::error!("Error fetching path {uri}: {e}");
+                    String::new()
+                }
+            })
+            .clone()
+    }
+}
+
+/// Tags of the selected page, with a field to add one
+pub(crate) fn show_page_tags(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    ui: &mut Ui,
+) {
+    let Some(uri) = page_url(&app.selected_wiki_user_id, &app.selected_wiki_page_id) else {
+        return;
+    };
+    let own_pk = session.info().public_key().z32();
+
+    let Some(cache) = app.get_tags(session, pub_storage, ui.ctx()) else {
+        CollapsingHeader::new(egui::RichText::new("🏷 Tags (…)").size(15.0)).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Loading tags…");
+            });
+        });
+        return;
+    };
+
+    // Label, number of users who used it and whether the user did
+    let mut labels: BTreeMap<String, (usize, bool)> = BTreeMap::new();
+    for (tagger, tag) in &cache.tags {
+        if tag.uri == uri {
+            let (count, is_own) = labels.entry(tag.label.clone()).or_default();
+            *count += 1;
+            *is_own |= *tagger == own_pk;
+        }
+    }
+
+    let mut update = None;
+    CollapsingHeader::new(egui::RichText::new(format!("🏷 Tags ({})", labels.len())).size(15.0))
+        .show(ui, |ui| {
+            ui.add_space(5.0);
+            ui.horizontal_wrapped(|ui| {
+                for (label, (count, is_own)) in &labels {
+                    let text = match count {
+                        1 => format!("#{label}"),
+                        _ => format!("#{label} ×{count}"),
+                    };
+                    match is_own {
+                        true => {
+                            let button = ui
+                                .button(format!("{text} ✖"))
+                                .on_hover_text("Remove your tag");
+                            if button.clicked() {
+                                update = Some((label.clone(), true));
+                            }
+                        }
+                        false => {
+                            let button = ui.button(text).on_hover_text("Tag with this label too");
+                            if button.clicked() {
+                                update = Some((label.clone(), false));
+                            }
+                        }
+                    }
+                }
+            });
+
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut app.tag_input)
+                        .hint_text("New tag")
+                        .desired_width(150.0),
+                );
+                let label = normalize_label(&app.tag_input);
+                if ui
+                    .add_enabled(label.is_some(), egui::Button::new("➕ Add tag"))
+                    .clicked()
+                {
+                    update = label.map(|label| (label, false));
+                    app.tag_input.clear();
+                }
+            });
+        });
+
+    if let Some((label, remove)) = update {
+        app.update_tag(session, &uri, &label, remove);
+    }
+}
+
+/// Pages tagged by the user and their follows, grouped by tag
+pub(crate) fn update(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    ui: &mut Ui,
+) {
+    history::show_nav_buttons(app, session, pub_storage, ui);
+    ui.label(egui::RichText::new("Tags").size(20.0).strong());
+    ui.add_space(5.0);
+    ui.label(
+        egui::RichText::new("Pages tagged by you and your follows").color(egui::Color32::GRAY),
+    );
+    ui.add_space(15.0);
+
+    let groups = app
+        .get_tags(session, pub_storage, ui.ctx())
+        .map(|cache| group_by_label(&cache.tags));
+
+    let mut open_page = None;
+    egui::ScrollArea::vertical()
+        .max_height(450.0)
+        .show(ui, |ui| {
+            let Some(groups) = &groups else {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Loading tags…");
+                });
+                return;
+            };
+            if groups.is_empty() {
+                ui.label(
+                    egui::RichText::new("No tagged pages yet.")
+                        .italics()
+                        .color(egui::Color32::GRAY),
+                );
+            }
+            for (label, pages) in groups {
+                let header = egui::RichText::new(format!("#{label} ({})", pages.len())).size(15.0);
+                CollapsingHeader::new(header).id_salt(label).show(ui, |ui| {
+                    for (uri, count) in pages {
+                        let Some(link) = WikiLink::parse(uri) else {
+                            continue;
+                        };
+                        let user_pk = link.user_pk.clone().unwrap_or_default();
+                        let title = match app.tagged_page_title(pub_storage, uri) {
+                            title if title.is_empty() => link.page_id.clone(),
+                            title => title,
+                        };
+
+                        ui.horizontal(|ui| {
+                            if ui
+                                .button(egui::RichText::new(title).strong())
+                                .on_hover_text(uri)
+                                .clicked()
+                            {
+                                open_page = Some((user_pk.clone(), link.page_id.clone()));
+                            }
+                            ui.label(format!(
+                                "by {}",
+                                app.display_name(&user_pk, pub_storage, ui.ctx())
+                            ));
+                            if *count > 1 {
+                                ui.label(egui::RichText::new(format!("tagged by {count}")).small());
+                            }
+                        });
+                    }
+                });
+            }
+        });
+
+    if let Some((user_pk, page_id)) = open_page {
+        app.navigate_to_view_wiki_page(&user_pk, &page_id, session, pub_storage);
+    }
+
+    ui.add_space(15.0);
+    ui.horizontal(|ui| {
+        let refresh_button = ui.add_sized(
+            [120.0, 35.0],
+            egui::Button::new(egui::RichText::new("🔄 Refresh").size(15.0)),
+        );
+        if refresh_button.clicked() {
+            app.tags = None;
+        }
+        ui.add_space(10.0);
+        let back_button = ui.add_sized(
+            [120.0, 35.0],
+            egui::Button::new(egui::RichText::new("← Back").size(15.0)),
+        );
+        if back_button.clicked() {
+            app.go_back(session, pub_storage);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(uri: &str, label: &str) -> Tag {
+        Tag {
+            uri: uri.into(),
+            label: label.into(),
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn normalizes_labels() {
+        assert_eq!(normalize_label(" #Rust ").as_deref(), Some("rust"));
+        assert_eq!(
+            normalize_label("knowledge-garden").as_deref(),
+            Some("knowledge-garden")
+        );
+        assert_eq!(normalize_label("two words"), None);
+        assert_eq!(normalize_label("a-label-longer-than-twenty"), None);
+        assert_eq!(normalize_label(""), None);
+    }
+
+    #[test]
+    fn derives_ids_from_uri_and_label() {
+        let id = tag_id("pubky://pk/pub/wiki.app/id-1", "rust");
+        assert_eq!(id.len(), 26);
+        assert_eq!(id, tag_id("pubky://pk/pub/wiki.app/id-1", "rust"));
+        assert_ne!(id, tag_id("pubky://pk/pub/wiki.app/id-1", "lugano"));
+    }
+
+    #[test]
+    fn keeps_tags_of_pages_only() {
+        let pk = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+        assert!(is_page_uri(&format!("pubky://{pk}/pub/wiki.app/id-1")));
+        assert!(!is_page_uri(&format!(
+            "pubky://{pk}/pub/wiki.app/.trash/id-1"
+        )));
+        assert!(!is_page_uri(&format!(
+            "pubky://{pk}/pub/pubky.app/posts/0033SSE3B1FQ0"
+        )));
+        assert!(!is_page_uri(&format!(
+            "pubky://{pk}/pub/pubky.app/profile.json"
+        )));
+        assert!(!is_page_uri(&format!("{pk}/id-1")));
+    }
+
+    #[test]
+    fn groups_pages_by_label() {
+        let tags = [
+            (
+                "pk-1".to_string(),
+                tag("pubky://pk/pub/wiki.app/id-1", "lugano"),
+            ),
+            (
+                "pk-2".to_string(),
+                tag("pubky://pk/pub/wiki.app/id-1", "lugano"),
+            ),
+            (
+                "pk-1".to_string(),
+                tag("pubky://pk/pub/wiki.app/id-2", "lugano"),
+            ),
+            (
+                "pk-1".to_string(),
+                tag("pubky://pk/pub/wiki.app/id-2", "alps"),
+            ),
+        ];
+        let groups = group_by_label(&tags);
+        assert_eq!(groups[0].0, "lugano");
+        assert_eq!(
+            groups[0].1,
+            [
+                ("pubky://pk/pub/wiki.app/id-1".to_string(), 2),
+                ("pubky://pk/pub/wiki.app/id-2".to_string(), 1)
+            ]
+        );
+        assert_eq!(groups[1].0, "alps");
+    }
+}
@@ -0,0 +1,336 @@
+//! Soft deletion of pages: deleted pages are moved to a trash folder on the homeserver,
+//! from where they can be restored or purged. Purging a page also deletes its revisions.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use eframe::egui::{Context, Ui};
+use pubky::{PubkySession, PublicStorage};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::{
+    delete_wiki_post, history,
+    revisions::delete_history,
+    utils::{extract_title, format_age, get_list, now_timestamp},
+    AuthState, PubkyApp,
+};
+
+const TRASH_PATH: &str = "/pub/wiki.app/.trash/";
+
+/// How long the undo toast stays visible after a deletion
+const UNDO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A deleted page, as stored in the trash
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct TrashedPage {
+    pub(crate) page_id: String,
+    pub(crate) content: String,
+    /// Unix timestamp (seconds) of the deletion
+    pub(crate) deleted_at: u64,
+}
+
+/// A deletion that can still be undone from the toast
+pub(crate) struct UndoDelete {
+    page_id: String,
+    title: String,
+    deleted_at: Instant,
+}
+
+fn trash_path(page_id: &str) -> String {
+    format!("{TRASH_PATH}{page_id}")
+}
+
+/// Move a page to the trash
+pub(crate) async fn trash_wiki_post(session: &PubkySession, page_id: &str) -> Result<TrashedPage> {
+    let path = format!("/pub/wiki.app/{}", page_id);
+    let content = session.storage().get(&path).await?.text().await?;
+
+    let trashed_page = TrashedPage {
+        page_id: page_id.into(),
+        content,
+        deleted_at: now_timestamp(),
+    };
+    session
+        .storage()
+        .put(trash_path(page_id), serde_json::to_string(&trashed_page)?)
+        .await?;
+    delete_wiki_post(session, page_id).await?;
+
+    log::info!("Moved post {page_id} to trash");
+
+    Ok(trashed_page)
+}
+
+/// Put a page back from the trash, at its original path
+pub(crate) async fn restore_wiki_post(session: &PubkySession, page_id: &str) -> Result<TrashedPage> {
+    let path = format!("/pub/wiki.app/{}", page_id);
+    if session.storage().exists(&path).await? {
+        anyhow::bail!("A page already exists at {path}");
+    }
+
+    let trashed_page = get_trashed_page(session, page_id).await?;
+    session.storage().put(&path, trashed_page.content.clone()).await?;
+    session.storage().delete(trash_path(page_id)).await?;
+
+    log::info!("Restored post {page_id} from trash");
+
+    Ok(trashed_page)
+}
+
+async fn get_trashed_page(session: &PubkySession, page_id: &str) -> Result<TrashedPage> {
+    let json = session.storage().get(trash_path(page_id)).await?.text().await?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Permanently delete a page from the trash
+pub(crate) async fn purge_wiki_post(session: &PubkySession, page_id: &str) -> Result<()> {
+    session.storage().delete(trash_path(page_id)).await?;
+
+    // A page created again with the same ID since keeps the history
+    if !session.storage().exists(format!("/pub/wiki.app/{page_id}")).await? {
+        delete_history(session, page_id).await?;
+    }
+
+    log::info!("Purged post {page_id} from trash");
+
+    Ok(())
+}
+
+/// All the pages in the trash, most recently deleted first
+fn list_trash(session: &PubkySession, rt: Arc<Runtime>) -> Result<Vec<TrashedPage>> {
+    let mut result = vec![];
+    for file_url in get_list(session, TRASH_PATH, rt.clone())? {
+        let Some(page_id) = file_url.split('/').next_back() else {
+            continue;
+        };
+        match rt.block_on(get_trashed_page(session, page_id)) {
+            Ok(trashed_page) => result.push(trashed_page),
+            Err(e) => log::error!("Error reading trashed page {page_id}: {e}"),
+        }
+    }
+    result.sort_by_key(|trashed_page| std::cmp::Reverse(trashed_page.deleted_at));
+
+    Ok(result)
+}
+
+impl PubkyApp {
+    /// Move the selected page to the trash, and go back to where the user was before opening it
+    pub(crate) fn trash_selected_page(&mut self, session: &PubkySession, pub_storage: &PublicStorage) {
+        let page_id = self.selected_wiki_page_id.clone();
+
+        match self.rt.block_on(trash_wiki_post(session, &page_id)) {
+            Ok(trashed_page) => {
+                // Remove from file_urls list
+                if let Ok(mut state) = self.state.lock() {
+                    if let AuthState::Authenticated {
+                        ref session,
+                        ref mut file_cache,
+                        ..
+                    } = *state
+                    {
+                        let own_user_pk = session.info().public_key().z32();
+                        let file_url = format!("pubky://{own_user_pk}/pub/wiki.app/{page_id}");
+                        file_cache.remove(&file_url);
+                    }
+                }
+                self.discard_draft(session);
+
+                self.undo_delete = Some(UndoDelete {
+                    page_id: page_id.clone(),
+                    title: extract_title(&trashed_page.content).to_string(),
+                    deleted_at: Instant::now(),
+                });
+                self.trash = None;
+
+                self.edit_wiki_content.clear();
+                self.needs_refresh = true;
+                let user_pk = self.selected_wiki_user_id.clone();
+                self.leave_deleted_page(&user_pk, &page_id, session, pub_storage);
+            }
+            Err(e) => {
+                log::error!("Failed to delete wiki post: {e}");
+                self.keep_draft(session);
+                self.edit_wiki_content.clear();
+                self.go_back(session, pub_storage);
+            }
+        }
+    }
+
+    fn restore_trashed_page(&mut self, session: &PubkySession, page_id: &str) {
+        match self.rt.block_on(restore_wiki_post(session, page_id)) {
+            Ok(_) => self.needs_refresh = true,
+            Err(e) => log::error!("Failed to restore wiki post {page_id}: {e}"),
+        }
+        self.trash = None;
+    }
+}
+
+/// Ask for confirmation before moving the selected page to the trash
+pub(crate) fn show_confirm_delete_window(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    ctx: &Context,
+) {
+    if !app.confirm_delete {
+        return;
+    }
+
+    egui::Window::new("Delete Page")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            let title = extract_title(&app.selected_wiki_content);
+            ui.label(format!("Move \"{title}\" to the trash?"));
+            ui.label(
+                egui::RichText::new("It can be restored from the Trash view.")
+                    .color(egui::Color32::GRAY),
+            );
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                let delete_button = egui::Button::new(
+                    egui::RichText::new("🗑 Move to Trash").color(egui::Color32::from_rgb(200, 80, 80)),
+                );
+                if ui.add(delete_button).clicked() {
+                    app.confirm_delete = false;
+                    app.trash_selected_page(session, pub_storage);
+                }
+                if ui.button("Cancel").clicked() {
+                    app.confirm_delete = false;
+                }
+            });
+        });
+}
+
+/// Ask for confirmation before permanently deleting a page from the trash
+fn show_confirm_purge_window(app: &mut PubkyApp, session: &PubkySession, ctx: &Context) {
+    let Some(trashed_page) = app.confirm_purge.clone() else {
+        return;
+    };
+
+    egui::Window::new("Delete Forever")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            let title = extract_title(&trashed_page.content);
+            ui.label(format!("Permanently delete \"{title}\" and its history?"));
+            ui.label(egui::RichText::new("This cannot be undone.").color(egui::Color32::GRAY));
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                let purge_button = egui::Button::new(
+                    egui::RichText::new("Delete Forever").color(egui::Color32::from_rgb(200, 80, 80)),
+                );
+                if ui.add(purge_button).clicked() {
+                    app.confirm_purge = None;
+                    match app.rt.block_on(purge_wiki_post(session, &trashed_page.page_id)) {
+                        Ok(()) => app.trash = None,
+                        Err(e) => log::error!("Failed to purge wiki post: {e}"),
+                    }
+                }
+                if ui.button("Cancel").clicked() {
+                    app.confirm_purge = None;
+                }
+            });
+        });
+}
+
+/// Offer to undo the last deletion, for a few seconds
+pub(crate) fn show_undo_toast(app: &mut PubkyApp, session: &PubkySession, ctx: &Context) {
+    let Some(undo) = &app.undo_delete else {
+        return;
+    };
+    if undo.deleted_at.elapsed() >= UNDO_TIMEOUT {
+        app.undo_delete = None;
+        return;
+    }
+
+    let page_id = undo.page_id.clone();
+    let text = format!("Moved \"{}\" to the trash", undo.title);
+    let mut undo_clicked = false;
+
+    egui::Area::new(egui::Id::new("undo_delete_toast"))
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -20.0])
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(text);
+                    undo_clicked = ui.button("Undo").clicked();
+                });
+            });
+        });
+    // Repaint to hide the toast once it timed out
+    ctx.request_repaint_after(Duration::from_secs(1));
+
+    if undo_clicked {
+        app.undo_delete = None;
+        app.restore_trashed_page(session, &page_id);
+    }
+}
+
+/// Trash view: restore or permanently purge deleted pages
+pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, pub_storage: &PublicStorage, ctx: &Context, ui: &mut Ui) {
+    history::show_nav_buttons(app, session, pub_storage, ui);
+    if ui.button("← Back").clicked() {
+        app.go_back(session, pub_storage);
+        return;
+    }
+    ui.add_space(10.0);
+
+    ui.label(egui::RichText::new("Trash").size(20.0).strong());
+    ui.add_space(15.0);
+
+    if app.trash.is_none() {
+        app.trash = Some(
+            list_trash(session, app.rt.clone())
+                .inspect_err(|e| log::error!("Failed to list trash: {e}"))
+                .unwrap_or_default(),
+        );
+    }
+    let trashed_pages = app.trash.clone().unwrap_or_default();
+    show_confirm_purge_window(app, session, ctx);
+
+    if trashed_pages.is_empty() {
+        ui.label(egui::RichText::new("The trash is empty.").italics().color(egui::Color32::GRAY));
+        return;
+    }
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for trashed_page in trashed_pages {
+            ui.horizontal(|ui| {
+                if ui.button("Restore").clicked() {
+                    app.restore_trashed_page(session, &trashed_page.page_id);
+                }
+
+                let purge_button = egui::Button::new(
+                    egui::RichText::new("Delete Forever").color(egui::Color32::from_rgb(200, 80, 80)),
+                );
+                if ui
+                    .add(purge_button)
+                    .on_hover_text("Permanently delete this page and its history")
+                    .clicked()
+                {
+                    app.confirm_purge = Some(trashed_page.clone());
+                }
+
+                ui.label(egui::RichText::new(extract_title(&trashed_page.content)).strong());
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{}, deleted {}",
+                        trashed_page.page_id,
+                        format_age(trashed_page.deleted_at)
+                    ))
+                    .color(egui::Color32::GRAY),
+                );
+            });
+            ui.add_space(5.0);
+        }
+    });
+}
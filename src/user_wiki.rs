@@ -0,0 +1,196 @@
+//! Browsing the wiki of any user by public key, typed, pasted or picked from the user's follows.
+
+use std::sync::Arc;
+
+use eframe::egui::Ui;
+use pubky::{PubkySession, PublicKey, PublicStorage};
+use tokio::runtime::Runtime;
+
+use crate::{
+    follows::show_follow_button,
+    history,
+    profiles::show_user,
+    utils::{extract_title, get_content, get_page_list},
+    PubkyApp, ViewState,
+};
+
+/// Pages of the user being browsed
+pub(crate) struct UserWiki {
+    pub(crate) user_pk: String,
+    /// Page ID and title of each page, sorted by title
+    pub(crate) pages: Vec<(String, String)>,
+    /// Why the pages could not be listed
+    pub(crate) error: Option<String>,
+}
+
+/// Public key in a typed or pasted text: a bare key, a `pubky<key>` or a `pubky://` URL, or a page link
+pub(crate) fn parse_user_pk(input: &str) -> Option<PublicKey> {
+    let input = input.trim().trim_start_matches("pubky://");
+    let user_pk = input.split('/').next()?;
+    PublicKey::try_from(user_pk).ok()
+}
+
+fn fetch_user_wiki(pub_storage: &PublicStorage, user_pk: &str, rt: Arc<Runtime>) -> UserWiki {
+    let file_urls = match get_page_list(pub_storage, user_pk, rt.clone()) {
+        Ok(file_urls) => file_urls,
+        Err(e) => {
+            log::error!("Failed to list files of {user_pk}: {e}");
+            return UserWiki {
+                user_pk: user_pk.to_string(),
+                pages: vec![],
+                error: Some(format!("Could not list the pages: {e}")),
+            };
+        }
+    };
+
+    let mut pages = vec![];
+    for file_url in file_urls {
+        let page_id = file_url.split('/').next_back().unwrap_or(&file_url).to_string();
+        match get_content(pub_storage, &file_url, rt.clone()) {
+            Ok(content) => pages.push((page_id, extract_title(&content).trim().to_string())),
+            Err(e) => log::error!("Error fetching path {file_url}: {e}"),
+        }
+    }
+    pages.sort_by_key(|(_, title)| title.to_lowercase());
+
+    UserWiki {
+        user_pk: user_pk.to_string(),
+        pages,
+        error: None,
+    }
+}
+
+impl PubkyApp {
+    pub(crate) fn navigate_to_user_wiki(&mut self, user_pk: &str, pub_storage: &PublicStorage) {
+        let current = self.current_history_entry();
+        self.history.visit(current);
+
+        self.show_user_wiki(user_pk, pub_storage);
+    }
+
+    /// List the pages of a user, without recording it in the history
+    pub(crate) fn show_user_wiki(&mut self, user_pk: &str, pub_storage: &PublicStorage) {
+        self.user_wiki_input = user_pk.to_string();
+        self.user_wiki = Some(fetch_user_wiki(pub_storage, user_pk, self.rt.clone()));
+        self.view_state = ViewState::UserWiki;
+    }
+}
+
+pub(crate) fn update(app: &mut PubkyApp, session: &PubkySession, pub_storage: &PublicStorage, ui: &mut Ui) {
+    history::show_nav_buttons(app, session, pub_storage, ui);
+    ui.label(egui::RichText::new("Browse a User's Wiki").size(20.0).strong());
+    ui.add_space(15.0);
+
+    let mut show = None;
+    ui.horizontal(|ui| {
+        ui.label("Public key:");
+        let input = ui.add(
+            egui::TextEdit::singleline(&mut app.user_wiki_input)
+                .hint_text("Key or page link")
+                .desired_width(360.0),
+        );
+        let user_pk = parse_user_pk(&app.user_wiki_input);
+        let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        let show_button = ui.add_enabled(user_pk.is_some(), egui::Button::new("📖 Show pages"));
+        if show_button.clicked() || submitted {
+            show = user_pk.map(|user_pk| user_pk.z32());
+        }
+    });
+    if !app.user_wiki_input.trim().is_empty() && parse_user_pk(&app.user_wiki_input).is_none() {
+        ui.label(egui::RichText::new("Not a valid public key").small().color(egui::Color32::GRAY));
+    }
+
+    ui.add_space(5.0);
+    if app.follows.is_none() {
+        app.follows = Some(app.get_my_follows(session));
+    }
+    let follows: Vec<(String, String)> = app
+        .follows
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|follow_pk| (app.display_name(&follow_pk, pub_storage, ui.ctx()), follow_pk))
+        .collect();
+    if !follows.is_empty() {
+        egui::ComboBox::from_label("or pick one of your follows")
+            .selected_text("Follows")
+            .show_ui(ui, |ui| {
+                for (name, follow_pk) in &follows {
+                    if ui.selectable_label(false, name).on_hover_text(follow_pk).clicked() {
+                        show = Some(follow_pk.clone());
+                    }
+                }
+            });
+    }
+
+    if let Some(user_pk) = show {
+        app.navigate_to_user_wiki(&user_pk, pub_storage);
+    }
+
+    ui.add_space(15.0);
+    ui.separator();
+    ui.add_space(15.0);
+
+    if let Some(user_pk) = app.user_wiki.as_ref().map(|user_wiki| user_wiki.user_pk.clone()) {
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("Pages by").size(16.0).strong());
+            show_user(app, ui, &user_pk, pub_storage, 32.0);
+            show_follow_button(app, session, &user_pk, ui);
+        });
+        if let Some(bio) = app.bio(&user_pk, pub_storage, ui.ctx()) {
+            ui.label(egui::RichText::new(bio).italics());
+        }
+        ui.add_space(10.0);
+    }
+
+    let mut open_page = None;
+    if let Some(user_wiki) = &app.user_wiki {
+        egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+            if let Some(error) = &user_wiki.error {
+                ui.colored_label(egui::Color32::RED, error);
+            } else if user_wiki.pages.is_empty() {
+                ui.label(egui::RichText::new("No wiki pages yet.").italics().color(egui::Color32::GRAY));
+            }
+            for (page_id, title) in &user_wiki.pages {
+                ui.horizontal(|ui| {
+                    if ui.button(egui::RichText::new(page_id).monospace()).clicked() {
+                        open_page = Some((user_wiki.user_pk.clone(), page_id.clone()));
+                    }
+                    ui.label(egui::RichText::new(title).strong());
+                });
+                ui.add_space(5.0);
+            }
+        });
+    }
+
+    if let Some((user_pk, page_id)) = open_page {
+        app.navigate_to_view_wiki_page(&user_pk, &page_id, session, pub_storage);
+    }
+
+    ui.add_space(15.0);
+    let back_button = ui.add_sized([120.0, 35.0], egui::Button::new(egui::RichText::new("← Back").size(15.0)));
+    if back_button.clicked() {
+        app.go_back(session, pub_storage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PK: &str = "6ookcbkiyn8ced651eu6rqgm5o1prorajzxgyhg4bxkkcfduzo4y";
+
+    #[test]
+    fn parses_pasted_keys() {
+        for input in [
+            PK.to_string(),
+            format!(" pubky{PK} "),
+            format!("pubky://{PK}/pub/wiki.app/id-1"),
+            format!("{PK}/id-1#history"),
+        ] {
+            assert_eq!(parse_user_pk(&input).map(|pk| pk.z32()).as_deref(), Some(PK), "{input}");
+        }
+        assert!(parse_user_pk("not a key").is_none());
+        assert!(parse_user_pk("").is_none());
+    }
+}
@@ -1,6 +1,9 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use pubky::PubkySession;
+use pubky::{ListBuilder, PubkySession, PublicStorage};
 use qrcode::QrCode;
 use tokio::runtime::Runtime;
 
@@ -39,20 +42,40 @@ pub fn extract_title(input: &str) -> &str {
     first_line.trim_start_matches("# ")
 }
 
-pub fn extract_details_wiki_url(url: &str) -> Option<(String, String)> {
-    // Split once on '/' and collect the two parts.
-    let mut parts = url.splitn(2, '/');
-
-    let first = parts.next()?.trim();
-    let second = parts.next()?.trim();
+/// List every file of a folder. A single LIST returns at most as many entries as the
+/// homeserver allows, so the listing is continued from the last entry until it comes back empty.
+pub async fn list_all<'a>(
+    list: impl Fn() -> pubky::Result<ListBuilder<'a>>,
+    shallow: bool,
+) -> anyhow::Result<Vec<String>> {
+    let mut result_list: Vec<String> = vec![];
+    loop {
+        let mut list_builder = list()?.shallow(shallow);
+        if let Some(cursor) = result_list.last() {
+            list_builder = list_builder.cursor(cursor);
+        }
 
-    // Ensure both parts are present and not empty.
-    if first.is_empty() || second.is_empty() {
-        log::warn!("Invalid Pubky Wiki link: {url}");
-        return None;
+        let entries = list_builder.send().await?;
+        if entries.is_empty() {
+            break;
+        }
+        result_list.extend(entries.iter().map(|entry| entry.to_pubky_url()));
     }
 
-    Some((first.to_string(), second.to_string()))
+    Ok(result_list)
+}
+
+/// List the wiki pages of a folder. The listing is shallow, so the revisions, fork points and
+/// trash stored below the pages are neither counted nor returned.
+pub async fn list_pages<'a>(
+    list: impl Fn() -> pubky::Result<ListBuilder<'a>>,
+) -> anyhow::Result<Vec<String>> {
+    let file_urls = list_all(list, true).await?;
+
+    Ok(file_urls
+        .into_iter()
+        .filter(|url| !url.ends_with('/') && is_wiki_page_url(url))
+        .collect())
 }
 
 /// List files from the homeserver
@@ -62,14 +85,156 @@ pub fn get_list(
     rt: Arc<Runtime>,
 ) -> anyhow::Result<Vec<String>> {
     let session_storage = session.storage();
-    let session_storage_list_fut = session_storage.list(folder_path).unwrap().send();
 
     log::info!("listing {folder_path}");
 
-    let mut result_list = vec![];
-    for entry in rt.block_on(session_storage_list_fut)? {
-        result_list.push(entry.to_pubky_url());
+    rt.block_on(list_all(|| session_storage.list(folder_path), false))
+}
+
+/// List files from any user's homeserver, given the pubky URL of a folder
+pub fn get_public_list(
+    pub_storage: &PublicStorage,
+    folder_url: &str,
+    rt: Arc<Runtime>,
+) -> anyhow::Result<Vec<String>> {
+    log::info!("listing {folder_url}");
+
+    rt.block_on(list_all(|| pub_storage.list(folder_url), false))
+}
+
+/// List the wiki pages of any user, as pubky URLs
+pub fn get_page_list(
+    pub_storage: &PublicStorage,
+    user_pk: &str,
+    rt: Arc<Runtime>,
+) -> anyhow::Result<Vec<String>> {
+    let folder_url = format!("pubky://{user_pk}/pub/wiki.app/");
+
+    log::info!("listing {folder_url}");
+
+    rt.block_on(list_pages(|| pub_storage.list(&folder_url)))
+}
+
+/// Fetch the text content of a file, given its pubky URL
+pub fn get_content(
+    pub_storage: &PublicStorage,
+    file_url: &str,
+    rt: Arc<Runtime>,
+) -> anyhow::Result<String> {
+    let response = rt.block_on(pub_storage.get(file_url))?;
+    let content = rt.block_on(response.text())?;
+
+    Ok(content)
+}
+
+/// Get the content of a file of the signed in user, such as `/pub/wiki.app/<page id>`
+pub fn get_own_content(session: &PubkySession, path: &str, rt: Arc<Runtime>) -> anyhow::Result<String> {
+    let response = rt.block_on(session.storage().get(path))?;
+    let content = rt.block_on(response.text())?;
+
+    Ok(content)
+}
+
+/// Whether a listed file URL is a wiki page, and not an app file such as the trash
+pub fn is_wiki_page_url(file_url: &str) -> bool {
+    !file_url.contains("/pub/wiki.app/.")
+}
+
+/// Current Unix timestamp, in seconds
+pub fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// How long ago a timestamp was, in a human readable form
+pub fn format_age(timestamp: u64) -> String {
+    let seconds = now_timestamp().saturating_sub(timestamp);
+    match seconds {
+        0..60 => "just now".into(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
     }
+}
 
-    Ok(result_list)
+/// Format a Unix timestamp (seconds) as an RFC 3339 UTC date, such as `2025-10-09T08:53:20Z`
+pub fn format_rfc3339(timestamp: u64) -> String {
+    // Civil date from the number of days since the epoch, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86400) as i64 + 719468;
+    let seconds = timestamp % 86400;
+
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Unix timestamp (seconds) of an RFC 3339 UTC date such as `2025-10-09T08:53:20Z`, the format
+/// of [`format_rfc3339`]
+pub fn parse_rfc3339(date: &str) -> Option<u64> {
+    let (date, time) = date.trim().strip_suffix('Z')?.split_once('T')?;
+    let numbers = |text: &str, separator| -> Option<Vec<i64>> {
+        text.splitn(3, separator).map(|part| part.parse().ok()).collect()
+    };
+    let [year, month, day] = numbers(date, '-')?[..] else {
+        return None;
+    };
+    let [hours, minutes, seconds] = numbers(time, ':')?[..] else {
+        return None;
+    };
+    let is_valid = (1..=12).contains(&month)
+        && (1..=31).contains(&day)
+        && (0..24).contains(&hours)
+        && (0..60).contains(&minutes)
+        && (0..=60).contains(&seconds);
+    if !is_valid {
+        return None;
+    }
+
+    // Days since the epoch of a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    u64::try_from(days * 86400 + hours * 3600 + minutes * 60 + seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_rfc3339(1760000000), "2025-10-09T08:53:20Z");
+    }
+
+    #[test]
+    fn parses_rfc3339() {
+        for timestamp in [0, 951782400, 1760000000] {
+            assert_eq!(parse_rfc3339(&format_rfc3339(timestamp)), Some(timestamp));
+        }
+        assert_eq!(parse_rfc3339("2025-13-01T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339("2025-10-09"), None);
+    }
 }
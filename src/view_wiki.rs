@@ -1,24 +1,46 @@
-use crate::{utils::extract_details_wiki_url, PubkyApp, ViewState};
+use std::collections::HashMap;
+
+use crate::{
+    follows::show_follow_button,
+    history,
+    profiles::show_user,
+    tags::show_page_tags,
+    revisions::{revision_time, revision_url},
+    utils::{extract_title, format_rfc3339},
+    links::{expand_title_links, find_anchor, find_pages_by_title, LinkTarget, WikiLink},
+    PubkyApp, ViewState,
+};
 
 use eframe::egui::{Context, Ui};
 use egui::CollapsingHeader;
-use egui_commonmark::CommonMarkViewer;
-use pubky::{PubkySession, PublicStorage};
+use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
+use pubky::{PubkySession, PublicKey, PublicStorage};
 
 pub(crate) fn update(
     app: &mut PubkyApp,
     session: &PubkySession,
     pub_storage: &PublicStorage,
+    file_cache: &HashMap<String, String>,
     ctx: &Context,
     ui: &mut Ui,
 ) {
+    history::show_nav_buttons(app, session, pub_storage, ui);
     ui.label(egui::RichText::new("View Wiki Post").size(20.0).strong());
     ui.add_space(25.0);
 
     CollapsingHeader::new(egui::RichText::new("📋 Page Details").size(15.0)).show(ui, |ui| {
         ui.add_space(5.0);
         ui.label(egui::RichText::new(format!("Page ID: {}", &app.selected_wiki_page_id)).monospace());
-        ui.label(egui::RichText::new(format!("User ID: {}", &app.selected_wiki_user_id)).monospace());
+        ui.horizontal(|ui| {
+            ui.label("Author:");
+            let user_pk = app.selected_wiki_user_id.clone();
+            show_user(app, ui, &user_pk, pub_storage, 20.0);
+            show_follow_button(app, session, &user_pk, ui);
+        });
+        if ui.button("👥 All pages by this user").clicked() {
+            let user_pk = app.selected_wiki_user_id.clone();
+            app.navigate_to_user_wiki(&user_pk, pub_storage);
+        }
     });
 
     ui.add_space(10.0);
@@ -26,20 +48,40 @@ pub(crate) fn update(
     CollapsingHeader::new(egui::RichText::new(format!("🔀 Available Forks ({})", fork_links.len())).size(15.0)).show(ui, |ui| {
         ui.add_space(5.0);
         for fork_link in fork_links {
-            if let Some((user_pk, page_id)) = extract_details_wiki_url(&fork_link) {
-                let mut btn_label = format!("Fork: {user_pk}");
+            if let Some(link) = WikiLink::parse(&fork_link) {
+                let user_pk = link.user_pk_or(&app.selected_wiki_user_id).to_string();
+                let mut btn_label = format!("Fork: {}", app.display_name(&user_pk, pub_storage, ctx));
 
-                if &app.selected_wiki_user_id == &user_pk {
+                if app.selected_wiki_user_id == user_pk {
                     btn_label = format!("{btn_label} (current)");
                 }
 
-                if ui.button(btn_label).clicked() {
-                    app.navigate_to_view_wiki_page(&user_pk, &page_id, session, pub_storage);
-                }
+                ui.horizontal(|ui| {
+                    if ui.button(btn_label).on_hover_text(&user_pk).clicked() {
+                        app.navigate_to_wiki_link(&link, session, pub_storage);
+                    }
+                    show_follow_button(app, session, &user_pk, ui);
+                });
             }
         }
     });
 
+    ui.add_space(10.0);
+    show_page_tags(app, session, pub_storage, ui);
+
+    if let Some(revision) = app.selected_wiki_revision.clone() {
+        ui.add_space(10.0);
+        let date = revision_time(&revision).map(format_rfc3339).unwrap_or(revision);
+        ui.colored_label(
+            egui::Color32::from_rgb(200, 150, 50),
+            format!("📜 Revision of {date}, shown read-only"),
+        );
+        if ui.button("Show the latest version").clicked() {
+            let (user_pk, page_id) = (app.selected_wiki_user_id.clone(), app.selected_wiki_page_id.clone());
+            app.navigate_to_view_wiki_page(&user_pk, &page_id, session, pub_storage);
+        }
+    }
+
     ui.add_space(15.0);
     // Add "Share Page Link" button with tooltip support
     let share_button = ui.add_sized(
@@ -53,9 +95,15 @@ pub(crate) fn update(
     }
 
     if share_button.clicked() {
-        let user_id = &app.selected_wiki_user_id;
-        let page_id = &app.selected_wiki_page_id;
-        ctx.copy_text(format!("[link]({user_id}/{page_id})"));
+        let user_id = app.selected_wiki_user_id.clone();
+        let page_id = app.selected_wiki_page_id.clone();
+        let title = match extract_title(&app.selected_wiki_content).trim() {
+            "" => "link".to_string(),
+            title => title.to_string(),
+        };
+        let name = app.display_name(&user_id, pub_storage, ctx);
+        let pin = app.selected_wiki_revision.as_ref().map(|revision| format!("@{revision}")).unwrap_or_default();
+        ctx.copy_text(format!("[{title} by {name}]({user_id}/{page_id}{pin})"));
         app.show_copy_tooltip = true;
     }
 
@@ -81,7 +129,10 @@ pub(crate) fn update(
                 let path_clone = app.selected_wiki_page_id.clone();
                 let user_id = app.selected_wiki_user_id.clone();
 
-                let path = format!("pubky{user_id}/pub/wiki.app/{path_clone}");
+                let path = match (&app.selected_wiki_revision, user_id.parse::<PublicKey>()) {
+                    (Some(revision), Ok(user_pk)) => revision_url(&user_pk, &path_clone, revision),
+                    _ => format!("pubky{user_id}/pub/wiki.app/{path_clone}"),
+                };
 
                 // Synchronously fetch the content
                 let get_path_fut = public_storage_clone.get(&path);
@@ -95,34 +146,32 @@ pub(crate) fn update(
                 app.selected_wiki_content = fetched_content;
             }
 
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                CommonMarkViewer::new().max_image_width(Some(512)).show(
-                    ui,
-                    &mut app.cache,
-                    &app.selected_wiki_content.as_str(),
-                );
+            let content_output = egui::ScrollArea::vertical().show(ui, |ui| {
+                show_markdown(ui, &mut app.cache, &app.selected_wiki_content);
             });
 
-            // Intercept link clicks by checking the output commands
-            let clicked_urls: Vec<String> = ui.ctx().output_mut(|o| {
-                let mut urls = Vec::new();
-                // Drain commands to prevent external opening and capture URLs
-                o.commands.retain(|cmd| {
-                    if let egui::output::OutputCommand::OpenUrl(open_url) = cmd {
-                        log::info!("Intercepted link click: {}", open_url.url);
-                        urls.push(open_url.url.to_string());
-                        false // Remove this command to prevent external opening
-                    } else {
-                        true // Keep other commands
+            // Restore the scroll position, or scroll to the section the link pointed to
+            let mut scroll_state = content_output.state;
+            app.page_scroll_offset = scroll_state.offset.y;
+            if let Some(offset) = app.pending_page_scroll.take() {
+                scroll_state.offset.y = offset;
+                scroll_state.store(ui.ctx(), content_output.id);
+            }
+            if let Some(anchor) = app.selected_wiki_anchor.take() {
+                match find_anchor(&app.selected_wiki_content, &anchor) {
+                    Some(position) => {
+                        scroll_state.offset.y = position * content_output.content_size.y;
+                        scroll_state.store(ui.ctx(), content_output.id);
                     }
-                });
-                urls
-            });
+                    None => log::warn!("Section not found: {anchor}"),
+                }
+            }
 
             // Navigate to clicked URLs
-            for url in clicked_urls {
-                if let Some((user_pk, page_id)) = extract_details_wiki_url(&url) {
-                    app.navigate_to_view_wiki_page(&user_pk, &page_id, session, pub_storage);
+            for url in take_clicked_urls(ui.ctx()) {
+                match LinkTarget::classify(&url) {
+                    LinkTarget::Anchor(anchor) => app.selected_wiki_anchor = Some(anchor),
+                    target => follow_link(app, target, session, pub_storage, file_cache),
                 }
             }
         });
@@ -131,23 +180,25 @@ pub(crate) fn update(
 
     // Check if this is the user's own page
     let pk = session.info().public_key();
-    let is_own_page = app.selected_wiki_user_id == pk.to_string();
+    let is_own_page = app.selected_wiki_user_id == pk.z32();
+    // A pinned revision is only shown, edits and forks start from the latest version
+    let is_latest = app.selected_wiki_revision.is_none();
 
     ui.horizontal(|ui| {
         // Show Edit button only for own pages
-        if is_own_page {
+        if is_own_page && is_latest {
             let edit_button = ui.add_sized(
                 [120.0, 35.0],
                 egui::Button::new(egui::RichText::new("✏ Edit").size(15.0))
             );
             if edit_button.clicked() {
-                app.navigate_to_edit_selected_wiki_page();
+                app.navigate_to_edit_selected_wiki_page(session);
             }
             ui.add_space(10.0);
         }
 
         // Fork button - available for only when viewing other user's pages
-        if !is_own_page {
+        if !is_own_page && is_latest {
             let fork_button = ui.add_sized(
                 [120.0, 35.0],
                 egui::Button::new(egui::RichText::new("🍴 Fork").size(15.0))
@@ -155,7 +206,7 @@ pub(crate) fn update(
             if fork_button.clicked() {
                 app.edit_wiki_content = app.selected_wiki_content.clone();
                 app.forked_from_page_id = Some(app.selected_wiki_page_id.clone());
-                app.view_state = ViewState::CreateWiki;
+                app.navigate_to(ViewState::CreateWiki);
             }
             ui.add_space(10.0);
         }
@@ -166,10 +217,165 @@ pub(crate) fn update(
             egui::Button::new(egui::RichText::new("← Back").size(15.0))
         );
         if back_button.clicked() {
-            app.selected_wiki_page_id.clear();
-            app.selected_wiki_content.clear();
-            app.selected_wiki_fork_urls.clear();
-            app.view_state = ViewState::WikiList;
+            app.go_back(session, pub_storage);
         }
     });
+
+    show_title_link_picker(app, session, pub_storage, ctx);
+    show_pending_link(app, ctx);
+}
+
+/// Ask for confirmation before opening a web page, or warn about a link the app can't open
+pub(crate) fn show_pending_link(app: &mut PubkyApp, ctx: &Context) {
+    let Some(target) = app.pending_link.clone() else {
+        return;
+    };
+
+    let mut close = false;
+    match target {
+        LinkTarget::External { url, domain } => {
+            egui::Window::new("Open External Link")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label("This link leads outside of the wiki, to:");
+                    ui.label(egui::RichText::new(&domain).size(16.0).strong());
+                    ui.label(egui::RichText::new(&url).monospace().small());
+                    ui.add_space(10.0);
+                    ui.horizontal(|ui| {
+                        if ui.button("🌐 Open in browser").clicked() {
+                            ctx.open_url(egui::OpenUrl::new_tab(&url));
+                            close = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            close = true;
+                        }
+                    });
+                });
+        }
+        LinkTarget::Unsupported(url) => {
+            egui::Window::new("Unsupported Link")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.colored_label(egui::Color32::YELLOW, "⚠ This link can't be opened:");
+                    ui.label(egui::RichText::new(&url).monospace());
+                    ui.add_space(10.0);
+                    close = ui.button("OK").clicked();
+                });
+        }
+        _ => close = true,
+    }
+
+    if close {
+        app.pending_link = None;
+    }
+}
+
+/// URLs of the links clicked in the rendered markdown, removed from the output commands so
+/// that they are not opened in the system browser
+pub(crate) fn take_clicked_urls(ctx: &Context) -> Vec<String> {
+    ctx.output_mut(|o| {
+        let mut urls = Vec::new();
+        o.commands.retain(|cmd| {
+            if let egui::output::OutputCommand::OpenUrl(open_url) = cmd {
+                log::info!("Intercepted link click: {}", open_url.url);
+                urls.push(open_url.url.to_string());
+                false
+            } else {
+                true
+            }
+        });
+        urls
+    })
+}
+
+/// Follow a clicked link leading out of the current page
+pub(crate) fn follow_link(
+    app: &mut PubkyApp,
+    target: LinkTarget,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    file_cache: &HashMap<String, String>,
+) {
+    match target {
+        LinkTarget::Wiki(link) => app.navigate_to_wiki_link(&link, session, pub_storage),
+        LinkTarget::Title(title) => app.navigate_to_title_link(&title, session, pub_storage, file_cache),
+        target => app.pending_link = Some(target),
+    }
+}
+
+/// Render page content, the same way in the viewer and in the editor preview
+pub(crate) fn show_markdown(ui: &mut Ui, cache: &mut CommonMarkCache, content: &str) {
+    CommonMarkViewer::new().max_image_width(Some(512)).show(
+        ui,
+        cache,
+        &expand_title_links(content),
+    );
+}
+
+/// Let the user pick among the pages matching a title link, or create the page if none matches
+pub(crate) fn show_title_link_picker(
+    app: &mut PubkyApp,
+    session: &PubkySession,
+    pub_storage: &PublicStorage,
+    ctx: &Context,
+) {
+    let Some(mut picker) = app.title_link_picker.clone() else {
+        return;
+    };
+
+    if picker.searching_follows {
+        if let Some(follows_file_cache) = app.get_follows_file_cache(session, pub_storage, ctx) {
+            picker.candidates = find_pages_by_title(follows_file_cache, &picker.title);
+            picker.searching_follows = false;
+
+            if let [single] = picker.candidates.as_slice() {
+                app.title_link_picker = None;
+                if let Some(link) = WikiLink::parse(single) {
+                    app.navigate_to_wiki_link(&link, session, pub_storage);
+                }
+                return;
+            }
+            app.title_link_picker = Some(picker.clone());
+        }
+    }
+
+    let mut open = true;
+    egui::Window::new(format!("Pages titled \"{}\"", picker.title))
+        .collapsible(false)
+        .open(&mut open)
+        .show(ctx, |ui| {
+            if picker.searching_follows {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("No page of yours has this title, searching your follows' pages…");
+                });
+            } else if picker.candidates.is_empty() {
+                ui.label("No page has this title, among yours and your follows' pages.");
+                ui.add_space(10.0);
+                if ui.button("✨ Create page").clicked() {
+                    app.title_link_picker = None;
+                    app.leave_editor(session);
+                    app.edit_wiki_content = format!("# {}\n\n", picker.title);
+                    app.forked_from_page_id = None;
+                    app.navigate_to(ViewState::CreateWiki);
+                }
+            } else {
+                ui.label("Several pages have this title:");
+                ui.add_space(10.0);
+                for candidate in &picker.candidates {
+                    if let Some(link) = WikiLink::parse(candidate) {
+                        let user_pk = link.user_pk.clone().unwrap_or_default();
+                        let label = format!("{} by {}", link.page_id, app.display_name(&user_pk, pub_storage, ctx));
+                        if ui.button(egui::RichText::new(label).monospace()).on_hover_text(candidate).clicked() {
+                            app.title_link_picker = None;
+                            app.navigate_to_wiki_link(&link, session, pub_storage);
+                        }
+                    }
+                }
+            }
+        });
+
+    if !open {
+        app.title_link_picker = None;
+    }
 }